@@ -0,0 +1,52 @@
+// Drives one module from another program through the library, without the GUI.
+//
+//   cargo run --example embed_controller -- [PORT] [TEMP] [SECONDS]
+//
+// PORT defaults to "sim" (a simulated wristband); serial ports, tcp://IP:PORT and udp://IP:PORT
+// work as in the GUI. The target is clamped to the safety limits before it is sent.
+
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::{Controller, Module, SafetyLimits, WireProtocol};
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let port = args.first().map(String::as_str).unwrap_or("sim");
+    let temp: f32 = args.get(1).and_then(|t| t.parse().ok()).unwrap_or(20.0);
+    let seconds: f64 = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(5.0);
+
+    let mut module = Module::new("X", "Experiment", port);
+    module.protocol = WireProtocol::Binary; // Falls back to ASCII on older firmware
+    let mut controller = Controller::default();
+    controller.modules = vec![module];
+    controller.limits = SafetyLimits {
+        value_min: 10.0,
+        value_max: 35.0,
+    };
+
+    controller.connect(0);
+    controller.set_manual_target(0, temp);
+    controller.start(); // The module is activated as soon as its link is up
+
+    let deadline = Instant::now() + Duration::from_secs_f64(seconds);
+    let mut last_print = Instant::now();
+    while Instant::now() < deadline {
+        controller.tick();
+        let module = &controller.modules[0];
+        if last_print.elapsed() >= Duration::from_secs(1) {
+            last_print = Instant::now();
+            match module.telemetry {
+                Some(telemetry) => println!(
+                    "target {}°C, skin {:?}°C, peltier {:?}°C",
+                    module.target, telemetry.skin_temp_smoothed, telemetry.exterior_temp
+                ),
+                None => println!("{}", module.status_message),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    controller.shutdown(); // Sends tempActive 0 before closing the link
+}
@@ -0,0 +1,140 @@
+// Connects to the recording outlet (see src/outlet.rs), checks the stream and prints it.
+//
+//   cargo run --example outlet_client -- [ADDRESS] [SECONDS]
+//
+// ADDRESS defaults to the outlet's default (127.0.0.1:16600). Enable the outlet on the OSC
+// Settings page or with "outlet": {"enabled": true, ...} in the bridge config first. Every line
+// must parse, the header must come first and the timestamps of each module must not go
+// backwards; the summary at the end says whether that held.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::outlet::{OutletEvent, DEFAULT_OUTLET_ADDRESS, STREAM_FORMAT, STREAM_NAME};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let address = args
+        .first()
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_OUTLET_ADDRESS);
+    let seconds: f64 = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(10.0);
+
+    let stream = match TcpStream::connect(address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Cannot connect to {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .ok();
+    let mut reader = BufReader::new(stream);
+
+    let deadline = Instant::now() + Duration::from_secs_f64(seconds);
+    let mut problems = Vec::new();
+    let mut header_seen = false;
+    let mut last_t: BTreeMap<String, f64> = BTreeMap::new(); // Latest sample per module
+    let (mut samples, mut markers) = (0, 0);
+    let mut line = String::new();
+    while Instant::now() < deadline {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => {
+                problems.push("the outlet closed the connection".to_string());
+                break;
+            }
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => {
+                problems.push(format!("read error: {}", e));
+                break;
+            }
+        }
+        let event: OutletEvent = match serde_json::from_str(line.trim()) {
+            Ok(event) => event,
+            Err(e) => {
+                problems.push(format!("not a stream line ({}): {}", e, line.trim()));
+                continue;
+            }
+        };
+        match event {
+            OutletEvent::Header {
+                stream,
+                format,
+                modules,
+                clock_zero_unix,
+            } => {
+                if header_seen || stream != STREAM_NAME || format != STREAM_FORMAT {
+                    problems.push(format!("unexpected header: {} format {}", stream, format));
+                }
+                header_seen = true;
+                println!(
+                    "{} format {}, modules {}, host clock started at unix {:.3}",
+                    stream,
+                    format,
+                    modules.join(", "),
+                    clock_zero_unix
+                );
+            }
+            _ if !header_seen => problems.push("data before the header".to_string()),
+            OutletEvent::Sample {
+                t,
+                module,
+                target,
+                skin,
+                device_time,
+                ..
+            } => {
+                samples += 1;
+                if last_t.get(&module).is_some_and(|last| t < *last) {
+                    problems.push(format!("{} went back in time at t={:.6}", module, t));
+                }
+                last_t.insert(module.clone(), t);
+                let skin = skin.map_or("-".to_string(), |skin| format!("{:.2}", skin));
+                println!(
+                    "{:12.6}{} {} target {:.2} skin {}",
+                    t,
+                    if device_time { '*' } else { ' ' },
+                    module,
+                    target,
+                    skin
+                );
+            }
+            OutletEvent::Marker {
+                t, marker, module, ..
+            } => {
+                markers += 1;
+                println!(
+                    "{:12.6}  MARKER {}{}",
+                    t,
+                    marker,
+                    module.map(|m| format!(" ({})", m)).unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    println!("{} samples, {} markers", samples, markers);
+    if !header_seen {
+        problems.push("no header received".to_string());
+    }
+    if problems.is_empty() {
+        println!("Stream OK");
+    } else {
+        for problem in &problems {
+            eprintln!("Problem: {}", problem);
+        }
+        std::process::exit(1);
+    }
+}
@@ -1,334 +1,305 @@
 // src/app.rs
 
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::esp_comm::{EspCommand, EspStatus, esp_worker_thread}; // Adjust path if needed
+use crate::calibration::{Calibration, CalibrationModel, CalibrationPoint, CalibrationSession};
+use crate::comfort;
+use crate::config::{self, Config, OscConfig};
+use crate::console::{self, LineEnding, ViewMode};
+use crate::experiment::Protocol;
+use crate::controller::{Controller, MAX_TARGET_DECIMALS};
+use crate::logging::{LogBuffer, LogEntry, LogFileConfig, LogFilter};
+use crate::osc::spawn_osc_listener;
+use crate::outlet;
+use crate::pid::{PidGains, PidLoop, PidPreset, PidSettings, StepResponse, StepSample, SETTLING_BAND};
+use crate::profiles::{self, Profiles};
+use crate::scripting::Script;
+use crate::spatial::{BodyPart, Falloff, ModulePosition, SpatialPoint};
+use crate::waveform::{WaveShape, Waveform, WaveformPreset};
+use crate::esp_comm::WireProtocol;
+use crate::esp_comm::flasher::{FlashOptions, FlashStage};
+use crate::esp_comm::sim::SIMULATED_PORT_PREFIX;
+
+// Storage key for settings that failed to load, so a bad upgrade doesn't lose them
+const INVALID_SETTINGS_KEY: &str = "app_invalid";
+// Path of the log file, empty while file logging is off
+const LOG_FILE_KEY: &str = "log_file";
+const DEFAULT_LOG_FILE: &str = "tempsense.log";
+// "true" when the advanced tools (device consoles) are shown
+const ADVANCED_KEY: &str = "advanced";
+// Path of the OSC mapping script, empty while none is loaded
+const SCRIPT_KEY: &str = "script";
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum Page {
     Home,
     OscSettings,
     EspConnection,
+    Waveforms,
+    Experiment,
+    Pid,
+    Spatial,
+    Log,
     AppSettings
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
-#[serde(default)]
+// Settings are persisted as profiles of config::Config, see save()
 pub struct TemplateApp {
     pub osc_ip: String,
-    #[serde(skip)]
     pub value: f32,
-    pub value_max: i8,
-    pub value_min: i8,
     pub osc_port: String,
-    #[serde(skip)]
-    pub is_running: bool,
-    
-    // ESP L (Peltier 1)
-    pub esp_port_1: String, 
-    #[serde(skip)]
-    pub pelt_temp_1: i8,
-    #[serde(skip)]
-    pub pelt_temp_1_old: i8,
-    #[serde(skip)]
-    pub esp_command_sender_1: Option<Sender<EspCommand>>,
-    #[serde(skip)]
-    pub esp_status_receiver_1: Option<Receiver<EspStatus>>,
-    #[serde(skip)]
-    pub esp_thread_handle_1: Option<JoinHandle<()>>,
-    #[serde(skip)]
-    pub esp_connected_1: bool,
-    #[serde(skip)]
-    pub esp_status_message_1: String,
-    pub esp_baud_rate_1: u32,
-
-    // ESP R (Peltier 2)
-    pub esp_port_2: String, 
-    #[serde(skip)]
-    pub pelt_temp_2: i8,
-    #[serde(skip)]
-    pub pelt_temp_2_old: i8,
-    #[serde(skip)]
-    pub esp_command_sender_2: Option<Sender<EspCommand>>,
-    #[serde(skip)]
-    pub esp_status_receiver_2: Option<Receiver<EspStatus>>,
-    #[serde(skip)]
-    pub esp_thread_handle_2: Option<JoinHandle<()>>,
-    #[serde(skip)]
-    pub esp_connected_2: bool,
-    #[serde(skip)]
-    pub esp_status_message_2: String,
-    pub esp_baud_rate_2: u32,
-
-    #[serde(skip)]
-    pub osc_receiver: Receiver<(i8, i8)>,
-    #[serde(skip)]
-    pub last_update_time: std::time::Instant,
-    #[serde(skip)]
+
+    // Modules, targets and ESP links. Keeps running whichever page is shown.
+    pub controller: Controller,
+
     pub current_page: Page,
-    #[serde(skip)]
-    pub esp_log: Vec<String>, // Shared log for messages from ESPs and app
-
-    #[serde(skip)]
-    pub manual_pelt_1_temp_str: String,
-    #[serde(skip)]
-    pub manual_pelt_2_temp_str: String,
-
-    #[serde(skip)]
-    pub skin_temp_1: Option<f32>, 
-    #[serde(skip)] 
-    pub skin_temp_2: Option<f32>,
-
-    #[serde(skip)]
-    pub manual_pelt_1: bool,
-    #[serde(skip)]
-    pub manual_pelt_2: bool
+    pub manual_temp_strs: Vec<String>, // Manual target input, one per module
+    calibration_sessions: Vec<CalibrationSession>, // Readings being recorded, one per module
+    firmware_paths: Vec<String>, // Firmware image to flash, one per module
+    firmware_error: Option<(usize, String)>, // Why the last update of a module could not start
+
+    pub profiles: Profiles, // The active one is kept in sync with the settings above on save
+    pub profile_name_edit: String,
+
+    pub config_path: String, // Import/export file on the App Settings page
+    pub config_message: Option<Result<String, String>>, // Outcome of the last profile/import/export action
+
+    pub log: LogBuffer, // Filled by the logger installed in main (logging::init)
+    pub log_filter: LogFilter,
+    pub log_file_path: String,
+    log_paused: Option<Vec<LogEntry>>, // Snapshot shown while paused
+    pub advanced: bool, // Device consoles on the connection page
+    pub script_path: String,
+    script_message: Option<String>, // Why the last load failed
+
+    pub waveform_edit: Waveform, // Waveform page editor
+    pub waveform_module: usize,
+    pub waveform_name: String,
+    waveform_error: Option<String>, // Why the last start was refused
+
+    pub pid_edit: PidGains, // PID Tuning page editor
+    pub pid_module: usize,
+    pub pid_name: String,
+    pub pid_step_target: f32,
+    pub pid_step_secs: f32,
+    pid_message: Option<Result<String, String>>, // Outcome of the last write/commit/step test
+
+    pub spatial_test_intensity: f32, // Test point placed by clicking the Spatial Layout diagram
+    pub spatial_test_radius: f32,
+
+    pub protocol_path: String,
+    pub protocol: Option<Protocol>, // Loaded from protocol_path, started on the Experiment page
+    protocol_message: Option<Result<String, String>>,
+    osc_listen_addr: Option<String>, // Where the running OSC listener is bound
+    outlet_message: Option<String>, // Why the outlet could not be started
+    invalid_stored_settings: Option<String>, // Kept aside instead of being overwritten by defaults
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
-        let (_, osc_receiver) = mpsc::channel();
         Self {
             osc_ip: "127.0.0.1".to_owned(),
             value: 2.7,
-            value_max: 40,
-            value_min: -10,
             osc_port: "9000".to_owned(),
-            is_running: false,
-            
-            // ESP L (Peltier 1)
-            esp_port_1: if cfg!(windows) { "COM3".to_string() } else { "/dev/ttyUSB0".to_string() },
-            pelt_temp_1: 0,
-            pelt_temp_1_old: -127,
-            esp_command_sender_1: None,
-            esp_status_receiver_1: None,
-            esp_thread_handle_1: None,
-            esp_connected_1: false,
-            esp_status_message_1: "ESP L: Not connected.".to_string(),
-            esp_baud_rate_1: 115200,
-
-            // ESP R (Peltier 2)
-            esp_port_2: if cfg!(windows) { "COM4".to_string() } else { "/dev/ttyUSB1".to_string() },
-            pelt_temp_2: 0,
-            pelt_temp_2_old: -127,
-            esp_command_sender_2: None,
-            esp_status_receiver_2: None,
-            esp_thread_handle_2: None,
-            esp_connected_2: false,
-            esp_status_message_2: "ESP R: Not connected.".to_string(),
-            esp_baud_rate_2: 115200,
-
-            last_update_time: std::time::Instant::now(),
-            osc_receiver,
+            controller: Controller::default(),
             current_page: Page::Home,
-            esp_log: Vec::new(),
-
-            manual_pelt_1_temp_str: "0".to_string(),
-            manual_pelt_2_temp_str: "0".to_string(),
-            skin_temp_1: None,
-            skin_temp_2: None,
-            manual_pelt_1: false,
-            manual_pelt_2: false
+            manual_temp_strs: Vec::new(),
+            calibration_sessions: Vec::new(),
+            firmware_paths: Vec::new(),
+            firmware_error: None,
+            profiles: Profiles::default(),
+            profile_name_edit: String::new(),
+            config_path: "tempsense_config.json".to_owned(),
+            config_message: None,
+            log: LogBuffer::default(),
+            log_filter: LogFilter::default(),
+            log_file_path: DEFAULT_LOG_FILE.to_owned(),
+            log_paused: None,
+            advanced: cfg!(debug_assertions),
+            script_path: "mapping.rhai".to_owned(),
+            script_message: None,
+            waveform_edit: Waveform::default(),
+            waveform_module: 0,
+            waveform_name: String::new(),
+            waveform_error: None,
+            pid_edit: PidGains::default(),
+            pid_module: 0,
+            pid_name: String::new(),
+            pid_step_target: 30.0,
+            pid_step_secs: 30.0,
+            pid_message: None,
+            spatial_test_intensity: 0.8,
+            spatial_test_radius: 0.2,
+            protocol_path: "protocol.json".to_owned(),
+            protocol: None,
+            protocol_message: None,
+            osc_listen_addr: None,
+            outlet_message: None,
+            invalid_stored_settings: None,
         }
     }
 }
 
 impl TemplateApp {
-    pub fn update_pelt_temp(&mut self, _id: i8, temp: i8) {
-        match _id {
-            0 => {
-                if self.manual_pelt_1 != true {
-                    self.pelt_temp_1 = temp;
-                    println!("OSC temp update for Peltier 0: {:?}", temp); // Added print here
-                }
-            },
-            1 => {
-                if self.manual_pelt_2 != true {
-                    self.pelt_temp_2 = temp;
-                    println!("OSC temp update for Peltier 1: {:?}", temp); // Added print here
-                }
-
-            },
-            _ => {
-                // This is for invalid _id
-                println!("OSC temp received with INVALID _id ({}): {:?}. Defaulting to pelt_temp_1", _id, temp);
-                self.add_esp_log_message("APP", format!("Invalid peltier _id: {}. Defaulting to pelt_temp_1", _id));
-                self.pelt_temp_1 = temp;
-            },
+    pub fn new(cc: &eframe::CreationContext<'_>, log: LogBuffer) -> Self {
+        let mut app = Self { log, ..Self::default() };
+        if let Some(storage) = cc.storage {
+            if let Some(path) = storage.get_string(LOG_FILE_KEY).filter(|path| !path.is_empty()) {
+                if let Err(e) = app.log.set_file(Some(LogFileConfig::new(&path))) {
+                    log::error!(target: "APP", "{}", e);
+                }
+                app.log_file_path = path;
+            }
+            if let Some(advanced) = storage.get_string(ADVANCED_KEY) {
+                app.advanced = advanced == "true";
+            }
+            if let Some(path) = storage.get_string(SCRIPT_KEY).filter(|path| !path.is_empty()) {
+                app.script_path = path;
+                app.load_script();
+            }
+            let stored_profiles = storage.get_string(profiles::STORAGE_KEY);
+            let stored_config = storage.get_string(config::STORAGE_KEY);
+            let legacy = storage.get_string(eframe::APP_KEY);
+            match profiles::from_storage(stored_profiles.clone(), stored_config.clone(), legacy.clone()) {
+                Ok(Some(profiles)) => {
+                    app.apply_config(&profiles.active().config).expect("no module is connected yet");
+                    app.profiles = profiles;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::error!(target: "APP", "Saved settings could not be loaded: {}", e);
+                    app.config_message = Some(Err(format!("Saved settings could not be loaded, using defaults.\n{}", e)));
+                    app.invalid_stored_settings = stored_profiles.or(stored_config).or(legacy);
+                }
+            }
         }
+        app
     }
 
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+    pub fn config(&self) -> Config {
+        let osc = OscConfig {
+            ip: self.osc_ip.trim().to_string(),
+            port: self.osc_port.trim().parse().unwrap_or(0), // 0 is reported by Config::validate
+        };
+        Config::capture(&self.controller, &osc)
+    }
+
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), String> {
+        config.apply_to(&mut self.controller)?;
+        self.osc_ip = config.osc.ip.clone();
+        self.osc_port = config.osc.port.to_string();
+        self.manual_temp_strs.clear();
+        self.calibration_sessions.clear();
+        self.firmware_error = None;
+        if self.osc_listen_addr.is_some() {
+            self.start_osc_listener();
+        }
+        if config.outlet.enabled {
+            self.start_outlet();
+        } else {
+            self.controller.stop_outlet();
+        }
+        Ok(())
+    }
+
+    // (Re)starts the OSC listener on the configured address. A listener on a previous address
+    // exits on its next packet, once it notices nobody receives its messages anymore.
+    pub fn start_osc_listener(&mut self) {
+        let addr = format!("{}:{}", self.osc_ip.trim(), self.osc_port.trim());
+        if self.osc_listen_addr.as_ref() == Some(&addr) && self.controller.osc_connected() {
+            return;
+        }
+        log::info!(target: "APP", "Starting OSC listener on {}", addr);
+        self.controller.set_osc_receiver(spawn_osc_listener(&addr));
+        self.osc_listen_addr = Some(addr);
+    }
+
+    // (Re)starts the telemetry outlet on the configured address
+    pub fn start_outlet(&mut self) {
+        self.outlet_message = self.controller.start_outlet().err();
+        if let Some(e) = &self.outlet_message {
+            log::error!(target: "APP", "{}", e);
+        }
+    }
+
+    // (Re)loads the mapping script from script_path. A failure leaves the current one in place.
+    pub fn load_script(&mut self) {
+        match Script::load_file(self.script_path.trim()) {
+            Ok(script) => {
+                self.controller.set_script(Some(script));
+                self.script_message = None;
+            }
+            Err(e) => {
+                log::error!(target: "SCRIPT", "{}", e);
+                self.script_message = Some(e);
+            }
+        }
+    }
+
+    // Stops and disconnects the current modules, then loads the profile's settings.
+    // Edits to the current profile are kept unless they are invalid.
+    pub fn switch_profile(&mut self, index: usize) -> Result<(), String> {
+        if index == self.profiles.active_index() {
+            return Ok(());
+        }
+        let current = self.config();
+        if let Err(e) = current.validate() {
+            log::warn!(target: "APP", "Unsaved changes to '{}' discarded: {}", self.profiles.active().name, e);
+        } else {
+            self.profiles.update_active(current);
         }
-        Default::default()
+        self.controller.disconnect_all();
+        let profile = self.profiles.set_active(index)?.clone();
+        self.apply_config(&profile.config)?;
+        log::info!(target: "APP", "Switched to profile '{}'", profile.name);
+        Ok(())
     }
 
     // Render the Home page content
-fn render_home_page(&mut self, ui: &mut egui::Ui) {
-        // Peltier 1
-        ui.horizontal(|ui| {
-            ui.label("L Module:");
-            ui.visuals_mut().override_text_color = Some(if self.is_running && self.esp_connected_1 { egui::Color32::GREEN } else { egui::Color32::LIGHT_RED });
-            ui.label(if self.is_running && self.esp_connected_1 { "ON" } else { "OFF" });
-            ui.visuals_mut().override_text_color = Some(egui::Color32::GRAY); 
-            ui.label("Temp:");
-            let actual_temp_str_1 = self.skin_temp_1.map_or_else(
-                || "--.-°C".to_string(), 
-                |temp| format!("{:.1}°C", temp)
-            );
-            ui.label(actual_temp_str_1);
-            ui.label("➡ "); 
-            ui.label(format!("{}°C", self.pelt_temp_1));
-
-            if self.esp_connected_1 && self.pelt_temp_1 != self.pelt_temp_1_old {
-                if let Some(sender) = &self.esp_command_sender_1 {
-                    let command_to_send = format!("setTemp {}", self.pelt_temp_1);
-                    if let Err(e) = sender.send(EspCommand::SendCommand(command_to_send.clone())) {
-                        self.esp_status_message_1 = format!("ESP L: Error sending command: {:?}", e);
-                        self.add_esp_log_message("ESP L", format!("Failed to send '{}': {:?}", command_to_send, e));
-                    } else {
-                        self.add_esp_log_message("ESP L", format!("Sent command: {}", command_to_send));
-                    }
+    fn render_home_page(&mut self, ui: &mut egui::Ui) {
+        let is_running = self.controller.is_running;
+        for module in self.controller.modules.iter() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} Module:", module.short_name));
+                ui.visuals_mut().override_text_color = Some(if is_running && module.connected { egui::Color32::GREEN } else { egui::Color32::LIGHT_RED });
+                ui.label(if is_running && module.connected { "ON" } else { "OFF" });
+                ui.visuals_mut().override_text_color = Some(egui::Color32::GRAY);
+                ui.label("Temp:");
+                let actual_temp_str = module.skin_temp.map_or_else(
+                    || "--.-°C".to_string(),
+                    |temp| format!("{:.1}°C", temp)
+                );
+                if module.skin_alarm {
+                    ui.colored_label(egui::Color32::RED, format!("⚠ {}", actual_temp_str))
+                        .on_hover_text("Outside the skin limits, module switched off");
+                } else {
+                    ui.label(actual_temp_str);
                 }
-            } else if self.pelt_temp_1 != self.pelt_temp_1_old && !self.esp_connected_1 { // only log if temp changed
-                self.esp_status_message_1 = "ESP L: Not connected.".to_string();
-                self.add_esp_log_message("ESP L", "Attempted to send command while ESP L not connected.".to_string());
-            }
-            self.pelt_temp_1_old = self.pelt_temp_1;
-        });
-        ui.visuals_mut().override_text_color = None;
-            
-        // Peltier 2
-        ui.horizontal(|ui| {
-            ui.label("R Module:");
-            ui.visuals_mut().override_text_color = Some(if self.is_running && self.esp_connected_2 { egui::Color32::GREEN } else { egui::Color32::LIGHT_RED });
-            ui.label(if self.is_running && self.esp_connected_2 { "ON" } else { "OFF" });
-            ui.visuals_mut().override_text_color = Some(egui::Color32::GRAY);
-            ui.label("Temp:");
-            let actual_temp_str_2 = self.skin_temp_2.map_or_else(
-                || "--.-°C".to_string(),
-                |temp| format!("{:.1}°C", temp)
-            );
-            ui.label(actual_temp_str_2);
-            ui.label("➡ ");
-            ui.label(format!("{}°C", self.pelt_temp_2));
-
-            if self.esp_connected_2 && self.pelt_temp_2 != self.pelt_temp_2_old {
-                if let Some(sender) = &self.esp_command_sender_2 {
-                    let command_to_send = format!("setTemp {}", self.pelt_temp_2);
-                    if let Err(e) = sender.send(EspCommand::SendCommand(command_to_send.clone())) {
-                        self.esp_status_message_2 = format!("ESP R: Error sending command: {:?}", e);
-                        self.add_esp_log_message("ESP R", format!("Failed to send '{}': {:?}", command_to_send, e));
-                    } else {
-                        self.add_esp_log_message("ESP R", format!("Sent command: {}", command_to_send));
-                    }
+                ui.label("➡ ");
+                ui.label(format!("{}°C", module.target));
+                if let Some(run) = &module.waveform {
+                    ui.label(format!("({} {:.0}s)", run.name, run.elapsed().as_secs_f32()));
+                } else if let Some(state) = &module.comfort {
+                    ui.label(format!("(feels {}, {:+.2})", comfort::label(state.sensation), state.sensation));
                 }
-            } else if self.pelt_temp_2 != self.pelt_temp_2_old && !self.esp_connected_2 { // only log if temp changed
-                self.esp_status_message_2 = "ESP R: Not connected.".to_string();
-                self.add_esp_log_message("ESP R", "Attempted to send command while ESP R not connected.".to_string());
-            }
-            self.pelt_temp_2_old = self.pelt_temp_2;
-        });
-        ui.visuals_mut().override_text_color = None;
-        
+            });
+            ui.visuals_mut().override_text_color = None;
+        }
+
         ui.separator();
 
         ui.horizontal(|ui| {
             if ui.button("START ▶").clicked() {
-                self.is_running = true;
-                let mut s1_msg_set = false;
-                let mut s2_msg_set = false;
-
-                if self.esp_connected_1 {
-                    if let Some(sender) = &self.esp_command_sender_1 {
-                        if let Err(e) = sender.send(EspCommand::SendCommand("tempActive 1".to_string())) {
-                            self.esp_status_message_1 = format!("ESP L: Error sending START: {}", e);
-                            self.add_esp_log_message("ESP L", format!("Error sending START: {}", e));
-                            s1_msg_set = true;
-                        } else {
-                             self.esp_status_message_1 = "ESP L: START command sent.".to_string();
-                             self.add_esp_log_message("ESP L", "START command sent.".to_string());
-                             s1_msg_set = true;
-                        }
-                    }
-                } else {
-                    self.esp_status_message_1 = "ESP L: Cannot START, not connected.".to_string();
-                    self.add_esp_log_message("ESP L", "Attempted START while ESP L not connected.".to_string());
-                    s1_msg_set = true;
-                }
-
-                if self.esp_connected_2 {
-                    if let Some(sender) = &self.esp_command_sender_2 {
-                        if let Err(e) = sender.send(EspCommand::SendCommand("tempActive 1".to_string())) {
-                            self.esp_status_message_2 = format!("ESP R: Error sending START: {}", e);
-                            self.add_esp_log_message("ESP R", format!("Error sending START: {}", e));
-                            s2_msg_set = true;
-                        } else {
-                             self.esp_status_message_2 = "ESP R: START command sent.".to_string();
-                             self.add_esp_log_message("ESP R", "START command sent.".to_string());
-                             s2_msg_set = true;
-                        }
-                    }
-                } else {
-                    self.esp_status_message_2 = "ESP R: Cannot START, not connected.".to_string();
-                    self.add_esp_log_message("ESP R", "Attempted START while ESP R not connected.".to_string());
-                    s2_msg_set = true;
-                }
-                 if !s1_msg_set { self.esp_status_message_1 = "ESP L: Status unchanged.".to_string(); }
-                 if !s2_msg_set { self.esp_status_message_2 = "ESP R: Status unchanged.".to_string(); }
+                self.controller.start();
             }
             if ui.button("STOP ALL ■").clicked() {
-                self.is_running = false;
-                let mut s1_msg_set = false;
-                let mut s2_msg_set = false;
-
-                if self.esp_connected_1 {
-                    if let Some(sender) = &self.esp_command_sender_1 {
-                        if let Err(e) = sender.send(EspCommand::SendCommand("tempActive 0".to_string())) {
-                            self.esp_status_message_1 = format!("ESP L: Error sending STOP: {}", e);
-                            self.add_esp_log_message("ESP L", format!("Error sending STOP: {}",e));
-                            s1_msg_set = true;
-                        } else {
-                            self.esp_status_message_1 = "ESP L: STOP command sent.".to_string();
-                            self.add_esp_log_message("ESP L", "STOP command sent.".to_string());
-                            s1_msg_set = true;
-                        }
-                    }
-                } else {
-                    self.esp_status_message_1 = "ESP L: Cannot STOP, not connected.".to_string();
-                    self.add_esp_log_message("ESP L", "Attempted STOP while ESP L not connected.".to_string());
-                    s1_msg_set = true;
-                }
-
-                if self.esp_connected_2 {
-                    if let Some(sender) = &self.esp_command_sender_2 {
-                        if let Err(e) = sender.send(EspCommand::SendCommand("tempActive 0".to_string())) {
-                            self.esp_status_message_2 = format!("ESP R: Error sending STOP: {}", e);
-                            self.add_esp_log_message("ESP R", format!("Error sending STOP: {}",e));
-                            s2_msg_set = true;
-                        } else {
-                            self.esp_status_message_2 = "ESP R: STOP command sent.".to_string();
-                            self.add_esp_log_message("ESP R", "STOP command sent.".to_string());
-                            s2_msg_set = true;
-                        }
-                    }
-                } else {
-                    self.esp_status_message_2 = "ESP R: Cannot STOP, not connected.".to_string();
-                    self.add_esp_log_message("ESP R", "Attempted STOP while ESP R not connected.".to_string());
-                    s2_msg_set = true;
-                }
-                if !s1_msg_set { self.esp_status_message_1 = "ESP L: Status unchanged.".to_string(); }
-                if !s2_msg_set { self.esp_status_message_2 = "ESP R: Status unchanged.".to_string(); }
+                self.controller.stop();
             }
         });
 
         ui.horizontal(|ui| {
             ui.label("System Status:");
-            if self.is_running {
+            if self.controller.is_running {
                 ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
                 ui.label("RUNNING");
             } else {
@@ -336,79 +307,62 @@ fn render_home_page(&mut self, ui: &mut egui::Ui) {
                 ui.label("STOPPED");
             }
         });
-         ui.visuals_mut().override_text_color = None; 
+        ui.visuals_mut().override_text_color = None;
+        if let Some(report) = &self.controller.last_sync {
+            ui.label(format!("Last {}: modules within {:.2} ms", report.label, report.skew.as_secs_f64() * 1000.0))
+                .on_hover_text(report.describe());
+        }
 
         ui.horizontal(|ui| {
             ui.label("OSC: ");
-            ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN); 
-            ui.label("READY"); 
-        });
-        ui.visuals_mut().override_text_color = None; 
-
-        ui.horizontal(|ui| {
-            ui.label("ESP L: ");
-            if self.esp_connected_1 {
+            if self.controller.osc_connected() {
                 ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
-                ui.label("CONNECTED");
+                ui.label("READY");
             } else {
                 ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
-                ui.label("DISCONNECTED");
-            }
-        });
-        ui.visuals_mut().override_text_color = None; 
-
-        ui.horizontal(|ui| {
-            ui.label("ESP R: ");
-            if self.esp_connected_2 {
-                ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
-                ui.label("CONNECTED");
-            } else {
-                ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
-                ui.label("DISCONNECTED");
+                ui.label("STOPPED");
             }
         });
-        ui.visuals_mut().override_text_color = None; 
-
-        ui.separator();
-
-        ui.horizontal(|ui| {
-            ui.label("Manual L Temp: ");
-            ui.add(egui::TextEdit::singleline(&mut self.manual_pelt_1_temp_str).desired_width(50.0));
+        ui.visuals_mut().override_text_color = None;
 
-            if ui.button("Set Temp").clicked() {
-                if let Ok(temp_val) = self.manual_pelt_1_temp_str.parse::<i8>() {
-                    if self.pelt_temp_1 != temp_val { // Only if value actually changes
-                        self.pelt_temp_1 = temp_val;
-                        self.add_esp_log_message("APP", format!("Manual override: Peltier 1 target directly set to {}°C", temp_val));
-                        ui.ctx().request_repaint(); // Ensure repaint for immediate feedback and re-evaluation
-                    }
+        for module in self.controller.modules.iter() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}: ", module.source()));
+                if module.connected {
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
+                    ui.label("CONNECTED");
                 } else {
-                    self.add_esp_log_message("APP", format!("Invalid temperature input for Peltier 1: '{}'", self.manual_pelt_1_temp_str));
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+                    ui.label("DISCONNECTED");
                 }
-            }
-            ui.checkbox(&mut self.manual_pelt_1, "Override OSC");
-        });
+            });
+            ui.visuals_mut().override_text_color = None;
+        }
 
-        ui.horizontal(|ui| {
-            ui.label("Manual R Temp: ");
-            ui.add(egui::TextEdit::singleline(&mut self.manual_pelt_2_temp_str).desired_width(50.0));
+        ui.separator();
 
-            if ui.button("Set Temp").clicked() {
-                if let Ok(temp_val) = self.manual_pelt_2_temp_str.parse::<i8>() {
-                    if self.pelt_temp_2 != temp_val { // Only if value actually changes
-                        self.pelt_temp_2 = temp_val;
-                        self.add_esp_log_message("APP", format!("Manual override: Peltier 2 target directly set to {}°C", temp_val));
-                        ui.ctx().request_repaint(); // Ensure repaint for immediate feedback and re-evaluation
+        self.manual_temp_strs.resize(self.controller.modules.len(), "0".to_string());
+        for index in 0..self.controller.modules.len() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Manual {} Temp: ", self.controller.modules[index].short_name));
+                ui.add(egui::TextEdit::singleline(&mut self.manual_temp_strs[index]).desired_width(50.0));
+
+                if ui.button("Set Temp").clicked() {
+                    // Accept "20,5" as well, whatever the locale
+                    if let Ok(temp_val) = self.manual_temp_strs[index].trim().replace(',', ".").parse::<f32>() {
+                        if self.controller.set_manual_target(index, temp_val) {
+                            ui.ctx().request_repaint(); // Ensure repaint for immediate feedback
+                        }
+                    } else {
+                        log::warn!(target: "APP", "Invalid temperature input for Peltier {}: '{}'", index + 1, self.manual_temp_strs[index]);
                     }
-                } else {
-                    self.add_esp_log_message("APP", format!("Invalid temperature input for Peltier 2: '{}'", self.manual_pelt_2_temp_str));
                 }
-            }
-            ui.checkbox(&mut self.manual_pelt_2, "Override OSC");
-        });
+                ui.checkbox(&mut self.controller.modules[index].manual_override, "Override OSC");
+            });
+        }
     }
-    
-     fn render_osc_settings_page(&mut self, ui: &mut egui::Ui) {
+
+    fn render_osc_settings_page(&mut self, ui: &mut egui::Ui) {
         ui.heading("OSC Settings");
         
         ui.horizontal(|ui| {
@@ -424,19 +378,152 @@ fn render_home_page(&mut self, ui: &mut egui::Ui) {
         ui.add_space(20.0);
         
         if ui.button("Apply OSC Settings").clicked() {
-             // For now, just log it. Actual implementation of applying OSC settings would go here.
-             self.add_esp_log_message("APP", "OSC Settings Applied (Placeholder).".to_string());
+            self.start_osc_listener();
         }
         
         ui.add_space(10.0);
         
         ui.horizontal(|ui| {
             ui.label("OSC Status:");
-            // TODO: Implement actual OSC connection status logic
-            ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN); // Placeholder
-            ui.label("READY"); // Placeholder
+            match &self.osc_listen_addr {
+                Some(addr) if self.controller.osc_connected() => {
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
+                    ui.label(format!("LISTENING on {}", addr));
+                }
+                _ => {
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+                    ui.label("STOPPED");
+                }
+            }
         });
         ui.visuals_mut().override_text_color = None; 
+
+        ui.add_space(20.0);
+        ui.heading("Mapping Script");
+        ui.label("A Rhai script that turns OSC input and telemetry into targets. Without one, /PeltN sets the targets directly.");
+        ui.horizontal(|ui| {
+            ui.label("Script:");
+            ui.add(egui::TextEdit::singleline(&mut self.script_path).desired_width(200.0));
+            if ui.button("Load").on_hover_text("The file is reloaded automatically when it changes").clicked() {
+                self.load_script();
+            }
+            if ui.add_enabled(self.controller.script.is_some(), egui::Button::new("Unload")).clicked() {
+                self.controller.set_script(None);
+                self.script_message = None;
+            }
+        });
+        match &self.controller.script {
+            Some(script) => {
+                let path = script.path().map(|path| path.display().to_string()).unwrap_or_default();
+                ui.colored_label(egui::Color32::GREEN, format!("Running {}", path));
+                if let Some(e) = script.error() {
+                    ui.colored_label(egui::Color32::LIGHT_RED, e);
+                }
+            }
+            None => {
+                ui.label("No script loaded.");
+            }
+        }
+        if let Some(e) = &self.script_message {
+            ui.colored_label(egui::Color32::LIGHT_RED, e);
+        }
+
+        ui.add_space(20.0);
+        self.render_comfort_settings(ui);
+
+        ui.add_space(20.0);
+        self.render_outlet_settings(ui);
+    }
+
+    fn render_outlet_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Recording Outlet");
+        ui.label("Streams target and skin temperature of every module plus START/STOP, override, safety and experiment markers as JSON lines over TCP, timestamped on the host clock, so recordings of other signals can be aligned with the stimuli. See outlet.rs for the format.");
+        ui.horizontal(|ui| {
+            ui.label("Listen on:");
+            ui.add(egui::TextEdit::singleline(&mut self.controller.outlet_settings.address).desired_width(150.0).hint_text(outlet::DEFAULT_OUTLET_ADDRESS));
+            if ui.checkbox(&mut self.controller.outlet_settings.enabled, "Enabled").changed() {
+                if self.controller.outlet_settings.enabled {
+                    self.start_outlet();
+                } else {
+                    self.controller.stop_outlet();
+                    self.outlet_message = None;
+                }
+            }
+            if self.controller.outlet_settings.enabled && ui.button("Restart").on_hover_text("Listen on the address above").clicked() {
+                self.start_outlet();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Outlet Status:");
+            match self.controller.outlet() {
+                Some(outlet) => {
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
+                    ui.label(format!("STREAMING on {} to {} client(s)", outlet.local_addr(), outlet.clients()));
+                }
+                None => {
+                    ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+                    ui.label("STOPPED");
+                }
+            }
+        });
+        ui.visuals_mut().override_text_color = None;
+        if let Some(e) = &self.outlet_message {
+            ui.colored_label(egui::Color32::LIGHT_RED, e);
+        }
+    }
+
+    fn render_comfort_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Perceived Temperature");
+        ui.label("/SensationN sets how warm module N should feel, from -1 (cold) to +1 (hot), relative to the wearer's skin temperature when the first sensation arrives. /PeltN leaves the mode.");
+        let model = &mut self.controller.comfort_model;
+        egui::Grid::new("comfort_model").num_columns(2).show(ui, |ui| {
+            ui.label("Warm span (+1):");
+            ui.add(egui::DragValue::new(&mut model.warm_span).speed(0.1).range(0.1..=30.0).suffix(" °C"));
+            ui.end_row();
+            ui.label("Cool span (-1):");
+            ui.add(egui::DragValue::new(&mut model.cool_span).speed(0.1).range(0.1..=30.0).suffix(" °C"));
+            ui.end_row();
+            ui.label("Curve exponent:").on_hover_text("1 is linear, above 1 gives finer control around neutral");
+            ui.add(egui::DragValue::new(&mut model.exponent).speed(0.01).range(0.2..=4.0));
+            ui.end_row();
+            ui.label("Neutral offset:");
+            ui.add(egui::DragValue::new(&mut model.neutral_offset).speed(0.1).range(-10.0..=10.0).suffix(" °C"));
+            ui.end_row();
+            ui.label("Ambient correction:").on_hover_text("°C the neutral point moves per °C of ambient temperature above the reference");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut model.ambient_weight).speed(0.01).range(-1.0..=1.0));
+                ui.label("per °C above");
+                ui.add(egui::DragValue::new(&mut model.ambient_reference).speed(0.1).range(0.0..=40.0).suffix(" °C"));
+            });
+            ui.end_row();
+            ui.label("Skin until measured:");
+            ui.add(egui::DragValue::new(&mut model.default_skin).speed(0.1).range(20.0..=40.0).suffix(" °C"));
+            ui.end_row();
+        });
+        let model = self.controller.comfort_model;
+        ui.label(format!("With a 32°C baseline: cold {:.1}°C, neutral {:.1}°C, hot {:.1}°C (before the safety limits)",
+            model.target(-1.0, 32.0, None), model.target(0.0, 32.0, None), model.target(1.0, 32.0, None)));
+
+        for index in 0..self.controller.modules.len() {
+            let module = &self.controller.modules[index];
+            let (source, target) = (module.source(), module.target);
+            let Some(state) = module.comfort else {
+                ui.label(format!("{}: not in perceived-temperature mode", source));
+                continue;
+            };
+            ui.horizontal(|ui| {
+                ui.label(format!("{}: feels {} ({:+.2}), neutral {:.2}°C{}, target {}°C", source, comfort::label(state.sensation), state.sensation,
+                    state.baseline, if state.measured { "" } else { " (default)" }, target));
+                if ui.button("Rebaseline").on_hover_text("Use the current skin temperature as neutral").clicked() {
+                    if let Err(e) = self.controller.rebaseline_comfort(index) {
+                        log::warn!(target: &source, "{}", e);
+                    }
+                }
+                if ui.button("Leave").clicked() {
+                    self.controller.stop_comfort(index);
+                }
+            });
+        }
     }
 
 
@@ -444,155 +531,632 @@ fn render_home_page(&mut self, ui: &mut egui::Ui) {
         ui.heading("ESP Connections");
         ui.separator();
 
-        // --- ESP L (Peltier 1) ---
-        ui.heading("Left Module");
-        let port_text_edit_1 = egui::TextEdit::singleline(&mut self.esp_port_1).desired_width(150.0);
+        for index in 0..self.controller.modules.len() {
+            self.render_module_connection(ui, index);
+            ui.separator();
+        }
+
+        ui.add_space(10.0);
+        ui.label("Recent events (see the Log page for all):");
+        egui::ScrollArea::vertical().max_height(150.0).stick_to_bottom(true).show(ui, |ui| {
+            for entry in self.log.recent(50) {
+                ui.label(format!("[{}] [{}] {}", entry.timestamp.format("%H:%M:%S%.3f"), entry.source, entry.message));
+            }
+        });
+    }
+
+    fn render_waveforms_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Waveforms");
+        self.waveform_module = self.waveform_module.min(self.controller.modules.len().saturating_sub(1));
+
         ui.horizontal(|ui| {
-            ui.label("Serial Port:");
-            ui.add_enabled(self.esp_thread_handle_1.is_none(), port_text_edit_1); 
+            ui.label("Presets:");
+            let mut load = None;
+            egui::ComboBox::from_id_salt("waveform_preset")
+                .selected_text(if self.waveform_name.is_empty() { "custom" } else { self.waveform_name.as_str() })
+                .show_ui(ui, |ui| {
+                    for preset in &self.controller.waveform_presets {
+                        if ui.selectable_label(preset.name == self.waveform_name, &preset.name).clicked() {
+                            load = Some(preset.clone());
+                        }
+                    }
+                });
+            if let Some(preset) = load {
+                self.waveform_edit = preset.waveform;
+                self.waveform_name = preset.name;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.waveform_name).desired_width(100.0).hint_text("Preset name"));
+            let name = self.waveform_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).on_hover_text("Save (or overwrite) the preset; presets are stored with the profile").clicked() {
+                let preset = WaveformPreset { name: name.clone(), waveform: self.waveform_edit };
+                match self.controller.waveform_presets.iter_mut().find(|p| p.name == name) {
+                    Some(existing) => *existing = preset,
+                    None => self.controller.waveform_presets.push(preset),
+                }
+            }
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Delete")).clicked() {
+                self.controller.waveform_presets.retain(|p| p.name != name);
+                self.waveform_name.clear();
+            }
         });
 
-        let mut baud_str_edit_1 = self.esp_baud_rate_1.to_string();
-         ui.horizontal(|ui| {
-            ui.label("Baud Rate:");
-            let response = ui.add_enabled(
-                self.esp_thread_handle_1.is_none(),
-                egui::TextEdit::singleline(&mut baud_str_edit_1).desired_width(100.0)
-            );
-            if response.changed() {
-                if let Ok(new_baud) = baud_str_edit_1.parse::<u32>() {
-                    self.esp_baud_rate_1 = new_baud;
-                }
-            }
-        });
-
-        if self.esp_thread_handle_1.is_none() { 
-            if ui.button("Connect to ESP Left").clicked() {
-                let (command_s, command_r) = mpsc::channel();
-                let (status_s, status_r) = mpsc::channel();
-                self.esp_command_sender_1 = Some(command_s.clone());
-                self.esp_status_receiver_1 = Some(status_r);
-                let port_name_clone = self.esp_port_1.clone();
-                let baud_rate_clone = self.esp_baud_rate_1;
-                
-                self.esp_thread_handle_1 = Some(thread::spawn(move || {
-                    esp_worker_thread(command_r, status_s); // This worker thread now implicitly handles ESP L
-                }));
-                
-                let connect_msg = format!("Attempting to connect to ESP L @ {} ({} baud)...", self.esp_port_1, self.esp_baud_rate_1);
-                if let Err(e) = command_s.send(EspCommand::Connect(port_name_clone, baud_rate_clone)) {
-                     self.esp_status_message_1 = format!("ESP L: Failed to send connect cmd: {}",e);
-                     self.add_esp_log_message("ESP L", format!("Failed to send connect cmd: {}",e));
-                     self.esp_command_sender_1 = None;
-                     self.esp_status_receiver_1 = None;
-                     self.esp_thread_handle_1.take();
-                } else {
-                    self.esp_status_message_1 = connect_msg.clone();
-                    self.add_esp_log_message("ESP L", connect_msg);
+        let waveform = &mut self.waveform_edit;
+        egui::Grid::new("waveform_editor").num_columns(2).show(ui, |ui| {
+            ui.label("Shape:");
+            ui.horizontal(|ui| {
+                for shape in WaveShape::ALL {
+                    ui.selectable_value(&mut waveform.shape, shape, format!("{:?}", shape));
                 }
+            });
+            ui.end_row();
+            ui.label("Low / high:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut waveform.low).speed(0.1).max_decimals(2).suffix("°C"));
+                ui.add(egui::DragValue::new(&mut waveform.high).speed(0.1).max_decimals(2).suffix("°C"));
+            });
+            ui.end_row();
+            ui.label("Period:");
+            ui.add(egui::DragValue::new(&mut waveform.period_secs).range(0.1..=3600.0).speed(0.1).suffix(" s"));
+            ui.end_row();
+            if waveform.shape == WaveShape::Pulse {
+                ui.label("Duty cycle:");
+                ui.add(egui::Slider::new(&mut waveform.duty_cycle, 0.01..=0.99));
+                ui.end_row();
             }
-        } else {
-            if ui.button("Disconnect from ESP Left").clicked() {
-                if let Some(sender) = &self.esp_command_sender_1 {
-                    if let Err(e) = sender.send(EspCommand::Disconnect) {
-                         self.esp_status_message_1 = format!("ESP L: Failed to send disconnect cmd: {}",e);
-                         self.add_esp_log_message("ESP L", format!("Failed to send disconnect cmd: {}",e));
-                    } else {
-                        self.esp_status_message_1 = "ESP L: Disconnect command sent.".to_string();
-                        self.add_esp_log_message("ESP L", "Disconnect command sent.".to_string());
+            ui.label("Duration:");
+            ui.horizontal(|ui| {
+                let mut limited = waveform.duration_secs.is_some();
+                if ui.checkbox(&mut limited, "").changed() {
+                    waveform.duration_secs = limited.then_some(waveform.period_secs * 3.0);
+                }
+                match &mut waveform.duration_secs {
+                    Some(duration) => {
+                        ui.add(egui::DragValue::new(duration).range(0.1..=86400.0).speed(0.5).suffix(" s"));
+                    }
+                    None => {
+                        ui.label("until stopped");
                     }
                 }
+            });
+            ui.end_row();
+        });
+
+        let running = self.controller.modules.get(self.waveform_module).and_then(|m| m.waveform.clone());
+        self.render_waveform_preview(ui, running.as_ref().map(|run| run.elapsed().as_secs_f32()));
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("waveform_module")
+                .selected_text(self.controller.modules.get(self.waveform_module).map(|m| m.long_name.clone()).unwrap_or_default())
+                .show_ui(ui, |ui| {
+                    for (index, module) in self.controller.modules.iter().enumerate() {
+                        ui.selectable_value(&mut self.waveform_module, index, &module.long_name);
+                    }
+                });
+            if ui.button("Start ▶").on_hover_text("Drive this module's target; OSC input to it is ignored meanwhile").clicked() {
+                let name = if self.waveform_name.trim().is_empty() { "custom" } else { self.waveform_name.trim() }.to_string();
+                self.waveform_error = self.controller.start_waveform(self.waveform_module, &name, self.waveform_edit).err();
             }
+            if ui.add_enabled(running.is_some(), egui::Button::new("Stop ■")).clicked() {
+                self.controller.stop_waveform(self.waveform_module);
+            }
+        });
+        if let Some(run) = &running {
+            let module = &self.controller.modules[self.waveform_module];
+            let paused = if module.manual_override { " (paused: manual override)" } else { "" };
+            ui.label(format!("Running '{}' for {:.1}s, target {}°C{}", run.name, run.elapsed().as_secs_f32(), module.target, paused));
         }
+        if let Err(e) = self.waveform_edit.validate(&self.controller.limits) {
+            ui.colored_label(egui::Color32::LIGHT_RED, e);
+        } else if let Some(e) = &self.waveform_error {
+            ui.colored_label(egui::Color32::LIGHT_RED, e);
+        }
+    }
+
+    fn render_experiment_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Experiment Protocol");
+        let running = self.controller.experiment.is_some();
         ui.horizontal(|ui| {
-            ui.label("ESP L Status:");
-            if self.esp_connected_1 {
-                ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
-                ui.label("CONNECTED");
-            } else {
-                ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
-                ui.label("DISCONNECTED");
+            ui.label("Protocol file:");
+            ui.add(egui::TextEdit::singleline(&mut self.protocol_path).desired_width(220.0));
+            if ui.add_enabled(!running, egui::Button::new("Load")).clicked() {
+                let names: Vec<String> = self.controller.modules.iter().map(|m| m.short_name.clone()).collect();
+                let loaded = Protocol::load_file(self.protocol_path.trim())
+                    .and_then(|protocol| protocol.validate(&names, &self.controller.limits).map(|()| protocol));
+                self.protocol_message = Some(loaded.as_ref().map(|p| format!("Loaded '{}'", p.name)).map_err(Clone::clone));
+                self.protocol = loaded.ok();
             }
         });
-        ui.visuals_mut().override_text_color = None; 
-        ui.label(&self.esp_status_message_1);
-        #[cfg(debug_assertions)] 
-        if self.esp_connected_1 {
-            if ui.button("Send 'PING' to ESP L").clicked() {
-                 if let Some(sender) = &self.esp_command_sender_1 {
-                    if let Err(e) = sender.send(EspCommand::SendCommand("PING".to_string())) {
-                        self.add_esp_log_message("ESP L", format!("Error sending PING: {}", e));
-                    } else {
-                        self.add_esp_log_message("ESP L", "Sent PING to ESP L.".to_string());
+
+        if let Some(protocol) = &self.protocol {
+            let seed = protocol.seed.map_or("random".to_string(), |seed| seed.to_string());
+            let steps = protocol.schedule(0);
+            let total: f32 = steps.iter().map(|step| step.duration.as_secs_f32()).sum();
+            ui.label(format!("'{}': {} blocks, {} steps, {:.0}s, seed {}, modules {}",
+                protocol.name, protocol.blocks.len(), steps.len(), total, seed, protocol.modules().join(", ")));
+            if let Some(markers) = &protocol.markers {
+                ui.label(format!("OSC markers to {}", markers));
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!running && self.protocol.is_some(), egui::Button::new("Start ▶")).clicked() {
+                if let Some(protocol) = self.protocol.clone() {
+                    self.protocol_message = self.controller.start_experiment(protocol).err().map(Err);
+                }
+            }
+            let paused = self.controller.experiment.as_ref().is_some_and(|run| run.is_paused());
+            if ui.add_enabled(running, egui::Button::new(if paused { "Resume" } else { "Pause" })).clicked() {
+                if paused {
+                    self.controller.resume_experiment();
+                } else {
+                    self.controller.pause_experiment();
+                }
+            }
+            if ui.add_enabled(running, egui::Button::new("Abort ■")).clicked() {
+                self.controller.abort_experiment();
+            }
+        });
+
+        if let Some(run) = &self.controller.experiment {
+            match run.current() {
+                Some((index, step)) => {
+                    let remaining = step.duration.saturating_sub(run.step_elapsed()).as_secs_f32();
+                    ui.label(format!("Step {}/{}: {} #{} / {} / {}", index + 1, run.schedule.len(), step.block, step.repetition, step.trial, step.step));
+                    let progress = run.step_elapsed().as_secs_f32() / step.duration.as_secs_f32().max(0.001);
+                    ui.add(egui::ProgressBar::new(progress.min(1.0)).text(format!("{:.1}s left{}", remaining, if run.is_paused() { " (paused)" } else { "" })));
+                }
+                None => {
+                    ui.label("Starting...");
+                }
+            }
+            ui.label(format!("Seed {}", run.seed));
+        }
+
+        match &self.protocol_message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+            None => {}
+        }
+    }
+
+    // Plot of the edited waveform with the safety limits; `elapsed` marks a running one
+    fn render_waveform_preview(&self, ui: &mut egui::Ui, elapsed: Option<f32>) {
+        let waveform = &self.waveform_edit;
+        let limits = self.controller.limits;
+        let (response, painter) = ui.allocate_painter(egui::vec2(ui.available_width().min(400.0), 120.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let span = waveform.preview_secs().max(0.1);
+        let (min_temp, max_temp) = (
+            limits.value_min.min(waveform.low).min(waveform.high) - 1.0,
+            limits.value_max.max(waveform.low).max(waveform.high) + 1.0,
+        );
+        let to_screen = |t: f32, temp: f32| egui::pos2(
+            rect.left() + rect.width() * t / span,
+            rect.bottom() - rect.height() * (temp - min_temp) / (max_temp - min_temp),
+        );
+
+        for limit in [limits.value_min, limits.value_max] {
+            let y = to_screen(0.0, limit).y;
+            painter.hline(rect.x_range(), y, egui::Stroke::new(1.0, egui::Color32::DARK_RED));
+        }
+        if waveform.problems().is_empty() {
+            let samples = 400;
+            let points: Vec<egui::Pos2> = (0..=samples)
+                .map(|i| span * i as f32 / samples as f32)
+                .map(|t| to_screen(t, waveform.value_at(t)))
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, egui::Color32::LIGHT_BLUE)));
+        }
+        if let Some(t) = elapsed.filter(|t| *t <= span) {
+            let x = to_screen(t, 0.0).x;
+            painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, egui::Color32::YELLOW));
+        }
+        painter.text(rect.left_top() + egui::vec2(4.0, 2.0), egui::Align2::LEFT_TOP, format!("{:.0}°C", max_temp), egui::FontId::monospace(10.0), egui::Color32::GRAY);
+        painter.text(rect.left_bottom() + egui::vec2(4.0, -2.0), egui::Align2::LEFT_BOTTOM, format!("{:.0}°C, {:.0}s shown", min_temp, span), egui::FontId::monospace(10.0), egui::Color32::GRAY);
+    }
+
+    fn render_spatial_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Spatial Layout");
+        ui.label(format!("Drag modules to where they sit. {} x y intensity radius [source] then sets their targets from a heat (intensity > 0) or cold (< 0) source at x, y.", crate::spatial::POINT_ADDRESS));
+
+        let layout = &mut self.controller.spatial;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Body part")
+                .selected_text(format!("{:?}", layout.body_part))
+                .show_ui(ui, |ui| {
+                    for body_part in BodyPart::ALL {
+                        ui.selectable_value(&mut layout.body_part, body_part, format!("{:?}", body_part));
                     }
-                 }
+                });
+            egui::ComboBox::from_label("Falloff")
+                .selected_text(format!("{:?}", layout.falloff))
+                .show_ui(ui, |ui| {
+                    for falloff in Falloff::ALL {
+                        ui.selectable_value(&mut layout.falloff, falloff, format!("{:?}", falloff));
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Neutral:");
+            ui.add(egui::DragValue::new(&mut layout.neutral).speed(0.1).range(-10.0..=45.0).suffix(" °C"));
+            ui.label("Span at full intensity:");
+            ui.add(egui::DragValue::new(&mut layout.span).speed(0.1).range(0.0..=30.0).suffix(" °C"));
+        });
+
+        // The diagram: x along, y across the body part
+        let width = ui.available_width().min(500.0);
+        let aspect = layout.body_part.aspect();
+        let (response, painter) = ui.allocate_painter(egui::vec2(width, width * aspect), egui::Sense::click());
+        let rect = response.rect;
+        let to_screen = |x: f32, y: f32| egui::pos2(rect.left() + x * rect.width(), rect.top() + y * rect.height());
+        let rounding = rect.height().min(rect.width()) * 0.3;
+        painter.rect_filled(rect, rounding, ui.visuals().extreme_bg_color);
+        painter.rect_stroke(rect, rounding, egui::Stroke::new(1.0, egui::Color32::GRAY), egui::StrokeKind::Inside);
+        let (start, end) = layout.body_part.ends();
+        painter.text(rect.left_center() + egui::vec2(4.0, 0.0), egui::Align2::LEFT_CENTER, start, egui::FontId::proportional(11.0), egui::Color32::GRAY);
+        painter.text(rect.right_center() - egui::vec2(4.0, 0.0), egui::Align2::RIGHT_CENTER, end, egui::FontId::proportional(11.0), egui::Color32::GRAY);
+        for point in self.controller.spatial_sources.values() {
+            let color = if point.intensity > 0.0 { egui::Color32::from_rgba_unmultiplied(255, 80, 60, 60) } else { egui::Color32::from_rgba_unmultiplied(60, 120, 255, 60) };
+            painter.circle_filled(to_screen(point.x, point.y), point.radius * rect.width(), color);
+        }
+
+        let mut moved = false;
+        for position in self.controller.spatial.positions.iter_mut() {
+            let center = to_screen(position.x, position.y);
+            let id = ui.id().with(("spatial_module", &position.module));
+            let handle = ui.interact(egui::Rect::from_center_size(center, egui::vec2(24.0, 24.0)), id, egui::Sense::drag());
+            if handle.dragged() {
+                let delta = handle.drag_delta();
+                position.x = (position.x + delta.x / rect.width()).clamp(0.0, 1.0);
+                position.y = (position.y + delta.y / rect.height()).clamp(0.0, 1.0);
+                moved = true;
+            }
+            let target = self.controller.modules.iter().find(|module| module.short_name == position.module).map(|module| module.target);
+            painter.circle(center, 10.0, egui::Color32::DARK_GRAY, egui::Stroke::new(1.5, if handle.hovered() { egui::Color32::WHITE } else { egui::Color32::LIGHT_GRAY }));
+            painter.text(center, egui::Align2::CENTER_CENTER, &position.module, egui::FontId::proportional(11.0), egui::Color32::WHITE);
+            if let Some(target) = target {
+                painter.text(center + egui::vec2(0.0, 14.0), egui::Align2::CENTER_TOP, format!("{}°C", target), egui::FontId::monospace(10.0), egui::Color32::GRAY);
             }
         }
+        if moved && !self.controller.spatial_sources.is_empty() {
+            self.controller.update_spatial_targets();
+        }
+        if let Some(pointer) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+            let x = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            let y = ((pointer.y - rect.top()) / rect.height()).clamp(0.0, 1.0);
+            let point = SpatialPoint { x, y, intensity: self.spatial_test_intensity, radius: self.spatial_test_radius, source: 0 };
+            self.controller.apply_point(point);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Click to test: intensity");
+            ui.add(egui::Slider::new(&mut self.spatial_test_intensity, -1.0..=1.0));
+            ui.label("radius");
+            ui.add(egui::Slider::new(&mut self.spatial_test_radius, 0.02..=1.0));
+            if ui.add_enabled(!self.controller.spatial_sources.is_empty(), egui::Button::new("Clear sources")).clicked() {
+                self.controller.clear_points();
+            }
+        });
+
+        ui.separator();
+        for index in 0..self.controller.modules.len() {
+            let name = self.controller.modules[index].short_name.clone();
+            let placed = self.controller.spatial.position(&name).is_some();
+            ui.horizontal(|ui| {
+                ui.label(format!("{} ({})", self.controller.modules[index].long_name, name));
+                if placed {
+                    if ui.button("Remove").clicked() {
+                        self.controller.spatial.positions.retain(|position| position.module != name);
+                    }
+                } else if ui.button("Place").on_hover_text("Adds it to the middle of the diagram").clicked() {
+                    self.controller.spatial.positions.push(ModulePosition { module: name.clone(), x: 0.5, y: 0.5 });
+                }
+            });
+        }
+    }
+
+    fn render_pid_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading("PID Tuning");
+        self.pid_module = self.pid_module.min(self.controller.modules.len().saturating_sub(1));
+        let index = self.pid_module;
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("pid_module")
+                .selected_text(self.controller.modules.get(index).map(|m| m.long_name.clone()).unwrap_or_default())
+                .show_ui(ui, |ui| {
+                    for (i, module) in self.controller.modules.iter().enumerate() {
+                        ui.selectable_value(&mut self.pid_module, i, &module.long_name);
+                    }
+                });
+            let connected = self.controller.modules.get(index).is_some_and(|m| m.connected);
+            if ui.add_enabled(connected, egui::Button::new("Read from device")).clicked() {
+                self.controller.read_pid(index);
+            }
+            let device_gains = self.controller.modules.get(index).and_then(|m| m.pid_gains());
+            if ui.add_enabled(device_gains.is_some(), egui::Button::new("Load into editor")).clicked() {
+                if let Some(gains) = device_gains {
+                    self.pid_edit = gains;
+                    self.pid_name.clear();
+                }
+            }
+        });
+        if let Some(module) = self.controller.modules.get(index) {
+            for pid_loop in PidLoop::ALL {
+                let text = module.pid.get(&pid_loop).map_or("not read".to_string(), PidSettings::to_fields);
+                ui.label(format!("Device {}: {}", pid_loop.name(), text));
+            }
+            if module.pid_unsaved {
+                ui.colored_label(egui::Color32::YELLOW, "Gains written but not committed to flash, they are lost when the device restarts");
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Gain sets:");
+            let mut load = None;
+            egui::ComboBox::from_id_salt("pid_preset")
+                .selected_text(if self.pid_name.is_empty() { "custom" } else { self.pid_name.as_str() })
+                .show_ui(ui, |ui| {
+                    for preset in &self.controller.pid_presets {
+                        if ui.selectable_label(preset.name == self.pid_name, &preset.name).clicked() {
+                            load = Some(preset.clone());
+                        }
+                    }
+                });
+            if let Some(preset) = load {
+                self.pid_edit = preset.gains;
+                self.pid_name = preset.name;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.pid_name).desired_width(100.0).hint_text("Set name"));
+            let name = self.pid_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).on_hover_text("Save (or overwrite) the gain set; gain sets are stored with the profile").clicked() {
+                let preset = PidPreset { name: name.clone(), gains: self.pid_edit };
+                match self.controller.pid_presets.iter_mut().find(|p| p.name == name) {
+                    Some(existing) => *existing = preset,
+                    None => self.controller.pid_presets.push(preset),
+                }
+            }
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Delete")).clicked() {
+                self.controller.pid_presets.retain(|p| p.name != name);
+                self.pid_name.clear();
+            }
+        });
+
+        let gains = &mut self.pid_edit;
+        egui::Grid::new("pid_editor").num_columns(3).show(ui, |ui| {
+            ui.label("");
+            ui.strong("Heat");
+            ui.strong("Cool");
+            ui.end_row();
+            type Field = fn(&mut PidSettings) -> &mut f32;
+            let rows: [(&str, Field, &str); 6] = [
+                ("Kp:", |s| &mut s.kp, ""),
+                ("Ki:", |s| &mut s.ki, ""),
+                ("Kd:", |s| &mut s.kd, ""),
+                ("Output min:", |s| &mut s.output_min, " %"),
+                ("Output max:", |s| &mut s.output_max, " %"),
+                ("Deadband:", |s| &mut s.deadband, "°C"),
+            ];
+            for (label, field, suffix) in rows {
+                ui.label(label);
+                for pid_loop in PidLoop::ALL {
+                    ui.add(egui::DragValue::new(field(gains.get_mut(pid_loop))).speed(0.01).max_decimals(3).suffix(suffix));
+                }
+                ui.end_row();
+            }
+        });
+        let problems = self.pid_edit.problems();
+        if !problems.is_empty() {
+            ui.colored_label(egui::Color32::LIGHT_RED, problems.join(", "));
+        }
+
+        ui.horizontal(|ui| {
+            let connected = self.controller.modules.get(index).is_some_and(|m| m.connected);
+            if ui.add_enabled(connected && problems.is_empty(), egui::Button::new("Write to device")).on_hover_text("Takes effect immediately; lost on restart unless committed").clicked() {
+                self.pid_message = Some(self.controller.write_pid(index, &self.pid_edit).map(|()| "Gains written".to_string()));
+            }
+            if ui.add_enabled(connected, egui::Button::new("Commit to flash")).on_hover_text("Store the device's current gains so they survive a restart").clicked() {
+                self.pid_message = Some(if self.controller.commit_pid(index) { Ok("Commit sent".to_string()) } else { Err("Commit could not be sent".to_string()) });
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Step test to");
+            ui.add(egui::DragValue::new(&mut self.pid_step_target).speed(0.1).max_decimals(2).suffix("°C"));
+            ui.label("for");
+            ui.add(egui::DragValue::new(&mut self.pid_step_secs).range(1.0..=600.0).speed(0.5).suffix(" s"));
+            if ui.button("Step ▶").on_hover_text("Sets the target in manual override and records the response").clicked() {
+                let duration = Duration::from_secs_f32(self.pid_step_secs);
+                self.pid_message = self.controller.start_step_test(index, self.pid_step_target, duration).err().map(Err);
+            }
+        });
+        if let Some(step) = self.controller.modules.get(index).and_then(|m| m.step_response.as_ref()) {
+            render_step_response(ui, step);
+            match step.metrics() {
+                Some(metrics) => {
+                    let seconds = |value: Option<f32>| value.map_or("-".to_string(), |secs| format!("{:.1}s", secs));
+                    ui.label(format!("Rise time {}, overshoot {:.2}°C, settled within ±{}°C after {}{}",
+                        seconds(metrics.rise_time), metrics.overshoot, SETTLING_BAND, seconds(metrics.settling_time),
+                        if step.is_finished() { "" } else { " (recording)" }));
+                }
+                None if step.samples.is_empty() => {
+                    ui.label("Waiting for telemetry...");
+                }
+                None => {
+                    ui.label("The step is too small to measure.");
+                }
+            }
+        }
+
+        match &self.pid_message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+            None => {}
+        }
+    }
+
+    fn render_log_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Log");
+        let entries = match &self.log_paused {
+            Some(snapshot) => snapshot.clone(),
+            None => self.log.snapshot(),
+        };
+        let mut sources: Vec<&str> = entries.iter().map(|entry| entry.source.as_str()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("log_level")
+                .selected_text(self.log_filter.min_level.as_str())
+                .show_ui(ui, |ui| {
+                    for level in [log::Level::Error, log::Level::Warn, log::Level::Info, log::Level::Debug] {
+                        ui.selectable_value(&mut self.log_filter.min_level, level, level.as_str());
+                    }
+                })
+                .response
+                .on_hover_text("Minimum level shown");
+            let source_label = if self.log_filter.source.is_empty() { "All sources" } else { self.log_filter.source.as_str() };
+            egui::ComboBox::from_id_salt("log_source")
+                .selected_text(source_label.to_string())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.log_filter.source, String::new(), "All sources");
+                    for source in &sources {
+                        ui.selectable_value(&mut self.log_filter.source, source.to_string(), *source);
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.log_filter.search).desired_width(120.0).hint_text("Search"));
+        });
+
+        let shown: Vec<&LogEntry> = entries.iter().filter(|entry| self.log_filter.matches(entry)).collect();
+        ui.horizontal(|ui| {
+            let paused = self.log_paused.is_some();
+            if ui.selectable_label(paused, "Pause").on_hover_text("Freeze the view; new entries are still recorded").clicked() {
+                self.log_paused = if paused { None } else { Some(entries.clone()) };
+            }
+            if ui.button("Copy").on_hover_text("Copy the shown entries to the clipboard").clicked() {
+                let text: Vec<String> = shown.iter().map(|entry| entry.format()).collect();
+                ui.ctx().copy_text(text.join("\n"));
+            }
+            if ui.button("Clear").clicked() {
+                self.log.clear();
+                self.log_paused = None;
+            }
+            ui.label(format!("{} of {} entries", shown.len(), entries.len()));
+        });
+
+        ui.horizontal(|ui| {
+            let mut enabled = self.log.file().is_some();
+            let checkbox = ui.checkbox(&mut enabled, "Write to file:")
+                .on_hover_text("Rotated at 5 MB, the last 3 files are kept (.1, .2, .3)");
+            ui.add_enabled(!enabled, egui::TextEdit::singleline(&mut self.log_file_path).desired_width(200.0));
+            if checkbox.changed() {
+                let config = enabled.then(|| LogFileConfig::new(self.log_file_path.trim()));
+                match self.log.set_file(config) {
+                    Ok(()) if enabled => log::info!(target: "APP", "Logging to {}", self.log_file_path.trim()),
+                    Ok(()) => log::info!(target: "APP", "Log file closed"),
+                    Err(e) => log::error!(target: "APP", "{}", e),
+                }
+            }
+        });
         ui.separator();
 
-        // --- ESP R (Peltier 2) ---
-        ui.heading("Right Module");
-        let port_text_edit_2 = egui::TextEdit::singleline(&mut self.esp_port_2).desired_width(150.0);
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::vertical()
+            .id_salt("log_entries")
+            .max_height(ui.available_height() * 0.6)
+            .auto_shrink([false, true])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, shown.len(), |ui, range| {
+                for entry in &shown[range] {
+                    let color = match entry.level {
+                        log::Level::Error => egui::Color32::RED,
+                        log::Level::Warn => egui::Color32::YELLOW,
+                        log::Level::Debug | log::Level::Trace => egui::Color32::GRAY,
+                        log::Level::Info => ui.visuals().text_color(),
+                    };
+                    ui.label(egui::RichText::new(entry.format()).monospace().color(color));
+                }
+            });
+
+        // Raw device output, kept out of the event log so it can't drown it
+        egui::CollapsingHeader::new(format!("Telemetry ({} lines)", self.controller.telemetry_log.len()))
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical().id_salt("telemetry_lines").max_height(150.0).stick_to_bottom(true).show(ui, |ui| {
+                    for line in self.controller.telemetry_log.iter() {
+                        let calibrated = line.calibrated_skin.map(|skin| format!(" (calibrated skin {:.2})", skin)).unwrap_or_default();
+                        // '*' marks times from the device clock, the others are arrival times
+                        let clock = if line.device_time { '*' } else { ' ' };
+                        ui.label(egui::RichText::new(format!("{}{} [{}] {}{}", line.timestamp.format("%H:%M:%S%.3f"), clock, line.source, line.line, calibrated)).monospace());
+                    }
+                });
+            });
+    }
+
+    fn render_module_connection(&mut self, ui: &mut egui::Ui, index: usize) {
+        let module = &mut self.controller.modules[index];
+        let editable = !module.is_worker_running();
+
+        ui.heading(format!("{} Module", module.long_name));
         ui.horizontal(|ui| {
-            ui.label("Serial Port:");
-            ui.add_enabled(self.esp_thread_handle_2.is_none(), port_text_edit_2); 
+            ui.label("Port:");
+            ui.add_enabled(
+                editable,
+                egui::TextEdit::singleline(&mut module.port)
+                    .desired_width(150.0)
+                    .hint_text("COM3, tcp://ip:port, udp://ip:port")
+            ).on_hover_text("Serial port name, or tcp://IP:PORT / udp://IP:PORT to reach a wristband over Wi-Fi. UDP telemetry is received on the same port number, add ?local=PORT to use another.");
+            if ui.add_enabled(editable, egui::Button::new("Simulated"))
+                .on_hover_text("Use a simulated wristband instead of a serial port")
+                .clicked()
+            {
+                module.port = format!("{}:{}", SIMULATED_PORT_PREFIX, module.short_name);
+            }
         });
 
-        let mut baud_str_edit_2 = self.esp_baud_rate_2.to_string();
-         ui.horizontal(|ui| {
+        let mut baud_str_edit = module.baud_rate.to_string();
+        ui.horizontal(|ui| {
             ui.label("Baud Rate:");
             let response = ui.add_enabled(
-                self.esp_thread_handle_2.is_none(),
-                egui::TextEdit::singleline(&mut baud_str_edit_2).desired_width(100.0)
+                editable,
+                egui::TextEdit::singleline(&mut baud_str_edit).desired_width(100.0)
             );
             if response.changed() {
-                if let Ok(new_baud) = baud_str_edit_2.parse::<u32>() {
-                    self.esp_baud_rate_2 = new_baud;
-                }
-            }
-        });
-
-        if self.esp_thread_handle_2.is_none() { 
-            if ui.button("Connect to ESP Right").clicked() {
-                let (command_s, command_r) = mpsc::channel();
-                let (status_s, status_r) = mpsc::channel();
-                self.esp_command_sender_2 = Some(command_s.clone());
-                self.esp_status_receiver_2 = Some(status_r);
-                let port_name_clone = self.esp_port_2.clone();
-                let baud_rate_clone = self.esp_baud_rate_2;
-                
-                self.esp_thread_handle_2 = Some(thread::spawn(move || {
-                    esp_worker_thread(command_r, status_s); // This worker thread now implicitly handles ESP R
-                }));
-                
-                let connect_msg = format!("Attempting to connect to ESP R @ {} ({} baud)...", self.esp_port_2, self.esp_baud_rate_2);
-                if let Err(e) = command_s.send(EspCommand::Connect(port_name_clone, baud_rate_clone)) {
-                     self.esp_status_message_2 = format!("ESP R: Failed to send connect cmd: {}",e);
-                     self.add_esp_log_message("ESP R", format!("Failed to send connect cmd: {}",e));
-                     self.esp_command_sender_2 = None;
-                     self.esp_status_receiver_2 = None;
-                     self.esp_thread_handle_2.take();
-                } else {
-                    self.esp_status_message_2 = connect_msg.clone();
-                    self.add_esp_log_message("ESP R", connect_msg);
+                if let Ok(new_baud) = baud_str_edit.parse::<u32>() {
+                    module.baud_rate = new_baud;
                 }
             }
-        } else {
-            if ui.button("Disconnect from ESP Right").clicked() {
-                if let Some(sender) = &self.esp_command_sender_2 {
-                    if let Err(e) = sender.send(EspCommand::Disconnect) {
-                         self.esp_status_message_2 = format!("ESP R: Failed to send disconnect cmd: {}",e);
-                         self.add_esp_log_message("ESP R", format!("Failed to send disconnect cmd: {}",e));
-                    } else {
-                        self.esp_status_message_2 = "ESP R: Disconnect command sent.".to_string();
-                        self.add_esp_log_message("ESP R", "Disconnect command sent.".to_string());
-                    }
-                }
+        });
+
+        let mut binary_framing = module.protocol == WireProtocol::Binary;
+        if ui.add_enabled(
+            editable,
+            egui::Checkbox::new(&mut binary_framing, "Binary framing with CRC (falls back to ASCII on older firmware)")
+        ).changed() {
+            module.protocol = if binary_framing { WireProtocol::Binary } else { WireProtocol::Ascii };
+        }
+
+        if editable {
+            if ui.button(format!("Connect to ESP {}", module.long_name)).clicked() {
+                self.controller.connect(index);
             }
+        } else if ui.button(format!("Disconnect from ESP {}", module.long_name)).clicked() {
+            self.controller.disconnect(index);
         }
+
+        let module = &self.controller.modules[index];
         ui.horizontal(|ui| {
-            ui.label("ESP R Status:");
-            if self.esp_connected_2 {
+            ui.label(format!("{} Status:", module.source()));
+            if module.connected {
                 ui.visuals_mut().override_text_color = Some(egui::Color32::GREEN);
                 ui.label("CONNECTED");
             } else {
@@ -600,208 +1164,397 @@ fn render_home_page(&mut self, ui: &mut egui::Ui) {
                 ui.label("DISCONNECTED");
             }
         });
-        ui.visuals_mut().override_text_color = None; 
-        ui.label(&self.esp_status_message_2);
-
-        #[cfg(debug_assertions)] 
-        if self.esp_connected_2 {
-            if ui.button("Send 'PING' to ESP R").clicked() {
-                 if let Some(sender) = &self.esp_command_sender_2 {
-                    if let Err(e) = sender.send(EspCommand::SendCommand("PING".to_string())) {
-                        self.add_esp_log_message("ESP R", format!("Error sending PING: {}", e));
-                    } else {
-                        self.add_esp_log_message("ESP R", "Sent PING to ESP R.".to_string());
+        ui.visuals_mut().override_text_color = None;
+        if let Some(protocol) = module.active_protocol {
+            ui.label(format!("Protocol: {:?}, corrupt frames: {}", protocol, module.corrupt_frames));
+            let resolution = match module.target_decimals(self.controller.target_decimals) {
+                0 if self.controller.target_decimals > 0 => "whole degrees (firmware does not support decimals)".to_string(),
+                decimals => format!("{}°C steps", resolution_label(decimals)),
+            };
+            ui.label(format!("Targets: {}", resolution));
+        }
+        if let Some(latency) = module.command_latency {
+            ui.label(format!(
+                "Command latency: last {:.2} ms, mean {:.2} ms, max {:.2} ms ({} sent)",
+                latency.last.as_secs_f64() * 1000.0, latency.mean().as_secs_f64() * 1000.0, latency.max.as_secs_f64() * 1000.0, latency.count
+            ));
+        }
+        if module.active_protocol.is_some() {
+            let clock = &module.clock;
+            match (clock.supported, clock.offset_us(), clock.uncertainty_us()) {
+                (Some(false), _, _) => {
+                    ui.label("Device clock: not supported by the firmware, telemetry is timestamped on arrival");
+                }
+                (_, Some(offset), Some(uncertainty)) => {
+                    let rtt = clock.rtt;
+                    ui.label(format!(
+                        "Round trip: last {:.2} ms, min {:.2} ms, mean {:.2} ms, max {:.2} ms, jitter {:.2} ms ({} pings)",
+                        rtt.last_us as f64 / 1000.0, rtt.min_us as f64 / 1000.0, rtt.mean_us() / 1000.0, rtt.max_us as f64 / 1000.0, rtt.jitter_us() / 1000.0, rtt.count
+                    ));
+                    ui.label(format!("Device clock: {:+.3}s from host time (±{:.2} ms)", offset as f64 / 1e6, uncertainty as f64 / 1000.0))
+                        .on_hover_text("Estimated from the ping with the shortest round trip of the last few. Telemetry carrying the device clock is timestamped in host time with it.");
+                }
+                _ => {
+                    ui.label("Device clock: synchronizing...");
+                }
+            }
+        }
+        ui.label(&module.status_message);
+        let source = module.source();
+        egui::CollapsingHeader::new(format!("{} Calibration", source))
+            .id_salt(("calibration", index))
+            .show(ui, |ui| self.render_calibration(ui, index));
+        egui::CollapsingHeader::new(format!("{} Firmware Update", source))
+            .id_salt(("firmware", index))
+            .show(ui, |ui| self.render_firmware_update(ui, index));
+        if self.advanced {
+            egui::CollapsingHeader::new(format!("{} Console", source))
+                .id_salt(("console", index))
+                .show(ui, |ui| self.render_console(ui, index));
+        }
+    }
+
+    // Recording readings against a reference thermometer and fitting a correction, see calibration.rs
+    fn render_calibration(&mut self, ui: &mut egui::Ui, index: usize) {
+        self.calibration_sessions.resize_with(self.controller.modules.len(), CalibrationSession::default);
+        let module = &self.controller.modules[index];
+        let raw = module.raw_skin_temp;
+        let key = module.device_key();
+        ui.label(match &module.serial {
+            Some(serial) => format!("Device serial number: {}", serial),
+            None => format!("No serial number known, calibration is stored for the port {}", key),
+        });
+        match self.controller.calibration(index) {
+            Some(calibration) => {
+                ui.label(format!("Current: {}", calibration.describe()));
+            }
+            None => {
+                ui.label("Current: none, skin temperatures are shown as reported");
+            }
+        }
+
+        let session = &mut self.calibration_sessions[index];
+        ui.horizontal(|ui| {
+            ui.label(format!("Reading: {}", raw.map_or("--.--°C".to_string(), |raw| format!("{:.2}°C", raw))));
+            ui.label("Reference:");
+            ui.add(egui::TextEdit::singleline(&mut session.reference).desired_width(50.0).hint_text("°C"));
+            if ui.add_enabled(raw.is_some(), egui::Button::new("Record")).on_hover_text("Pair the current reading with the reference thermometer value").clicked() {
+                match session.reference.trim().replace(',', ".").parse::<f32>() {
+                    Ok(reference) if reference.is_finite() => {
+                        session.points.push(CalibrationPoint { raw: raw.unwrap_or_default(), reference });
+                        session.message = None;
                     }
-                 }
+                    _ => session.message = Some(Err(format!("Invalid reference temperature '{}'", session.reference))),
+                }
             }
+        });
+        let mut remove = None;
+        for (point_index, point) in session.points.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{:.2}°C read, {:.2}°C reference", point.raw, point.reference));
+                if ui.small_button("✖").clicked() {
+                    remove = Some(point_index);
+                }
+            });
         }
-        
-        ui.add_space(10.0);
-        ui.separator();
-        ui.label("ESP Log/Messages (Shared):");
-        egui::ScrollArea::vertical().max_height(150.0).stick_to_bottom(true).show(ui, |ui| {
-            for msg in self.esp_log.iter() { 
-                ui.label(msg);
+        if let Some(point_index) = remove {
+            session.points.remove(point_index);
+        }
+
+        let mut save = None;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(("calibration_model", index))
+                .selected_text(session.model.label())
+                .show_ui(ui, |ui| {
+                    for model in CalibrationModel::ALL {
+                        ui.selectable_value(&mut session.model, model, model.label());
+                    }
+                });
+            let enough = session.points.len() >= session.model.min_points();
+            if ui.add_enabled(enough, egui::Button::new("Fit && save")).clicked() {
+                match Calibration::fit(session.model, &session.points) {
+                    Ok(calibration) => {
+                        let warning = calibration.check_plausible(&self.controller.limits).err().map(|e| format!(" Check the readings: {}", e)).unwrap_or_default();
+                        session.message = Some(Ok(format!("Saved: {}.{}", calibration.describe(), warning)));
+                        session.points.clear();
+                        save = Some(Some(calibration));
+                    }
+                    Err(e) => session.message = Some(Err(e)),
+                }
+            }
+            if ui.add_enabled(self.controller.calibration(index).is_some(), egui::Button::new("Remove calibration")).clicked() {
+                session.message = None;
+                save = Some(None);
             }
         });
+        match &session.message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+            None => {}
+        }
+        if let Some(calibration) = save {
+            self.controller.set_calibration(index, calibration);
+        }
     }
-    
+
+    // Flashing a .bin through the ESP's bootloader, see esp_comm/flasher.rs
+    fn render_firmware_update(&mut self, ui: &mut egui::Ui, index: usize) {
+        self.firmware_paths.resize(self.controller.modules.len(), "firmware.bin".to_string());
+        let module = &self.controller.modules[index];
+        let running = module.flash.as_ref().is_some_and(|job| job.is_running());
+        let connected = module.is_worker_running();
+        ui.horizontal(|ui| {
+            ui.label("Image:");
+            ui.add_enabled(!running, egui::TextEdit::singleline(&mut self.firmware_paths[index]).desired_width(200.0).hint_text(".bin"));
+            let button = ui.add_enabled(!running && !connected, egui::Button::new("Flash"))
+                .on_hover_text("Resets the ESP into its bootloader and writes the application image");
+            if button.clicked() {
+                let path = self.firmware_paths[index].trim().to_string();
+                let started = std::fs::read(&path)
+                    .map_err(|e| format!("Could not read {}: {}", path, e))
+                    .and_then(|image| self.controller.start_flash(index, image, FlashOptions::default()));
+                self.firmware_error = started.err().map(|e| (index, e));
+            }
+        });
+        if connected && !running {
+            ui.label("Disconnect the module to update its firmware.");
+        }
+        if let Some((_, e)) = self.firmware_error.as_ref().filter(|(i, _)| *i == index) {
+            ui.colored_label(egui::Color32::LIGHT_RED, e);
+        }
+        let Some(job) = &self.controller.modules[index].flash else { return };
+        ui.label(format!("Image: {}", job.image.describe()));
+        match (&job.result, job.progress) {
+            (None, Some(progress)) => {
+                let fraction = if progress.stage == FlashStage::Writing { progress.fraction() } else { 0.0 };
+                ui.add(egui::ProgressBar::new(fraction).text(progress.describe()).animate(progress.stage != FlashStage::Writing));
+            }
+            (None, None) => {
+                ui.label("Opening the port...");
+            }
+            (Some(Ok(report)), _) => {
+                ui.colored_label(egui::Color32::GREEN, format!(
+                    "Firmware updated in {:.1}s{}. Connect to use it.",
+                    report.elapsed.as_secs_f32(), if report.verified { ", verified" } else { " (not verified)" }
+                ));
+            }
+            (Some(Err(e)), _) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+        }
+    }
+
+    // Raw device console, see console.rs
+    fn render_console(&mut self, ui: &mut egui::Ui, index: usize) {
+        let connected = self.controller.modules[index].connected;
+        let binary = self.controller.modules[index].active_protocol == Some(WireProtocol::Binary);
+        let console = &mut self.controller.modules[index].console;
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut console.view, ViewMode::Text, "Text");
+            ui.selectable_value(&mut console.view, ViewMode::Hex, "Hex");
+            ui.separator();
+            egui::ComboBox::from_id_salt(("line_ending", index))
+                .selected_text(console.line_ending.label())
+                .show_ui(ui, |ui| {
+                    for ending in LineEnding::ALL {
+                        ui.selectable_value(&mut console.line_ending, ending, ending.label());
+                    }
+                });
+            ui.checkbox(&mut console.hex_input, "Hex input");
+            if ui.button("Clear").clicked() {
+                console.clear();
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_salt(("console_stream", index))
+            .max_height(150.0)
+            .auto_shrink([false, true])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for chunk in console.chunks() {
+                    ui.label(egui::RichText::new(console::format_chunk(chunk, console.view)).monospace());
+                }
+            });
+
+        if binary {
+            ui.label("Binary framing is active: console input is sent unframed.");
+        }
+        let mut send = false;
+        ui.horizontal(|ui| {
+            let hint = if console.hex_input { "C0 01 73 ..." } else { "Command, e.g. PING" };
+            let response = ui.add(egui::TextEdit::singleline(&mut console.input).desired_width(220.0).hint_text(hint).font(egui::TextStyle::Monospace));
+            if response.has_focus() {
+                if ui.input(|i| i.key_pressed(egui::Key::ArrowUp)) {
+                    console.history_prev();
+                } else if ui.input(|i| i.key_pressed(egui::Key::ArrowDown)) {
+                    console.history_next();
+                }
+            }
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            send = ui.add_enabled(connected, egui::Button::new("Send")).clicked() || (entered && connected);
+            if entered {
+                response.request_focus();
+            }
+        });
+        if send {
+            match console.take_input() {
+                Ok(bytes) => {
+                    self.controller.send_raw(index, bytes);
+                }
+                Err(e) => log::warn!(target: &self.controller.modules[index].source(), "Console: {}", e),
+            }
+        }
+    }
+
     fn render_app_settings_page(&mut self, ui: &mut egui::Ui) {
         ui.heading("App Settings");
         ui.separator();
         
         egui::widgets::global_theme_preference_buttons(ui);
+        ui.checkbox(&mut self.advanced, "Advanced tools")
+            .on_hover_text("Show a raw serial console per module on the ESP Connection page");
+        ui.horizontal(|ui| {
+            ui.label("Target resolution:");
+            egui::ComboBox::from_id_salt("target_decimals")
+                .selected_text(format!("{}°C", resolution_label(self.controller.target_decimals)))
+                .show_ui(ui, |ui| {
+                    for decimals in 0..=MAX_TARGET_DECIMALS {
+                        ui.selectable_value(&mut self.controller.target_decimals, decimals, format!("{}°C", resolution_label(decimals)));
+                    }
+                })
+                .response
+                .on_hover_text("Older firmware always gets whole degrees");
+        });
 
         ui.separator();
-        ui.label("App Version: v0.2"); // TODO make app version a variable so this does not get forgotten with updates
-    }
+        self.render_profiles(ui);
 
-    // Added esp_identifier to distinguish log messages
-    fn add_esp_log_message(&mut self, esp_identifier: &str, message: String) {
-        let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
-        self.esp_log.push(format!("[{}] [{}] {}", timestamp, esp_identifier, message));
-        if self.esp_log.len() > 200 { // Keep the log size manageable
-            self.esp_log.remove(0);
+        ui.separator();
+        ui.label("Configuration file (modules, ports, OSC, limits) of the active profile:");
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.config_path).desired_width(220.0).hint_text("tempsense_config.json"));
+            if ui.button("Export").clicked() {
+                let path = self.config_path.trim().to_string();
+                self.config_message = Some(self.config().save_file(&path).map(|()| format!("Exported to {}", path)));
+            }
+            if ui.button("Import").clicked() {
+                let path = self.config_path.trim().to_string();
+                self.config_message = Some(
+                    Config::load_file(&path)
+                        .and_then(|config| self.apply_config(&config))
+                        .map(|()| format!("Imported {}", path))
+                );
+            }
+        });
+        match &self.config_message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(message)) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, message);
+            }
+            None => {}
         }
+
+        ui.separator();
+        ui.label("App Version: v0.2"); // TODO make app version a variable so this does not get forgotten with updates
     }
 
-    fn parse_esp_message_and_update_state(&mut self, esp_id_str: &str, msg: &str) {
-        // Example msg: "Skin_Temp_Smoothed:12.12,Exterior_Temp:18.73,Target_Temp:10.0,Heat_PID_output:0.0,Cool_PID_output:64.4,Ambient:22.0"
-        for part in msg.split(',') {
-            let mut kv_iterator = part.splitn(2, ':');
-            if let (Some(key_raw), Some(value_raw)) = (kv_iterator.next(), kv_iterator.next()) {
-                let key = key_raw.trim();
-                let value_str = value_raw.trim();
-
-                if key == "Skin_Temp_Smoothed" {
-                    if let Ok(temp_f32) = value_str.parse::<f32>() {
-                        if esp_id_str == "ESP L" {
-                            self.skin_temp_1 = Some(temp_f32);
-                        } else if esp_id_str == "ESP R" {
-                            self.skin_temp_2 = Some(temp_f32);
+    fn render_profiles(&mut self, ui: &mut egui::Ui) {
+        let connected = self.controller.modules.iter().any(|module| module.is_worker_running());
+        let mut switch_to = None;
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+            egui::ComboBox::from_id_salt("profile")
+                .selected_text(&self.profiles.active().name)
+                .show_ui(ui, |ui| {
+                    for (index, profile) in self.profiles.profiles().iter().enumerate() {
+                        if ui.selectable_label(index == self.profiles.active_index(), &profile.name).clicked() {
+                            switch_to = Some(index);
                         }
-                    } else {
-                        self.add_esp_log_message(esp_id_str, format!("Failed to parse Skin_Temp_Smoothed value: '{}'", value_str));
                     }
+                })
+                .response
+                .on_hover_text(if connected { "Switching stops and disconnects all modules" } else { "Settings of the selected profile are loaded" });
+        });
+        if let Some(index) = switch_to {
+            self.config_message = match self.switch_profile(index) {
+                Ok(()) => None,
+                Err(e) => Some(Err(e)),
+            };
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.profile_name_edit).desired_width(150.0).hint_text("Profile name"));
+            let active = self.profiles.active_index();
+            let name = self.profile_name_edit.clone();
+            let result = if ui.button("New").on_hover_text("New profile with default settings").clicked() {
+                Some(self.profiles.create(&name, Config::default()).map(|_| format!("Created profile '{}'", name.trim())))
+            } else if ui.button("Clone").on_hover_text("New profile with the current settings").clicked() {
+                let current = self.config();
+                Some(current.validate()
+                    .and_then(|()| self.profiles.create(&name, current))
+                    .map(|_| format!("Cloned '{}' as '{}'", self.profiles.active().name, name.trim())))
+            } else if ui.button("Rename").on_hover_text("Rename the active profile").clicked() {
+                Some(self.profiles.rename(active, &name).map(|()| format!("Renamed profile to '{}'", name.trim())))
+            } else if ui.button("Delete").on_hover_text("Delete the profile named on the left").clicked() {
+                Some(self.profiles.find(name.trim())
+                    .ok_or_else(|| format!("No profile named '{}'", name.trim()))
+                    .and_then(|index| self.profiles.delete(index))
+                    .map(|profile| format!("Deleted profile '{}'", profile.name)))
+            } else {
+                None
+            };
+            if let Some(result) = result {
+                if result.is_ok() {
+                    self.profile_name_edit.clear();
                 }
-                // Future: Add parsing for other values like "Exterior_Temp", "Target_Temp" here
+                self.config_message = Some(result);
             }
-        }
+        });
     }
 }
 
 impl eframe::App for TemplateApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
-    }
-
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Process incoming OSC messages
-// Process ALL available OSC messages this frame
-        while let Ok(osc_id_and_message) = self.osc_receiver.try_recv() {
-          //  println!("APP_RS_RX: {:?}", osc_id_and_message); // Added a prefix for clarity
-            self.update_pelt_temp(osc_id_and_message.0, osc_id_and_message.1);
+        if let Some(invalid) = self.invalid_stored_settings.take() {
+            storage.set_string(INVALID_SETTINGS_KEY, invalid);
         }
-        
-
-        let mut processed_any_message_this_frame = false;
-
-        // Process incoming ESP L status messages
-        let receiver1_temp_opt = self.esp_status_receiver_1.take();
-        let mut clear_receiver1_permanently = false; 
-        if let Some(ref rx1) = receiver1_temp_opt { 
-            while let Ok(status) = rx1.try_recv() {
-                processed_any_message_this_frame = true;
-                match status {
-                    EspStatus::Connected => {
-                        self.esp_connected_1 = true;
-                        self.esp_status_message_1 = "ESP L Connected.".to_string();
-                        self.add_esp_log_message("ESP L", "Connected.".to_string());
-                    }
-                    EspStatus::Disconnected(reason) => {
-                        self.esp_connected_1 = false;
-                        let msg = reason.unwrap_or_else(|| "Disconnected by worker.".to_string());
-                        self.esp_status_message_1 = format!("ESP L: {}", msg);
-                        self.add_esp_log_message("ESP L", msg);
-
-                        if let Some(handle) = self.esp_thread_handle_1.take() {
-                             let _ = handle.join().map_err(|e| self.add_esp_log_message("ESP L", format!("Thread panicked or error on join: {:?}", e)));
-                        }
-                        self.esp_command_sender_1 = None;
-                        clear_receiver1_permanently = true; 
-                    }
-                    EspStatus::Error(err_msg) => {
-                        let full_err_msg = format!("Error: {}", err_msg);
-                        self.esp_status_message_1 = format!("ESP L: {}",full_err_msg);
-                        self.add_esp_log_message("ESP L", full_err_msg);
-                    }
-                    EspStatus::Message(msg) => {
-                        self.add_esp_log_message("ESP L", format!("MSG: {}", msg));
-                        self.parse_esp_message_and_update_state("ESP L", &msg);
-                    }
-                }
-            }
-        }
-        if !clear_receiver1_permanently && receiver1_temp_opt.is_some() {
-            self.esp_status_receiver_1 = receiver1_temp_opt;
-        } else if clear_receiver1_permanently {
-             if self.esp_thread_handle_1.is_some() {
-                if let Some(handle) = self.esp_thread_handle_1.take() {
-                    self.add_esp_log_message("ESP L", "Ensuring thread is joined after disconnect (update).".to_string());
-                    let _ = handle.join().map_err(|e| self.add_esp_log_message("ESP L", format!("Thread panicked/error on join (update): {:?}", e)));
-                }
-             }
-             if self.esp_command_sender_1.is_some() && self.esp_thread_handle_1.is_none() {
-                self.esp_command_sender_1 = None;
-                self.add_esp_log_message("ESP L", "Cleared command sender as thread handle is gone.".to_string());
-             }
-        }
-
-        // Process incoming ESP R status messages
-        let receiver2_temp_opt = self.esp_status_receiver_2.take();
-        let mut clear_receiver2_permanently = false;
-        if let Some(ref rx2) = receiver2_temp_opt {
-            while let Ok(status) = rx2.try_recv() {
-                processed_any_message_this_frame = true;
-                match status {
-                    EspStatus::Connected => {
-                        self.esp_connected_2 = true;
-                        self.esp_status_message_2 = "ESP R Connected.".to_string();
-                        self.add_esp_log_message("ESP R", "Connected.".to_string());
-                    }
-                    EspStatus::Disconnected(reason) => {
-                        self.esp_connected_2 = false;
-                        let msg = reason.unwrap_or_else(|| "Disconnected by worker.".to_string());
-                        self.esp_status_message_2 = format!("ESP R: {}", msg);
-                        self.add_esp_log_message("ESP R", msg);
-
-                        if let Some(handle) = self.esp_thread_handle_2.take() {
-                             let _ = handle.join().map_err(|e| self.add_esp_log_message("ESP R", format!("Thread panicked or error on join: {:?}", e)));
-                        }
-                        self.esp_command_sender_2 = None;
-                        clear_receiver2_permanently = true;
-                    }
-                    EspStatus::Error(err_msg) => {
-                        let full_err_msg = format!("Error: {}", err_msg);
-                        self.esp_status_message_2 = format!("ESP R: {}",full_err_msg);
-                        self.add_esp_log_message("ESP R", full_err_msg);
-                    }
-                    EspStatus::Message(msg) => {
-                        self.parse_esp_message_and_update_state("ESP R", &msg);
-                        self.add_esp_log_message("ESP R", format!("MSG: {}", msg));
-                    }
-                }
-            }
-        }
-
-        if !clear_receiver2_permanently && receiver2_temp_opt.is_some() {
-            self.esp_status_receiver_2 = receiver2_temp_opt;
-        } else if clear_receiver2_permanently {
-             if self.esp_thread_handle_2.is_some() {
-                if let Some(handle) = self.esp_thread_handle_2.take() {
-                    self.add_esp_log_message("ESP R", "Ensuring thread is joined after disconnect (update).".to_string());
-                    let _ = handle.join().map_err(|e| self.add_esp_log_message("ESP R", format!("Thread panicked/error on join (update): {:?}", e)));
-                }
-             }
-             if self.esp_command_sender_2.is_some() && self.esp_thread_handle_2.is_none() {
-                self.esp_command_sender_2 = None;
-                self.add_esp_log_message("ESP R", "Cleared command sender as thread handle is gone.".to_string());
-             }
+        let config = self.config();
+        match config.validate() {
+            Ok(()) => self.profiles.update_active(config),
+            // Keep the last valid settings rather than persisting something that won't load
+            Err(e) => log::warn!(target: "APP", "Settings not saved: {}", e),
         }
+        storage.set_string(profiles::STORAGE_KEY, self.profiles.to_json());
+        let log_file = self.log.file().map(|file| file.path.display().to_string()).unwrap_or_default();
+        storage.set_string(LOG_FILE_KEY, log_file);
+        storage.set_string(ADVANCED_KEY, self.advanced.to_string());
+        let script = self.controller.script.as_ref().and_then(Script::path).map(|path| path.display().to_string()).unwrap_or_default();
+        storage.set_string(SCRIPT_KEY, script);
+    }
 
-        if self.osc_receiver.try_recv().is_ok() || processed_any_message_this_frame {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // The controller does the work; the UI only shows and edits its state
+        self.controller.set_raw_capture(self.advanced);
+        if self.controller.tick() {
             ctx.request_repaint();
         } else {
             ctx.request_repaint_after_for(Duration::from_millis(75), ctx.viewport_id());
         }
 
-
         egui::TopBottomPanel::top("page_navigation").show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 let button_height = 32.0;
                 let button_width = 100.0;
  
-                ui.horizontal_centered(|ui| {
+                ui.horizontal_wrapped(|ui| { // Wraps once the pages no longer fit on one row
                     ui.spacing_mut().item_spacing.x = 5.0;
                     ui.spacing_mut().button_padding = egui::vec2(0.0, 8.0);
                     
@@ -814,6 +1567,21 @@ impl eframe::App for TemplateApp {
                     if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::EspConnection, "ESP Connection:")).clicked() {
                         self.current_page = Page::EspConnection;
                     }
+                    if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::Waveforms, "Waveforms")).clicked() {
+                        self.current_page = Page::Waveforms;
+                    }
+                    if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::Experiment, "Experiment")).clicked() {
+                        self.current_page = Page::Experiment;
+                    }
+                    if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::Pid, "PID Tuning")).clicked() {
+                        self.current_page = Page::Pid;
+                    }
+                    if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::Spatial, "Spatial Layout")).clicked() {
+                        self.current_page = Page::Spatial;
+                    }
+                    if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::Log, "Log")).clicked() {
+                        self.current_page = Page::Log;
+                    }
                     if ui.add_sized([button_width, button_height], egui::SelectableLabel::new(self.current_page == Page::AppSettings, "App Settings")).clicked() {
                         self.current_page = Page::AppSettings;
                     }
@@ -827,6 +1595,11 @@ impl eframe::App for TemplateApp {
                 Page::Home => self.render_home_page(ui),
                 Page::OscSettings => self.render_osc_settings_page(ui),
                 Page::EspConnection => self.render_esp_connection_page(ui),
+                Page::Waveforms => self.render_waveforms_page(ui),
+                Page::Experiment => self.render_experiment_page(ui),
+                Page::Pid => self.render_pid_page(ui),
+                Page::Spatial => self.render_spatial_page(ui),
+                Page::Log => self.render_log_page(ui),
                 Page::AppSettings => self.render_app_settings_page(ui),
             }
             ui.separator();
@@ -838,30 +1611,7 @@ impl eframe::App for TemplateApp {
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        self.add_esp_log_message("APP", "Application exiting. Stopping ESP workers.".to_string());
-        
-        // ESP L shutdown
-        if let Some(sender) = self.esp_command_sender_1.take() {
-            // Attempt to send StopThread, ignore error if channel already closed (e.g., worker already exited)
-            let _ = sender.send(EspCommand::StopThread); 
-        }
-        if let Some(handle) = self.esp_thread_handle_1.take() {
-           
-            if let Err(e) = handle.join().map_err(|e_join| format!("ESP L thread panicked or error on join: {:?}", e_join)) {
-                self.add_esp_log_message("ESP L", e); 
-            }
-        }
-
-        // ESP R shutdown
-        if let Some(sender) = self.esp_command_sender_2.take() {
-            let _ = sender.send(EspCommand::StopThread);
-        }
-        if let Some(handle) = self.esp_thread_handle_2.take() {
-        
-            if let Err(e) = handle.join().map_err(|e_join| format!("ESP R thread panicked or error on join: {:?}", e_join)) {
-                self.add_esp_log_message("ESP R", e);
-            }
-        }
+        self.controller.shutdown();
     }
 }
 
@@ -873,4 +1623,40 @@ fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
         ui.hyperlink_to("TempSense", "https://github.com/TempSenseVR/TempSense-GUI");
         ui.label(".");
     });
-}
\ No newline at end of file
+}
+
+// Exterior temperature against the target (top) and the PID outputs (bottom) of a step test
+fn render_step_response(ui: &mut egui::Ui, step: &StepResponse) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(ui.available_width().min(400.0), 160.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let (temps, outputs) = rect.split_top_bottom_at_fraction(0.7);
+
+    let span = step.duration.as_secs_f32().max(1.0);
+    let (min_temp, max_temp) = step.samples.iter()
+        .map(|s| s.exterior_temp)
+        .fold((step.from.min(step.target), step.from.max(step.target)), |(min, max), t| (min.min(t), max.max(t)));
+    let (min_temp, max_temp) = (min_temp - 1.0, max_temp + 1.0);
+    let x = |t: f32| rect.left() + rect.width() * t / span;
+    let temp_y = |temp: f32| temps.bottom() - temps.height() * (temp - min_temp) / (max_temp - min_temp);
+    let output_y = |output: f32| outputs.bottom() - outputs.height() * output / 100.0;
+
+    painter.hline(rect.x_range(), temp_y(step.target), egui::Stroke::new(1.0, egui::Color32::YELLOW));
+    painter.hline(rect.x_range(), outputs.top(), egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+    let line = |value: fn(&StepSample) -> f32, y: &dyn Fn(f32) -> f32, color| {
+        let points: Vec<egui::Pos2> = step.samples.iter().map(|s| egui::pos2(x(s.t), y(value(s)))).collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+    };
+    line(|s| s.exterior_temp, &temp_y, egui::Color32::LIGHT_BLUE);
+    line(|s| s.heat_output, &output_y, egui::Color32::LIGHT_RED);
+    line(|s| s.cool_output, &output_y, egui::Color32::from_rgb(80, 140, 255));
+
+    painter.text(temps.left_top() + egui::vec2(4.0, 2.0), egui::Align2::LEFT_TOP, format!("{:.0}°C", max_temp), egui::FontId::monospace(10.0), egui::Color32::GRAY);
+    painter.text(temps.left_bottom() + egui::vec2(4.0, -2.0), egui::Align2::LEFT_BOTTOM, format!("{:.0}°C", min_temp), egui::FontId::monospace(10.0), egui::Color32::GRAY);
+    painter.text(outputs.left_bottom() + egui::vec2(4.0, -2.0), egui::Align2::LEFT_BOTTOM, format!("heat/cool output %, {:.0}s shown", span), egui::FontId::monospace(10.0), egui::Color32::GRAY);
+}
+
+// Step size for `decimals` places: "1", "0.1", "0.01"
+fn resolution_label(decimals: u8) -> String {
+    format!("{:.*}", usize::from(decimals), 10f32.powi(-i32::from(decimals)))
+}
@@ -0,0 +1,189 @@
+// src/calibration.rs
+
+// Skin sensor calibration. Every wristband's thermistor reads a little differently, so readings
+// are recorded against a reference thermometer and a correction is fitted to them:
+//   Offset         corrected = raw + c0
+//   Linear         corrected = c0 + c1·raw
+//   Polynomial(n)  corrected = c0 + c1·raw + ... + cn·rawⁿ (least squares, n up to 3)
+// Calibrations are stored with the config, keyed by device (Module::device_key), and the
+// controller applies them to every skin reading before it is shown, logged or safety checked.
+
+use crate::controller::SafetyLimits;
+
+pub const MAX_POLYNOMIAL_DEGREE: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalibrationPoint {
+    pub raw: f32,       // What the module reported
+    pub reference: f32, // What the reference thermometer read
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CalibrationModel {
+    Offset,
+    Linear,
+    Polynomial(u8), // Degree
+}
+
+impl CalibrationModel {
+    pub const ALL: [CalibrationModel; 4] = [CalibrationModel::Offset, CalibrationModel::Linear, CalibrationModel::Polynomial(2), CalibrationModel::Polynomial(3)];
+
+    fn degree(self) -> usize {
+        match self {
+            CalibrationModel::Offset => 0,
+            CalibrationModel::Linear => 1,
+            CalibrationModel::Polynomial(degree) => usize::from(degree),
+        }
+    }
+
+    // Readings at different temperatures needed for a fit
+    pub fn min_points(self) -> usize {
+        self.degree() + 1
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            CalibrationModel::Offset => "Offset".to_string(),
+            CalibrationModel::Linear => "Linear".to_string(),
+            CalibrationModel::Polynomial(degree) => format!("Polynomial ({})", degree),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calibration {
+    pub model: CalibrationModel,
+    pub coefficients: Vec<f64>,        // c0, c1, ... (see the top of the file)
+    pub points: Vec<CalibrationPoint>, // The readings it was fitted from
+    pub created: String,               // Local time of the fit, for the record
+}
+
+impl Calibration {
+    // Least squares fit of `model` to the readings
+    pub fn fit(model: CalibrationModel, points: &[CalibrationPoint]) -> Result<Self, String> {
+        if let CalibrationModel::Polynomial(degree) = model {
+            if !(2..=MAX_POLYNOMIAL_DEGREE).contains(&degree) {
+                return Err(format!("Polynomial degree must be between 2 and {}", MAX_POLYNOMIAL_DEGREE));
+            }
+        }
+        if points.iter().any(|p| !(p.raw.is_finite() && p.reference.is_finite())) {
+            return Err("Readings must be numbers".to_string());
+        }
+        let mut distinct: Vec<f32> = points.iter().map(|p| p.raw).collect();
+        distinct.sort_by(f32::total_cmp);
+        distinct.dedup();
+        if distinct.len() < model.min_points() {
+            return Err(format!("{} needs readings at {} different temperatures, found {}", model.label(), model.min_points(), distinct.len()));
+        }
+        let coefficients = match model {
+            CalibrationModel::Offset => {
+                let offset = points.iter().map(|p| f64::from(p.reference) - f64::from(p.raw)).sum::<f64>() / points.len() as f64;
+                vec![offset, 1.0]
+            }
+            _ => least_squares(points, model.degree())?,
+        };
+        Ok(Self { model, coefficients, points: points.to_vec(), created: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string() })
+    }
+
+    // Corrected temperature for a raw reading
+    pub fn apply(&self, raw: f32) -> f32 {
+        let raw = f64::from(raw);
+        self.coefficients.iter().rev().fold(0.0, |acc, c| acc * raw + c) as f32
+    }
+
+    // Root mean square difference between the corrected readings and the reference
+    pub fn rms_error(&self) -> f32 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self.points.iter().map(|p| (self.apply(p.raw) - p.reference).powi(2)).sum();
+        (sum / self.points.len() as f32).sqrt()
+    }
+
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let expected = match self.model {
+            CalibrationModel::Offset => 2, // Offset is stored as c0 + 1·raw
+            model => model.degree() + 1,
+        };
+        if self.coefficients.len() != expected {
+            problems.push(format!("coefficients: {} expects {}, found {}", self.model.label(), expected, self.coefficients.len()));
+        }
+        if self.coefficients.iter().any(|c| !c.is_finite()) {
+            problems.push("coefficients: must be numbers".to_string());
+        }
+        if let CalibrationModel::Polynomial(degree) = self.model {
+            if !(2..=MAX_POLYNOMIAL_DEGREE).contains(&degree) {
+                problems.push(format!("model: polynomial degree must be between 2 and {}", MAX_POLYNOMIAL_DEGREE));
+            }
+        }
+        problems
+    }
+
+    // A correction that moves readings by more than this is most likely a recording mistake
+    pub fn check_plausible(&self, limits: &SafetyLimits) -> Result<(), String> {
+        const MAX_CORRECTION: f32 = 5.0;
+        let (min, max) = (limits.value_min.min(limits.value_max), limits.value_max.max(limits.value_min));
+        let worst = [min, (min + max) / 2.0, max].into_iter()
+            .map(|raw| (self.apply(raw) - raw).abs())
+            .fold(0.0, f32::max);
+        if worst > MAX_CORRECTION {
+            return Err(format!("corrects readings by up to {:.1}°C within the safety limits, more than {}°C", worst, MAX_CORRECTION));
+        }
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} from {} readings, RMS error {:.2}°C, {}", self.model.label(), self.points.len(), self.rms_error(), self.created)
+    }
+}
+
+// Solves the normal equations for a polynomial of `degree` with Gaussian elimination
+fn least_squares(points: &[CalibrationPoint], degree: usize) -> Result<Vec<f64>, String> {
+    let n = degree + 1;
+    let mut matrix = vec![vec![0.0f64; n + 1]; n];
+    for point in points {
+        let x = f64::from(point.raw);
+        let powers: Vec<f64> = (0..=2 * degree).map(|k| x.powi(k as i32)).collect();
+        for (row, equation) in matrix.iter_mut().enumerate() {
+            for (col, cell) in equation.iter_mut().take(n).enumerate() {
+                *cell += powers[row + col];
+            }
+            equation[n] += powers[row] * f64::from(point.reference);
+        }
+    }
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs())).unwrap_or(col);
+        if matrix[pivot][col].abs() < 1e-12 {
+            return Err("Readings are too close together to fit a curve".to_string());
+        }
+        matrix.swap(col, pivot);
+        for row in 0..n {
+            if row != col {
+                let factor = matrix[row][col] / matrix[col][col];
+                for k in col..=n {
+                    matrix[row][k] -= factor * matrix[col][k];
+                }
+            }
+        }
+    }
+    Ok((0..n).map(|i| matrix[i][n] / matrix[i][i]).collect())
+}
+
+// Readings being recorded for one module, see the ESP Connection page
+#[derive(Debug, Clone)]
+pub struct CalibrationSession {
+    pub points: Vec<CalibrationPoint>,
+    pub reference: String, // Reference thermometer input
+    pub model: CalibrationModel,
+    pub message: Option<Result<String, String>>, // Outcome of the last record/fit
+}
+
+impl Default for CalibrationSession {
+    fn default() -> Self {
+        Self { points: Vec::new(), reference: String::new(), model: CalibrationModel::Linear, message: None }
+    }
+}
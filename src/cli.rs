@@ -0,0 +1,454 @@
+// src/cli.rs
+
+// One-shot command line operations, so scripts and CI don't need the GUI:
+//   TempSenseGUI list-ports
+//   TempSenseGUI ping <port> [--baud N] [--binary]
+//   TempSenseGUI set <port> <temp> [--baud N] [--binary] [--activate] [--config FILE]
+//   TempSenseGUI monitor <port> [--baud N] [--binary] [--duration SECONDS]
+//   TempSenseGUI flash <port> <image.bin> [--baud N] [--offset ADDR] [--flash-baud N] [--no-verify]
+//   TempSenseGUI bridge [--config FILE | --profile NAME] [--script FILE] [--log-file FILE] [--start]
+//   TempSenseGUI --headless [--start]    (same as `bridge` with the GUI's active profile)
+// Without arguments the GUI starts.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::esp_comm::flasher::{self, FlashOptions, FlashStage};
+use TempSenseGUI::esp_comm::transport::TransportDescriptor;
+use TempSenseGUI::controller::{self, SafetyLimits, MAX_TARGET_DECIMALS};
+use TempSenseGUI::esp_comm::{Capabilities, EspCommand, EspStatus, WireProtocol, esp_worker_thread, CAPABILITIES_REQUEST};
+use TempSenseGUI::config::Config;
+use TempSenseGUI::headless::{self, EXIT_DEVICE_ERROR, EXIT_OK, EXIT_TIMEOUT, EXIT_USAGE};
+use TempSenseGUI::logging::LogFileConfig;
+use TempSenseGUI::scripting::Script;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const CAPABILITIES_TIMEOUT: Duration = Duration::from_millis(500); // Older firmware may not answer at all
+const DEFAULT_BAUD_RATE: u32 = 115200;
+
+const USAGE: &str = "Usage:
+  TempSenseGUI                                  start the GUI
+  TempSenseGUI --headless [--start]             run the OSC bridge with the GUI's active profile
+  TempSenseGUI list-ports                       list serial ports
+  TempSenseGUI ping <port> [options]            check that a module answers
+  TempSenseGUI set <port> <temp> [options]      set one target temperature
+        --activate                              also send tempActive 1
+        --config FILE                           check <temp> against the limits in a config file (default -10 to 40°C)
+  TempSenseGUI monitor <port> [options]         print telemetry
+        --duration SECONDS                      stop after this long (default: until Ctrl-C)
+  TempSenseGUI flash <port> <image.bin> [options]  write a firmware image through the ESP32 bootloader
+        --offset ADDR                           flash address, decimal or 0x hex (default 0x10000)
+        --flash-baud N                          baud rate while flashing (default 460800, 0 keeps --baud)
+        --no-verify                             skip the MD5 check of the written flash
+  TempSenseGUI bridge [options]                 run the OSC bridge
+        --config FILE                           with settings from a config file (as exported from App Settings)
+        --profile NAME                          with one of the GUI's saved profiles (default: the active one)
+        --script FILE                           map OSC input to targets with a Rhai script (reloaded on change)
+        --log-file FILE                         also write the log to FILE (rotated at 5 MB, 3 files kept)
+        --start                                 activate modules when connected (default: each once it has a target)
+Options:
+  --baud N      baud rate for serial ports (default 115200)
+  --binary      request binary framing (falls back to ASCII on older firmware)
+<port> is a serial port name, tcp://IP:PORT, udp://IP:PORT or sim (sim:legacy acts like older firmware).
+UDP telemetry is received on the device's port number, udp://IP:PORT?local=PORT listens on another.
+Exit codes: 0 ok, 1 device error, 2 usage error, 3 no reply. On Windows, cmd.exe doesn't wait for
+release builds, use `start /wait TempSenseGUI ...` there to get the exit code.";
+
+// Returns Some(exit code) when the arguments selected a command line operation,
+// None when the GUI should start.
+pub fn run(args: &[String], app_id: &str) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let code = match command.as_str() {
+        "--headless" => with_options(rest, 0, |_, options| {
+            headless::init_logging();
+            run_bridge(headless::load_persisted_config(app_id, None), None, options.start)
+        }),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        "list-ports" => list_ports(),
+        "ping" => with_options(rest, 1, |positional, options| ping(&positional[0], options)),
+        "set" => with_options(rest, 2, |positional, options| {
+            let limits = match &options.config {
+                Some(path) => match Config::load_file(path) {
+                    Ok(config) => config.limits,
+                    Err(e) => return usage_error(&e),
+                },
+                None => SafetyLimits::default(),
+            };
+            match positional[1].parse::<f32>() {
+                Ok(temp) if temp.is_finite() && temp >= limits.value_min && temp <= limits.value_max => set_temp(&positional[0], temp, &limits, options),
+                Ok(temp) if temp.is_finite() => usage_error(&format!("{}°C is outside the safety limits ({} to {}°C)", temp, limits.value_min, limits.value_max)),
+                _ => usage_error(&format!("Invalid temperature '{}'", positional[1])),
+            }
+        }),
+        "monitor" => with_options(rest, 1, |positional, options| monitor(&positional[0], options)),
+        "flash" => with_options(rest, 2, |positional, options| flash(&positional[0], &positional[1], options)),
+        "bridge" => with_options(rest, 0, |_, options| {
+            let log = headless::init_logging();
+            if let Some(path) = &options.log_file {
+                if let Err(e) = log.set_file(Some(LogFileConfig::new(path))) {
+                    log::error!("{}", e);
+                    return EXIT_USAGE;
+                }
+            }
+            match (&options.config, &options.profile) {
+                (Some(_), Some(_)) => usage_error("--config and --profile can't be combined"),
+                (Some(path), None) => run_bridge(Config::load_file(path), options.script.as_deref(), options.start),
+                (None, profile) => run_bridge(headless::load_persisted_config(app_id, profile.as_deref()), options.script.as_deref(), options.start),
+            }
+        }),
+        // eframe/OS may pass flags of their own (e.g. -psn_ on macOS); leave those to the GUI
+        other if other.starts_with('-') => return None,
+        other => usage_error(&format!("Unknown command '{}'", other)),
+    };
+    Some(code)
+}
+
+fn run_bridge(config: Result<Config, String>, script_path: Option<&str>, start: bool) -> i32 {
+    let script = script_path.map(Script::load_file).transpose();
+    match config.and_then(|config| script.map(|script| (config, script))) {
+        Ok((config, script)) => headless::run(config, script, start),
+        Err(e) => {
+            log::error!("{}", e);
+            EXIT_USAGE
+        }
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    baud_rate: Option<u32>,
+    binary: bool,
+    activate: bool,
+    start: bool,
+    duration: Option<Duration>,
+    config: Option<String>,
+    profile: Option<String>,
+    log_file: Option<String>,
+    script: Option<String>,
+    offset: Option<u32>,
+    flash_baud_rate: Option<u32>,
+    no_verify: bool,
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("{}\n\n{}", message, USAGE);
+    EXIT_USAGE
+}
+
+// Splits `args` into exactly `positional_count` positional arguments and options, then runs `f`.
+fn with_options(args: &[String], positional_count: usize, f: impl FnOnce(&[String], &Options) -> i32) -> i32 {
+    let mut positional = Vec::new();
+    let mut options = Options::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--binary" => options.binary = true,
+            "--activate" => options.activate = true,
+            "--start" => options.start = true,
+            "--no-verify" => options.no_verify = true,
+            "--baud" | "--duration" | "--config" | "--profile" | "--log-file" | "--script" | "--offset" | "--flash-baud" => {
+                let Some(value) = iter.next() else {
+                    return usage_error(&format!("Missing value for {}", arg));
+                };
+                let parsed = match arg.as_str() {
+                    "--baud" => value.parse().map(|baud| options.baud_rate = Some(baud)).is_ok(),
+                    "--duration" => value.parse::<f64>().map(|secs| options.duration = Some(Duration::from_secs_f64(secs.max(0.0)))).is_ok(),
+                    "--offset" => parse_address(value).map(|offset| options.offset = Some(offset)).is_some(),
+                    "--flash-baud" => value.parse().map(|baud| options.flash_baud_rate = Some(baud)).is_ok(),
+                    "--config" => {
+                        options.config = Some(value.clone());
+                        true
+                    }
+                    "--profile" => {
+                        options.profile = Some(value.clone());
+                        true
+                    }
+                    "--script" => {
+                        options.script = Some(value.clone());
+                        true
+                    }
+                    _ => {
+                        options.log_file = Some(value.clone());
+                        true
+                    }
+                };
+                if !parsed {
+                    return usage_error(&format!("Invalid value '{}' for {}", value, arg));
+                }
+            }
+            other if other.starts_with("--") => return usage_error(&format!("Unknown option '{}'", other)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if positional.len() != positional_count {
+        return usage_error(&format!("Expected {} argument(s), got {}", positional_count, positional.len()));
+    }
+    f(&positional, &options)
+}
+
+fn list_ports() -> i32 {
+    match serialport::available_ports() {
+        Ok(ports) => {
+            for port in ports {
+                let details = match port.port_type {
+                    serialport::SerialPortType::UsbPort(info) => format!(
+                        "USB {:04x}:{:04x} {} {}",
+                        info.vid, info.pid, info.manufacturer.unwrap_or_default(), info.product.unwrap_or_default()
+                    ),
+                    serialport::SerialPortType::PciPort => "PCI".to_string(),
+                    serialport::SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+                    serialport::SerialPortType::Unknown => "Unknown".to_string(),
+                };
+                println!("{}\t{}", port.port_name, details.trim());
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Could not list serial ports: {}", e);
+            EXIT_DEVICE_ERROR
+        }
+    }
+}
+
+// A connected ESP worker for the duration of one command
+struct Device {
+    command_sender: Sender<EspCommand>,
+    status_receiver: Receiver<EspStatus>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl Device {
+    fn connect(port: &str, options: &Options) -> Result<Self, i32> {
+        let (command_s, command_r) = mpsc::channel();
+        let (status_s, status_r) = mpsc::channel();
+        let thread_handle = thread::spawn(move || esp_worker_thread(command_r, status_s));
+        let descriptor = TransportDescriptor::from_port_name(port, options.baud_rate.unwrap_or(DEFAULT_BAUD_RATE));
+        let protocol = if options.binary { WireProtocol::Binary } else { WireProtocol::Ascii };
+        command_s.send(EspCommand::Connect(descriptor, protocol)).ok();
+
+        let device = Self { command_sender: command_s, status_receiver: status_r, thread_handle: Some(thread_handle) };
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            match device.next_status(deadline) {
+                Some(EspStatus::ProtocolSelected(_)) => return Ok(device),
+                Some(EspStatus::Error(e)) => {
+                    eprintln!("{}", e);
+                    return Err(EXIT_DEVICE_ERROR);
+                }
+                Some(EspStatus::Disconnected(reason)) => {
+                    eprintln!("{}", reason.unwrap_or_else(|| "Disconnected.".to_string()));
+                    return Err(EXIT_DEVICE_ERROR);
+                }
+                Some(_) => continue,
+                None => {
+                    eprintln!("Timed out connecting to {}", port);
+                    return Err(EXIT_TIMEOUT);
+                }
+            }
+        }
+    }
+
+    fn next_status(&self, deadline: Instant) -> Option<EspStatus> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match self.status_receiver.recv_timeout(remaining) {
+            Ok(status) => Some(status),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(EspStatus::Disconnected(Some("ESP worker thread exited.".to_string()))),
+        }
+    }
+
+    fn send(&self, command: &str) {
+        self.command_sender.send(EspCommand::SendCommand(command.to_string())).ok();
+    }
+
+    // Asks the firmware what it supports. None when it rejects the request or doesn't answer.
+    fn capabilities(&self) -> Option<Capabilities> {
+        self.send(CAPABILITIES_REQUEST);
+        let deadline = Instant::now() + CAPABILITIES_TIMEOUT;
+        loop {
+            match self.next_status(deadline)? {
+                EspStatus::Message(msg) => {
+                    for line in msg.lines() {
+                        if let Some(capabilities) = Capabilities::parse(line) {
+                            return Some(capabilities);
+                        }
+                        if line.trim().starts_with("ERR") && line.contains(CAPABILITIES_REQUEST) {
+                            return None;
+                        }
+                    }
+                }
+                EspStatus::Disconnected(_) => return None,
+                _ => continue,
+            }
+        }
+    }
+
+    // Waits until the worker confirms `count` commands were written, or fails
+    fn wait_written(&self, count: u32) -> Result<(), i32> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            match self.next_status(deadline) {
+                Some(EspStatus::CommandLatency(latency)) if latency.count >= count => return Ok(()),
+                Some(EspStatus::Error(e)) => {
+                    eprintln!("{}", e);
+                    return Err(EXIT_DEVICE_ERROR);
+                }
+                Some(_) => continue,
+                None => return Err(EXIT_TIMEOUT),
+            }
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.command_sender.send(EspCommand::StopThread).ok();
+        if let Some(handle) = self.thread_handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+fn ping(port: &str, options: &Options) -> i32 {
+    let device = match Device::connect(port, options) {
+        Ok(device) => device,
+        Err(code) => return code,
+    };
+    let sent_at = Instant::now();
+    device.send("PING");
+    let deadline = sent_at + REPLY_TIMEOUT;
+    loop {
+        match device.next_status(deadline) {
+            Some(EspStatus::Message(msg)) if msg.lines().any(|line| line.trim() == "PONG") => {
+                println!("PONG from {} in {:.1} ms", port, sent_at.elapsed().as_secs_f64() * 1000.0);
+                return EXIT_OK;
+            }
+            Some(EspStatus::Disconnected(reason)) => {
+                eprintln!("{}", reason.unwrap_or_else(|| "Disconnected.".to_string()));
+                return EXIT_DEVICE_ERROR;
+            }
+            Some(_) => continue,
+            None => {
+                eprintln!("No PONG from {} within {:?}", port, REPLY_TIMEOUT);
+                return EXIT_TIMEOUT;
+            }
+        }
+    }
+}
+
+fn set_temp(port: &str, temp: f32, limits: &SafetyLimits, options: &Options) -> i32 {
+    let device = match Device::connect(port, options) {
+        Ok(device) => device,
+        Err(code) => return code,
+    };
+    let decimals = if device.capabilities().is_some_and(|c| c.float_targets()) { MAX_TARGET_DECIMALS } else { 0 };
+    let temp = limits.quantize(temp, decimals);
+    device.send(&controller::format_target(temp, decimals));
+    let mut expected = 2; // getCaps and setTemp
+    if options.activate {
+        device.send("tempActive 1");
+        expected += 1;
+    }
+    match device.wait_written(expected) {
+        Ok(()) => {
+            println!("Set {} to {}°C{}", port, temp, if options.activate { " (active)" } else { "" });
+            EXIT_OK
+        }
+        Err(code) => code,
+    }
+}
+
+fn monitor(port: &str, options: &Options) -> i32 {
+    let device = match Device::connect(port, options) {
+        Ok(device) => device,
+        Err(code) => return code,
+    };
+    // Without --duration this runs until the process is interrupted
+    let deadline = options.duration.map(|duration| Instant::now() + duration);
+    loop {
+        let wait_until = deadline.unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
+        match device.next_status(wait_until) {
+            Some(EspStatus::Message(msg)) => {
+                for line in msg.lines().filter(|line| !line.trim().is_empty()) {
+                    println!("{} {}", chrono::Local::now().format("%H:%M:%S%.3f"), line.trim());
+                }
+            }
+            Some(EspStatus::Disconnected(reason)) => {
+                eprintln!("{}", reason.unwrap_or_else(|| "Disconnected.".to_string()));
+                return EXIT_DEVICE_ERROR;
+            }
+            Some(EspStatus::Error(e)) => eprintln!("{}", e),
+            Some(_) => {}
+            None if deadline.is_some_and(|d| Instant::now() >= d) => return EXIT_OK,
+            None => {}
+        }
+    }
+}
+
+// "65536" or "0x10000"
+fn parse_address(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn flash(port: &str, path: &str, options: &Options) -> i32 {
+    let image = match std::fs::read(path) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    };
+    let flash_options = FlashOptions {
+        offset: options.offset.unwrap_or(flasher::DEFAULT_APP_OFFSET),
+        flash_baud_rate: match options.flash_baud_rate {
+            Some(0) => None,
+            Some(baud) => Some(baud),
+            None => Some(flasher::DEFAULT_FLASH_BAUD_RATE),
+        },
+        verify: !options.no_verify,
+    };
+    match flasher::inspect_image(&image, flash_options.offset) {
+        Ok(info) => println!("{}: {}", path, info.describe()),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+    let mut link = match flasher::open_link(port, options.baud_rate.unwrap_or(DEFAULT_BAUD_RATE)) {
+        Ok(link) => link,
+        Err(e) => {
+            eprintln!("Could not open {}: {}", port, e);
+            return EXIT_DEVICE_ERROR;
+        }
+    };
+    // One line per stage, and every 10% while writing
+    let mut last = (None, 0);
+    let result = flasher::flash_image(link.as_mut(), &image, &flash_options, &mut |progress| {
+        let percent = (progress.fraction() * 10.0) as usize * 10;
+        if last != (Some(progress.stage), percent) {
+            last = (Some(progress.stage), percent);
+            match progress.stage {
+                FlashStage::Writing => eprintln!("Writing {}%", percent),
+                _ => eprintln!("{}", progress.describe()),
+            }
+        }
+    });
+    match result {
+        Ok(report) => {
+            println!("Flashed {} bytes to {} in {:.1}s{}", report.bytes, port, report.elapsed.as_secs_f32(), if report.verified { ", verified" } else { "" });
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_DEVICE_ERROR
+        }
+    }
+}
@@ -0,0 +1,95 @@
+// src/comfort.rs
+
+// Perceived-temperature mode. OSC /SensationN sends a sensation on a -1..+1 scale ("cold" to
+// "hot") instead of a target in °C, and the comfort model turns it into a target relative to the
+// wearer's own skin temperature:
+//   neutral = skin baseline + neutral_offset + ambient_weight·(ambient - ambient_reference)
+//   target  = neutral + warm_span·s^exponent          for s > 0
+//           = neutral - cool_span·|s|^exponent        for s < 0
+// The baseline is the module's (calibrated) skin temperature when it enters the mode, so the
+// target doesn't chase the skin it is heating or cooling. Until the module reports one,
+// default_skin stands in and the first reading replaces it.
+
+pub const SENSATION_MIN: f32 = -1.0;
+pub const SENSATION_MAX: f32 = 1.0;
+
+// Words for the scale, from -1 to +1
+const LABELS: [&str; 7] = ["cold", "cool", "slightly cool", "neutral", "slightly warm", "warm", "hot"];
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComfortModel {
+    pub warm_span: f32,         // °C above neutral at +1
+    pub cool_span: f32,         // °C below neutral at -1
+    pub exponent: f32,          // Shape of the curve, 1 is linear, above 1 is finer around neutral
+    pub neutral_offset: f32,    // °C added to the skin baseline for 0
+    pub ambient_reference: f32, // °C, room temperature without an ambient correction
+    pub ambient_weight: f32,    // °C the neutral point moves per °C of ambient above the reference
+    pub default_skin: f32,      // °C, baseline until the module reports a skin temperature
+}
+
+impl Default for ComfortModel {
+    fn default() -> Self {
+        Self {
+            warm_span: 8.0,
+            cool_span: 12.0, // Cold is felt less intensely than heat for the same difference
+            exponent: 1.0,
+            neutral_offset: 0.0,
+            ambient_reference: 22.0,
+            ambient_weight: 0.0,
+            default_skin: 32.0,
+        }
+    }
+}
+
+impl ComfortModel {
+    // Target that feels neutral. Without an ambient reading no correction is made.
+    pub fn neutral(&self, baseline: f32, ambient: Option<f32>) -> f32 {
+        let ambient_shift = ambient.map_or(0.0, |ambient| self.ambient_weight * (ambient - self.ambient_reference));
+        baseline + self.neutral_offset + ambient_shift
+    }
+
+    // Target for `sensation`, clamped to -1..+1. The safety limits are left to the controller.
+    pub fn target(&self, sensation: f32, baseline: f32, ambient: Option<f32>) -> f32 {
+        let sensation = sensation.clamp(SENSATION_MIN, SENSATION_MAX);
+        let span = if sensation >= 0.0 { self.warm_span } else { self.cool_span };
+        self.neutral(baseline, ambient) + sensation.signum() * span * sensation.abs().powf(self.exponent)
+    }
+
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in [("warm_span", self.warm_span), ("cool_span", self.cool_span), ("exponent", self.exponent)] {
+            if !(value.is_finite() && value > 0.0) {
+                problems.push(format!("{}: must be above 0, found {}", name, value));
+            }
+        }
+        for (name, value) in [("neutral_offset", self.neutral_offset), ("ambient_reference", self.ambient_reference), ("ambient_weight", self.ambient_weight), ("default_skin", self.default_skin)] {
+            if !value.is_finite() {
+                problems.push(format!("{}: must be a number, found {}", name, value));
+            }
+        }
+        problems
+    }
+}
+
+// Nearest word for a sensation, e.g. -0.3 is "slightly cool"
+pub fn label(sensation: f32) -> &'static str {
+    let step = (SENSATION_MAX - SENSATION_MIN) / (LABELS.len() - 1) as f32;
+    let index = ((sensation.clamp(SENSATION_MIN, SENSATION_MAX) - SENSATION_MIN) / step).round() as usize;
+    LABELS[index.min(LABELS.len() - 1)]
+}
+
+// A module in perceived-temperature mode, see Controller::set_sensation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComfortState {
+    pub sensation: f32,
+    pub baseline: f32,  // Skin temperature the sensation is relative to
+    pub measured: bool, // False while ComfortModel::default_skin stands in for the baseline
+}
+
+impl ComfortState {
+    pub fn new(sensation: f32, skin_temp: Option<f32>, model: &ComfortModel) -> Self {
+        Self { sensation, baseline: skin_temp.unwrap_or(model.default_skin), measured: skin_temp.is_some() }
+    }
+}
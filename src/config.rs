@@ -0,0 +1,415 @@
+// src/config.rs
+
+// Versioned, human editable configuration (JSON): modules, ports, baud rates, OSC settings,
+// target and skin limits, target resolution, waveform presets, sensor calibrations, PID gain sets, the comfort model,
+// the spatial layout and the telemetry outlet. The GUI persists it through eframe storage and can import/export it as a file, headless
+// mode and `bridge --config` read the same format, so a lab setup can be copied between machines.
+//
+// Loading never silently falls back to defaults: older versions are migrated step by step,
+// unknown fields are rejected and every validation problem is reported.
+//
+// OSC mapping: the N-th module receives the targets sent to /PeltN and the sensations sent to
+// /SensationN; modules placed on the spatial layout follow /TempSense/point.
+
+use std::collections::{BTreeMap, HashSet};
+use std::net::Ipv4Addr;
+
+use serde_json::{Map, Value, json};
+
+use crate::calibration::Calibration;
+use crate::comfort::ComfortModel;
+use crate::controller::{Controller, Module, SafetyLimits, DEFAULT_SKIN_LIMITS, DEFAULT_TARGET_DECIMALS, MAX_TARGET_DECIMALS};
+use crate::esp_comm::WireProtocol;
+use crate::outlet::OutletSettings;
+use crate::pid::PidPreset;
+use crate::spatial::SpatialLayout;
+use crate::waveform::WaveformPreset;
+
+// Version written by this build. Bump it together with a new migrate_vN step.
+pub const CONFIG_VERSION: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub version: u32,
+    pub osc: OscConfig,
+    pub limits: SafetyLimits,
+    pub skin_limits: SafetyLimits, // Skin temperatures that switch a module off
+    pub target_decimals: u8, // Decimal places of targets, see controller::DEFAULT_TARGET_DECIMALS
+    pub modules: Vec<ModuleConfig>,
+    pub waveforms: Vec<WaveformPreset>,
+    pub calibrations: BTreeMap<String, Calibration>, // By device serial number (or port, see Module::device_key)
+    pub pid_presets: Vec<PidPreset>,
+    pub comfort: ComfortModel, // Perceived-temperature mode, see comfort.rs
+    pub spatial: SpatialLayout, // Module positions for /TempSense/point, see spatial.rs
+    pub outlet: OutletSettings, // Telemetry and marker stream, see outlet.rs
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OscConfig {
+    pub ip: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleConfig {
+    pub short_name: String, // "L", used in log sources ("ESP L")
+    pub long_name: String,  // "Left", used in headings
+    pub port: String,       // Serial port, tcp://IP:PORT, udp://IP:PORT[?local=PORT] or sim
+    pub baud_rate: u32,
+    pub protocol: WireProtocol,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::capture(&Controller::default(), &OscConfig::default())
+    }
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self { ip: "127.0.0.1".to_owned(), port: 9000 }
+    }
+}
+
+impl Config {
+    // Snapshot of the current settings
+    pub fn capture(controller: &Controller, osc: &OscConfig) -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            osc: osc.clone(),
+            limits: controller.limits,
+            skin_limits: controller.skin_limits,
+            target_decimals: controller.target_decimals,
+            modules: controller.modules.iter().map(|module| ModuleConfig {
+                short_name: module.short_name.clone(),
+                long_name: module.long_name.clone(),
+                port: module.port.clone(),
+                baud_rate: module.baud_rate,
+                protocol: module.protocol,
+            }).collect(),
+            waveforms: controller.waveform_presets.clone(),
+            calibrations: controller.calibrations.clone(),
+            pid_presets: controller.pid_presets.clone(),
+            comfort: controller.comfort_model,
+            spatial: controller.spatial.clone(),
+            outlet: controller.outlet_settings.clone(),
+        }
+    }
+
+    // Replaces the controller's modules and limits. Modules must be disconnected first,
+    // since their ports and protocols may change.
+    pub fn apply_to(&self, controller: &mut Controller) -> Result<(), String> {
+        if controller.modules.iter().any(Module::is_worker_running) {
+            return Err("Disconnect all modules before loading a configuration.".to_string());
+        }
+        if controller.modules.iter().any(|module| module.flash.as_ref().is_some_and(|job| job.is_running())) {
+            return Err("Wait for the firmware update to finish before loading a configuration.".to_string());
+        }
+        controller.limits = self.limits;
+        controller.skin_limits = self.skin_limits;
+        controller.target_decimals = self.target_decimals;
+        controller.modules = self.modules.iter().map(|module_config| {
+            let mut module = Module::new(&module_config.short_name, &module_config.long_name, &module_config.port);
+            module.baud_rate = module_config.baud_rate;
+            module.protocol = module_config.protocol;
+            module
+        }).collect();
+        controller.waveform_presets = self.waveforms.clone();
+        controller.calibrations = self.calibrations.clone();
+        controller.pid_presets = self.pid_presets.clone();
+        controller.comfort_model = self.comfort;
+        controller.spatial = self.spatial.clone();
+        controller.spatial_sources.clear();
+        controller.outlet_settings = self.outlet.clone();
+        Ok(())
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| format!("Not valid JSON: {}", e))?;
+        Self::from_value(value)
+    }
+
+    // Migrates, parses and validates a configuration of any supported version
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        let value = migrate(value)?;
+        let config: Self = serde_json::from_value(value).map_err(|e| format!("Invalid configuration: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("config is always serializable")
+    }
+
+    pub fn load_file(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        Self::from_json(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save_file(&self, path: &str) -> Result<(), String> {
+        self.validate()?;
+        std::fs::write(path, self.to_json() + "\n").map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    // Reports every problem at once, one per line
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.version != CONFIG_VERSION {
+            errors.push(format!("version: expected {}, found {}", CONFIG_VERSION, self.version));
+        }
+        if self.osc.ip.parse::<Ipv4Addr>().is_err() {
+            errors.push(format!("osc.ip: '{}' is not an IPv4 address", self.osc.ip));
+        }
+        if self.osc.port == 0 {
+            errors.push("osc.port: must not be 0".to_string());
+        }
+        if let Err(e) = self.limits.validate() {
+            errors.push(format!("limits: {}", e));
+        }
+        if let Err(e) = self.skin_limits.validate() {
+            errors.push(format!("skin_limits: {}", e));
+        }
+        if self.target_decimals > MAX_TARGET_DECIMALS {
+            errors.push(format!("target_decimals: must be at most {}, found {}", MAX_TARGET_DECIMALS, self.target_decimals));
+        }
+        if self.modules.is_empty() {
+            errors.push("modules: at least one module is required".to_string());
+        }
+        let mut names = HashSet::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            if module.short_name.trim().is_empty() {
+                errors.push(format!("modules[{}].short_name: must not be empty", index));
+            } else if !names.insert(module.short_name.as_str()) {
+                errors.push(format!("modules[{}].short_name: '{}' is used twice", index, module.short_name));
+            }
+            if module.port.trim().is_empty() {
+                errors.push(format!("modules[{}].port: must not be empty", index));
+            }
+            if module.baud_rate == 0 {
+                errors.push(format!("modules[{}].baud_rate: must not be 0", index));
+            }
+        }
+        let mut preset_names = HashSet::new();
+        for (index, preset) in self.waveforms.iter().enumerate() {
+            if preset.name.trim().is_empty() {
+                errors.push(format!("waveforms[{}].name: must not be empty", index));
+            } else if !preset_names.insert(preset.name.as_str()) {
+                errors.push(format!("waveforms[{}].name: '{}' is used twice", index, preset.name));
+            }
+            for problem in preset.waveform.problems() {
+                errors.push(format!("waveforms[{}].{}", index, problem));
+            }
+            if let Err(e) = preset.waveform.check_limits(&self.limits) {
+                errors.push(format!("waveforms[{}]: {}", index, e));
+            }
+        }
+        for (device, calibration) in &self.calibrations {
+            for problem in calibration.problems() {
+                errors.push(format!("calibrations.{}.{}", device, problem));
+            }
+        }
+        let mut pid_names = HashSet::new();
+        for (index, preset) in self.pid_presets.iter().enumerate() {
+            if preset.name.trim().is_empty() {
+                errors.push(format!("pid_presets[{}].name: must not be empty", index));
+            } else if !pid_names.insert(preset.name.as_str()) {
+                errors.push(format!("pid_presets[{}].name: '{}' is used twice", index, preset.name));
+            }
+            for problem in preset.gains.problems() {
+                errors.push(format!("pid_presets[{}].gains.{}", index, problem));
+            }
+        }
+        for problem in self.comfort.problems() {
+            errors.push(format!("comfort.{}", problem));
+        }
+        let module_names: Vec<&str> = self.modules.iter().map(|module| module.short_name.as_str()).collect();
+        for problem in self.spatial.problems(&module_names) {
+            errors.push(format!("spatial.{}", problem));
+        }
+        for problem in self.outlet.problems() {
+            errors.push(format!("outlet.{}", problem));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration:\n  {}", errors.join("\n  ")))
+        }
+    }
+}
+
+// Brings `value` up to CONFIG_VERSION, one version at a time
+fn migrate(mut value: Value) -> Result<Value, String> {
+    loop {
+        let Value::Object(map) = &value else {
+            return Err("Invalid configuration: expected an object".to_string());
+        };
+        // Files without a version are the flat settings layout GUI versions up to v0.3 persisted
+        let version = match map.get("version") {
+            None => 1,
+            Some(version) => version.as_u64().ok_or_else(|| format!("version: expected a number, found {}", version))?,
+        };
+        value = match version {
+            1 => migrate_v1(map)?,
+            2 => migrate_v2(map),
+            3 => migrate_v3(map),
+            4 => migrate_v4(map),
+            5 => migrate_v5(map),
+            6 => migrate_v6(map),
+            7 => migrate_v7(map),
+            8 => migrate_v8(map),
+            9 => migrate_v9(map),
+            v if v == u64::from(CONFIG_VERSION) => return Ok(value),
+            v => return Err(format!("version: {} is not supported by this build (newest is {})", v, CONFIG_VERSION)),
+        };
+    }
+}
+
+// v1: flat `TemplateApp` fields, two fixed modules, OSC port as a string
+fn migrate_v1(map: &Map<String, Value>) -> Result<Value, String> {
+    let defaults = Config::default();
+    let field = |key: &str, default: Value| map.get(key).cloned().unwrap_or(default);
+
+    let osc_port = match map.get("osc_port") {
+        None => json!(defaults.osc.port),
+        Some(Value::String(port)) => json!(port.trim().parse::<u16>().map_err(|_| format!("osc_port: '{}' is not a port number", port))?),
+        Some(port) => port.clone(),
+    };
+    let modules: Vec<Value> = defaults.modules.iter().take(2).enumerate().map(|(index, default)| {
+        let n = index + 1;
+        json!({
+            "short_name": default.short_name,
+            "long_name": default.long_name,
+            "port": field(&format!("esp_port_{}", n), json!(default.port)),
+            "baud_rate": field(&format!("esp_baud_rate_{}", n), json!(default.baud_rate)),
+            "protocol": field(&format!("esp_protocol_{}", n), json!(default.protocol)),
+        })
+    }).collect();
+
+    Ok(json!({
+        "version": 2,
+        "osc": {
+            "ip": field("osc_ip", json!(defaults.osc.ip)),
+            "port": osc_port,
+        },
+        "limits": {
+            "value_min": field("value_min", json!(defaults.limits.value_min)),
+            "value_max": field("value_max", json!(defaults.limits.value_max)),
+        },
+        "modules": modules,
+    }))
+}
+
+// v2 -> v3: waveform presets
+fn migrate_v2(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(3));
+    map.insert("waveforms".to_string(), json!([]));
+    Value::Object(map)
+}
+
+// v3 -> v4: sub-degree targets
+fn migrate_v3(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(4));
+    map.insert("target_decimals".to_string(), json!(DEFAULT_TARGET_DECIMALS));
+    Value::Object(map)
+}
+
+// v4 -> v5: sensor calibrations
+fn migrate_v4(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(5));
+    map.insert("calibrations".to_string(), json!({}));
+    Value::Object(map)
+}
+
+// v5 -> v6: PID gain sets
+fn migrate_v5(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(6));
+    map.insert("pid_presets".to_string(), json!([]));
+    Value::Object(map)
+}
+
+// v6 -> v7: comfort model
+fn migrate_v6(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(7));
+    map.insert("comfort".to_string(), json!(ComfortModel::default()));
+    Value::Object(map)
+}
+
+// v7 -> v8: spatial layout
+fn migrate_v7(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(8));
+    map.insert("spatial".to_string(), json!(SpatialLayout::default()));
+    Value::Object(map)
+}
+
+// v8 -> v9: telemetry outlet, off
+fn migrate_v8(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(9));
+    map.insert("outlet".to_string(), json!(OutletSettings::default()));
+    Value::Object(map)
+}
+
+// v9 -> v10: skin limits of their own, the target limits used to double as them
+fn migrate_v9(map: &Map<String, Value>) -> Value {
+    let mut map = map.clone();
+    map.insert("version".to_string(), json!(10));
+    map.insert("skin_limits".to_string(), json!(DEFAULT_SKIN_LIMITS));
+    Value::Object(map)
+}
+
+// eframe storage key of the configuration. Stored as JSON, like exported files.
+pub const STORAGE_KEY: &str = "config";
+
+// Settings as GUI versions before the versioned format persisted them (RON under eframe::APP_KEY)
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct LegacySettings {
+    osc_ip: String,
+    osc_port: String,
+    value_max: i8,
+    value_min: i8,
+    esp_port_1: String,
+    esp_baud_rate_1: u32,
+    esp_protocol_1: WireProtocol,
+    esp_port_2: String,
+    esp_baud_rate_2: u32,
+    esp_protocol_2: WireProtocol,
+}
+
+impl Default for LegacySettings {
+    fn default() -> Self {
+        let defaults = Config::default();
+        Self {
+            osc_ip: defaults.osc.ip,
+            osc_port: defaults.osc.port.to_string(),
+            value_max: defaults.limits.value_max as i8,
+            value_min: defaults.limits.value_min as i8,
+            esp_port_1: defaults.modules[0].port.clone(),
+            esp_baud_rate_1: defaults.modules[0].baud_rate,
+            esp_protocol_1: defaults.modules[0].protocol,
+            esp_port_2: defaults.modules[1].port.clone(),
+            esp_baud_rate_2: defaults.modules[1].baud_rate,
+            esp_protocol_2: defaults.modules[1].protocol,
+        }
+    }
+}
+
+// Reads the configuration the GUI persisted through eframe storage: `stored` is the value under
+// STORAGE_KEY, `legacy` the one under eframe::APP_KEY. Ok(None) when nothing was stored yet.
+pub fn from_storage(stored: Option<String>, legacy: Option<String>) -> Result<Option<Config>, String> {
+    if let Some(text) = stored {
+        return Config::from_json(&text).map(Some);
+    }
+    let Some(text) = legacy else { return Ok(None) };
+    let settings: LegacySettings = ron::from_str(&text).map_err(|e| format!("Stored settings are not valid: {}", e))?;
+    Config::from_value(serde_json::to_value(settings).map_err(|e| e.to_string())?).map(Some)
+}
@@ -0,0 +1,193 @@
+// src/console.rs
+
+// Per module serial console for firmware debugging: arbitrary input sent as raw bytes,
+// command history and the raw RX/TX stream as reported by the worker (EspStatus::Raw).
+// The controller only feeds the stream while raw capture is on (Controller::set_raw_capture).
+
+use std::collections::VecDeque;
+
+use crate::esp_comm::{RawChunk, RawDirection};
+
+const MAX_CHUNKS: usize = 2000;
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    None,
+    Lf,
+    Cr,
+    CrLf,
+}
+
+impl LineEnding {
+    pub const ALL: [LineEnding; 4] = [LineEnding::None, LineEnding::Lf, LineEnding::Cr, LineEnding::CrLf];
+
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::None => b"",
+            LineEnding::Lf => b"\n",
+            LineEnding::Cr => b"\r",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::None => "No line ending",
+            LineEnding::Lf => "LF",
+            LineEnding::Cr => "CR",
+            LineEnding::CrLf => "CR LF",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    Text,
+    Hex,
+}
+
+pub struct Console {
+    pub input: String,
+    pub line_ending: LineEnding,
+    pub hex_input: bool, // Input is hex bytes ("C0 01 ff") instead of text
+    pub view: ViewMode,
+    chunks: VecDeque<RawChunk>,
+    history: Vec<String>,
+    history_cursor: Option<usize>, // Position while browsing the history with up/down
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            input: String::new(),
+            line_ending: LineEnding::Lf, // What the firmware's ASCII protocol expects
+            hex_input: false,
+            view: ViewMode::Text,
+            chunks: VecDeque::new(),
+            history: Vec::new(),
+            history_cursor: None,
+        }
+    }
+}
+
+impl Console {
+    pub fn push(&mut self, chunk: RawChunk) {
+        self.chunks.push_back(chunk);
+        if self.chunks.len() > MAX_CHUNKS {
+            self.chunks.pop_front();
+        }
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &RawChunk> {
+        self.chunks.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    // Bytes to send for the current input, with the line ending applied to text input
+    pub fn encode_input(&self) -> Result<Vec<u8>, String> {
+        if self.hex_input {
+            return parse_hex(&self.input);
+        }
+        let mut bytes = self.input.as_bytes().to_vec();
+        bytes.extend_from_slice(self.line_ending.bytes());
+        Ok(bytes)
+    }
+
+    // Encodes the input and moves it to the history. The input is kept on error so it can be fixed.
+    pub fn take_input(&mut self) -> Result<Vec<u8>, String> {
+        let bytes = self.encode_input()?;
+        let input = std::mem::take(&mut self.input);
+        if !input.is_empty() && self.history.last() != Some(&input) {
+            self.history.push(input);
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.history_cursor = None;
+        Ok(bytes)
+    }
+
+    // Up arrow: older entries
+    pub fn history_prev(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match self.history_cursor {
+            Some(index) => index.saturating_sub(1),
+            None => self.history.len() - 1,
+        };
+        self.history_cursor = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    // Down arrow: newer entries, then back to an empty input
+    pub fn history_next(&mut self) {
+        match self.history_cursor {
+            Some(index) if index + 1 < self.history.len() => {
+                self.history_cursor = Some(index + 1);
+                self.input = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.history_cursor = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+// "HH:MM:SS.mmm TX> setTemp 20\n" style line for one chunk
+pub fn format_chunk(chunk: &RawChunk, view: ViewMode) -> String {
+    let marker = match chunk.direction {
+        RawDirection::Rx => "RX<",
+        RawDirection::Tx => "TX>",
+    };
+    let data = match view {
+        ViewMode::Text => escape_text(&chunk.bytes),
+        ViewMode::Hex => chunk.bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "),
+    };
+    format!("{} {} {}", chunk.timestamp.format("%H:%M:%S%.3f"), marker, data)
+}
+
+// Printable ASCII as is, line endings and other bytes escaped so they stay visible
+fn escape_text(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\n' => text.push_str("\\n"),
+            b'\r' => text.push_str("\\r"),
+            b'\t' => text.push_str("\\t"),
+            b'\\' => text.push_str("\\\\"),
+            0x20..=0x7E => text.push(byte as char),
+            _ => text.push_str(&format!("\\x{:02X}", byte)),
+        }
+    }
+    text
+}
+
+// Hex bytes separated by whitespace, commas or nothing ("C0 01", "c0,01", "C001"); "0x" prefixes are allowed
+pub fn parse_hex(input: &str) -> Result<Vec<u8>, String> {
+    let mut digits = String::new();
+    for token in input.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        let token = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+        if token.len() % 2 != 0 {
+            return Err(format!("'{}' is not a whole number of bytes", token));
+        }
+        digits.push_str(token);
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            let pair = digits.get(i..i + 2).ok_or_else(|| format!("Invalid hex input '{}'", input.trim()))?;
+            u8::from_str_radix(pair, 16).map_err(|_| format!("Invalid hex byte '{}'", pair))
+        })
+        .collect()
+}
@@ -0,0 +1,1320 @@
+// src/controller.rs
+
+// UI independent control core: owns the modules, their ESP worker threads and the OSC input,
+// and turns targets into commands. The egui app and headless mode both drive it by calling
+// tick() regularly and only read/mutate its state otherwise.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::calibration::Calibration;
+use crate::comfort::{self, ComfortModel, ComfortState};
+use crate::console::Console;
+use crate::esp_comm::clock::{self, ClockSync};
+use crate::esp_comm::flasher::{FlashEvent, FlashJob, FlashOptions};
+use crate::esp_comm::transport::{self, TransportDescriptor};
+use crate::esp_comm::telemetry::Telemetry;
+use crate::esp_comm::{Capabilities, CommandLatency, EspCommand, EspStatus, WireProtocol, esp_worker_thread, parse_serial, CAPABILITIES_REQUEST, SERIAL_REQUEST};
+use crate::experiment::{ExperimentEvent, ExperimentRun, Protocol};
+use crate::osc::OscInput;
+use crate::outlet::{self, Outlet, OutletEvent, OutletSettings};
+use crate::pid::{self, PidGains, PidLoop, PidPreset, PidSettings, StepResponse};
+use crate::scripting::{ModuleInput, Script};
+use crate::spatial::{self, SpatialLayout, SpatialPoint};
+use crate::sync::{self, SyncReport, SyncRun};
+use crate::waveform::{Waveform, WaveformPreset, WaveformRun};
+
+// Raw telemetry is kept apart from the event log (see logging.rs) so it can't flood it
+const MAX_TELEMETRY_LINES: usize = 500;
+
+// Resolution of targets, as decimal places: 1 means 0.1°C steps. Firmware that doesn't report
+// float support (see Capabilities) gets whole degrees whatever this is set to.
+pub const DEFAULT_TARGET_DECIMALS: u8 = 1;
+pub const MAX_TARGET_DECIMALS: u8 = 2; // Telemetry has two decimals, finer steps mean nothing
+
+pub struct TelemetryLine {
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub source: String, // "ESP L"
+    pub line: String,
+    pub calibrated_skin: Option<f32>, // Skin temperature after calibration, when one applies
+    pub device_time: bool, // Timestamped from the device clock (Device_us), else when it arrived
+}
+
+// Targets outside these limits are never sent to a module, whatever the source
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SafetyLimits {
+    pub value_min: f32,
+    pub value_max: f32,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        Self { value_min: -10.0, value_max: 40.0 }
+    }
+}
+
+// Skin temperatures outside these switch a module off (see check_skin_temp): cold and heat pain
+// thresholds, not the range targets may take
+pub const DEFAULT_SKIN_LIMITS: SafetyLimits = SafetyLimits { value_min: 15.0, value_max: 43.0 };
+const SKIN_ALARM_REFUSAL: &str = "the skin temperature is outside the skin limits";
+
+impl SafetyLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.value_min.is_finite() && self.value_max.is_finite()) {
+            return Err(format!("Invalid limits: min {} and max {} must be numbers.", self.value_min, self.value_max));
+        }
+        if self.value_min > self.value_max {
+            return Err(format!("Invalid limits: min {} is above max {}.", self.value_min, self.value_max));
+        }
+        Ok(())
+    }
+
+    // Invalid (inverted) limits are treated as the range between the two values
+    pub fn clamp(&self, temp: f32) -> f32 {
+        temp.clamp(self.value_min.min(self.value_max), self.value_max.max(self.value_min))
+    }
+
+    // Clamps and rounds to `decimals` places, rounding towards the inside at the edges
+    // (39.6 with a 39.6 max and whole degrees gives 39, not 40)
+    pub fn quantize(&self, temp: f32, decimals: u8) -> f32 {
+        let (min, max) = (self.value_min.min(self.value_max), self.value_max.max(self.value_min));
+        let scale = 10f32.powi(i32::from(decimals));
+        match round_target(self.clamp(temp), decimals) {
+            rounded if rounded > max => round_target((max * scale).floor() / scale, decimals),
+            rounded if rounded < min => round_target((min * scale).ceil() / scale, decimals),
+            rounded => rounded,
+        }
+    }
+}
+
+// Rounds to `decimals` places
+pub fn round_target(temp: f32, decimals: u8) -> f32 {
+    let scale = 10f32.powi(i32::from(decimals));
+    // + 0.0 turns -0.0 into 0.0, so it isn't sent as "-0"
+    (temp * scale).round() / scale + 0.0
+}
+
+// "setTemp 20.5"; `decimals` 0 gives the whole degree form older firmware understands
+pub fn format_target(temp: f32, decimals: u8) -> String {
+    format!("setTemp {:.*}", usize::from(decimals), temp)
+}
+
+// One wristband / peltier module and the link to its ESP.
+// Settings are stored through config::ModuleConfig.
+pub struct Module {
+    pub short_name: String, // "L", used in log sources ("ESP L")
+    pub long_name: String,  // "Left", used in headings
+    pub port: String,
+    pub baud_rate: u32,
+    pub protocol: WireProtocol, // Requested protocol, negotiated on connect
+
+    pub target: f32,
+    pub has_target: bool, // A target was set (OSC, script, waveform, protocol or by hand); before that `target` means nothing
+    pub manual_override: bool, // Ignore OSC targets while set
+    pub connected: bool,
+    pub active: bool, // tempActive 1 was sent on the current link
+    pub console: Console, // Raw RX/TX, filled while raw capture is on
+    pub waveform: Option<WaveformRun>, // Drives the target instead of OSC while set
+    pub comfort: Option<ComfortState>, // Perceived-temperature mode, see Controller::set_sensation
+    pub status_message: String,
+    pub active_protocol: Option<WireProtocol>,
+    pub corrupt_frames: u32,
+    pub command_latency: Option<CommandLatency>,
+    pub clock: ClockSync, // Device clock offset and round trip times on the current link
+    pub skin_temp: Option<f32>, // Calibrated
+    pub raw_skin_temp: Option<f32>, // As reported, what calibration readings are recorded from
+    pub skin_alarm: bool, // Calibrated skin temperature is outside the skin limits
+    pub telemetry: Option<Telemetry>, // Latest telemetry line, skin temperature calibrated
+    pub capabilities: Option<Capabilities>, // Reported by the firmware on the current link, None for older firmware
+    pub serial: Option<String>, // Reported by the firmware, else the USB adapter's serial number
+    pub pid: BTreeMap<PidLoop, PidSettings>, // Gains read from the firmware on the current link
+    pub pid_unsaved: bool, // Gains were written since the last commit to flash
+    pub step_response: Option<StepResponse>, // Latest step test, see Controller::start_step_test
+    pub flash: Option<FlashJob>, // Latest firmware update, see Controller::start_flash
+
+    sent_target: Option<f32>, // Last target written to the ESP, None forces a resend
+    activated_on_target: bool, // Controller::activate_targeted activated it on the current link
+    override_streamed: bool, // manual_override as last reported to the outlet
+    last_target: f32, // Change detection while disconnected
+    command_sender: Option<Sender<EspCommand>>,
+    status_receiver: Option<Receiver<EspStatus>>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl Default for Module {
+    fn default() -> Self {
+        Self::new("L", "Left", if cfg!(windows) { "COM3" } else { "/dev/ttyUSB0" })
+    }
+}
+
+impl Module {
+    pub fn new(short_name: &str, long_name: &str, port: &str) -> Self {
+        Self {
+            short_name: short_name.to_string(),
+            long_name: long_name.to_string(),
+            port: port.to_string(),
+            baud_rate: 115200,
+            protocol: WireProtocol::Ascii,
+            target: 0.0,
+            has_target: false,
+            manual_override: false,
+            connected: false,
+            active: false,
+            console: Console::default(),
+            waveform: None,
+            comfort: None,
+            status_message: format!("ESP {}: Not connected.", short_name),
+            active_protocol: None,
+            corrupt_frames: 0,
+            command_latency: None,
+            clock: ClockSync::default(),
+            skin_temp: None,
+            raw_skin_temp: None,
+            skin_alarm: false,
+            telemetry: None,
+            capabilities: None,
+            serial: None,
+            pid: BTreeMap::new(),
+            pid_unsaved: false,
+            step_response: None,
+            flash: None,
+            sent_target: None,
+            activated_on_target: false,
+            override_streamed: false,
+            last_target: 0.0,
+            command_sender: None,
+            status_receiver: None,
+            thread_handle: None,
+        }
+    }
+
+    // Source name for log messages, e.g. "ESP L"
+    pub fn source(&self) -> String {
+        format!("ESP {}", self.short_name)
+    }
+
+    // Last target written to the ESP on the current link
+    pub fn sent_target(&self) -> Option<f32> {
+        self.sent_target
+    }
+
+    // Decimal places the firmware gets targets with
+    pub fn target_decimals(&self, configured: u8) -> u8 {
+        if self.capabilities.as_ref().is_some_and(Capabilities::float_targets) { configured } else { 0 }
+    }
+
+    // What calibrations are stored under: the serial number, or the port while it is unknown
+    pub fn device_key(&self) -> String {
+        self.serial.clone().unwrap_or_else(|| self.port.trim().to_string())
+    }
+
+    // Gains of both loops, once both were read
+    pub fn pid_gains(&self) -> Option<PidGains> {
+        Some(PidGains { heat: *self.pid.get(&PidLoop::Heat)?, cool: *self.pid.get(&PidLoop::Cool)? })
+    }
+
+    // True while a worker thread exists, i.e. connecting, connected or disconnecting
+    pub fn is_worker_running(&self) -> bool {
+        self.thread_handle.is_some()
+    }
+
+    fn join_worker(&mut self) -> Option<String> {
+        self.command_sender = None;
+        self.status_receiver = None;
+        self.thread_handle
+            .take()
+            .and_then(|handle| handle.join().err())
+            .map(|e| format!("Thread panicked or error on join: {:?}", e))
+    }
+}
+
+pub struct Controller {
+    pub modules: Vec<Module>,
+    pub limits: SafetyLimits,
+    pub skin_limits: SafetyLimits, // See DEFAULT_SKIN_LIMITS, saved with the config
+    pub target_decimals: u8, // See DEFAULT_TARGET_DECIMALS
+    pub is_running: bool,
+    pub telemetry_log: VecDeque<TelemetryLine>,
+    pub waveform_presets: Vec<WaveformPreset>, // Saved with the config
+    pub calibrations: BTreeMap<String, Calibration>, // By Module::device_key, saved with the config
+    pub pid_presets: Vec<PidPreset>, // Saved with the config
+    pub comfort_model: ComfortModel, // Saved with the config
+    pub spatial: SpatialLayout, // Module positions for /TempSense/point, saved with the config
+    pub spatial_sources: BTreeMap<u32, SpatialPoint>, // Current point sources, by source id
+    pub experiment: Option<ExperimentRun>,
+    pub script: Option<Script>, // Maps OSC input to targets instead of /PeltN while loaded
+    pub last_sync: Option<SyncReport>, // Skew of the latest synchronized START/STOP
+    pub outlet_settings: OutletSettings, // Saved with the config
+    sync_run: Option<SyncRun>, // Synchronized START/STOP waiting for the workers' reports
+    outlet: Option<Outlet>, // Telemetry and marker stream, see outlet.rs
+    osc_receiver: Option<Receiver<OscInput>>,
+    raw_capture: bool,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self {
+            modules: vec![
+                Module::new("L", "Left", if cfg!(windows) { "COM3" } else { "/dev/ttyUSB0" }),
+                Module::new("R", "Right", if cfg!(windows) { "COM4" } else { "/dev/ttyUSB1" }),
+            ],
+            limits: SafetyLimits::default(),
+            skin_limits: DEFAULT_SKIN_LIMITS,
+            target_decimals: DEFAULT_TARGET_DECIMALS,
+            is_running: false,
+            telemetry_log: VecDeque::new(),
+            waveform_presets: Vec::new(),
+            calibrations: BTreeMap::new(),
+            pid_presets: Vec::new(),
+            comfort_model: ComfortModel::default(),
+            spatial: SpatialLayout::default(),
+            spatial_sources: BTreeMap::new(),
+            experiment: None,
+            raw_capture: false,
+            script: None,
+            last_sync: None,
+            outlet_settings: OutletSettings::default(),
+            sync_run: None,
+            outlet: None,
+            osc_receiver: None,
+        }
+    }
+}
+
+impl Controller {
+    pub fn set_osc_receiver(&mut self, receiver: Receiver<OscInput>) {
+        self.osc_receiver = Some(receiver);
+    }
+
+    // False once the OSC listener has gone away (or was never attached)
+    pub fn osc_connected(&self) -> bool {
+        self.osc_receiver.is_some()
+    }
+
+    // Clamps to the limits and rounds to the target resolution. None for NaN and infinities.
+    fn clamp_target(&self, source: &str, temp: f32) -> Option<f32> {
+        if !temp.is_finite() {
+            log::warn!(target: source, "Target {} ignored, not a temperature", temp);
+            return None;
+        }
+        let clamped = self.limits.clamp(temp);
+        if clamped != temp {
+            log::warn!(target: source, "Target {}°C outside limits [{}, {}], clamped to {}°C", temp, self.limits.value_min, self.limits.value_max, clamped);
+        }
+        Some(self.limits.quantize(clamped, self.target_decimals))
+    }
+
+    // Target from OSC. Ignored for modules in manual override or driven by a waveform or protocol.
+    pub fn update_pelt_temp(&mut self, id: i8, temp: f32) {
+        let index = match usize::try_from(id) {
+            Ok(index) if index < self.modules.len() => index,
+            _ => {
+                // This is for invalid id
+                log::warn!(target: "APP", "Invalid peltier id: {}. Defaulting to peltier 0", id);
+                0
+            }
+        };
+        let Some(module) = self.modules.get(index) else { return };
+        if self.holds_target(index) {
+            return;
+        }
+        let source = module.source();
+        let Some(temp) = self.clamp_target(&source, temp) else { return };
+        if self.modules[index].comfort.take().is_some() {
+            log::info!(target: &source, "Perceived-temperature mode left, OSC sets the target in °C");
+        }
+        self.modules[index].target = temp;
+        self.modules[index].has_target = true;
+        log::debug!(target: &source, "OSC temp update: {}", temp);
+    }
+
+    // Perceived temperature from OSC, -1 (cold) to +1 (hot). The first one puts the module in
+    // perceived-temperature mode with its current skin temperature as the neutral baseline; an
+    // OSC target in °C ends it. Ignored like update_pelt_temp.
+    pub fn set_sensation(&mut self, id: i8, sensation: f32) {
+        let index = match usize::try_from(id) {
+            Ok(index) if index < self.modules.len() => index,
+            _ => {
+                log::warn!(target: "APP", "Invalid peltier id: {}. Defaulting to peltier 0", id);
+                0
+            }
+        };
+        let Some(module) = self.modules.get(index) else { return };
+        if self.holds_target(index) {
+            return;
+        }
+        let source = module.source();
+        if !sensation.is_finite() {
+            log::warn!(target: &source, "Sensation {} ignored, not a number", sensation);
+            return;
+        }
+        let clamped = sensation.clamp(comfort::SENSATION_MIN, comfort::SENSATION_MAX);
+        if clamped != sensation {
+            log::warn!(target: &source, "Sensation {} outside [-1, 1], clamped to {}", sensation, clamped);
+        }
+        let model = self.comfort_model;
+        let module = &mut self.modules[index];
+        match &mut module.comfort {
+            Some(state) => state.sensation = clamped,
+            None => {
+                let state = ComfortState::new(clamped, module.skin_temp, &model);
+                log::info!(target: &source, "Perceived-temperature mode: neutral is {:.2}°C skin temperature{}", state.baseline, if state.measured { "" } else { " (default until the module reports one)" });
+                module.comfort = Some(state);
+            }
+        }
+        log::debug!(target: &source, "OSC sensation update: {} ({})", clamped, comfort::label(clamped));
+        self.run_comfort();
+    }
+
+    // Leaves perceived-temperature mode, the target stays where it is
+    pub fn stop_comfort(&mut self, index: usize) {
+        let Some(module) = self.modules.get_mut(index) else { return };
+        if module.comfort.take().is_some() {
+            log::info!(target: &module.source(), "Perceived-temperature mode left");
+        }
+    }
+
+    // Takes the current skin temperature as the new neutral baseline, e.g. after the wearer changed
+    pub fn rebaseline_comfort(&mut self, index: usize) -> Result<f32, String> {
+        let Some(module) = self.modules.get_mut(index) else { return Err(format!("No module #{}", index)) };
+        let source = module.source();
+        let Some(skin) = module.skin_temp else { return Err(format!("No skin temperature from {} yet", source)) };
+        let Some(state) = &mut module.comfort else { return Err(format!("{} is not in perceived-temperature mode", source)) };
+        state.baseline = skin;
+        state.measured = true;
+        log::info!(target: &source, "Perceived-temperature baseline set to {:.2}°C", skin);
+        self.run_comfort();
+        Ok(skin)
+    }
+
+    // Recomputes the targets of modules in perceived-temperature mode, which move with the ambient
+    // temperature. Modules in manual override or driven by a waveform or protocol keep theirs.
+    fn run_comfort(&mut self) {
+        for index in 0..self.modules.len() {
+            let driven = self.experiment_drives(&self.modules[index].short_name);
+            let module = &mut self.modules[index];
+            let source = module.source();
+            let Some(state) = &mut module.comfort else { continue };
+            if let (false, Some(skin)) = (state.measured, module.skin_temp) {
+                state.baseline = skin;
+                state.measured = true;
+                log::info!(target: &source, "Perceived-temperature baseline set to the measured {:.2}°C", skin);
+            }
+            if module.manual_override || module.waveform.is_some() || driven {
+                continue;
+            }
+            let ambient = module.telemetry.and_then(|telemetry| telemetry.ambient_temp);
+            let target = self.comfort_model.target(state.sensation, state.baseline, ambient);
+            // Clamped quietly, like waveforms, rather than warning on every tick
+            module.target = self.limits.quantize(target, self.target_decimals);
+            module.has_target = true;
+        }
+    }
+
+    // Target typed in by the user. Returns true when the target changed.
+    pub fn set_manual_target(&mut self, index: usize, temp: f32) -> bool {
+        let Some(module) = self.modules.get(index) else { return false };
+        let source = module.source();
+        let Some(temp) = self.clamp_target(&source, temp) else { return false };
+        if self.modules[index].target == temp {
+            return false;
+        }
+        self.modules[index].target = temp;
+        self.modules[index].has_target = true;
+        log::info!(target: "APP", "Manual override: Peltier {} target directly set to {}°C", index + 1, temp);
+        true
+    }
+
+    // Lets `waveform` drive the module's target until it ends or stop_waveform is called
+    pub fn start_waveform(&mut self, index: usize, name: &str, waveform: Waveform) -> Result<(), String> {
+        let Some(module) = self.modules.get(index) else { return Err(format!("No module #{}", index)) };
+        let source = module.source();
+        if self.experiment_drives(&module.short_name) {
+            return Err(format!("{} is driven by the running protocol", source));
+        }
+        waveform.validate(&self.limits).map_err(|e| format!("Waveform '{}': {}", name, e))?;
+        self.modules[index].waveform = Some(WaveformRun::new(name, waveform));
+        log::info!(target: &source, "Waveform '{}' started ({:?} {}..{}°C, period {}s)", name, waveform.shape, waveform.low, waveform.high, waveform.period_secs);
+        Ok(())
+    }
+
+    pub fn stop_waveform(&mut self, index: usize) {
+        let Some(module) = self.modules.get_mut(index) else { return };
+        if let Some(run) = module.waveform.take() {
+            log::info!(target: &module.source(), "Waveform '{}' stopped after {:.1}s", run.name, run.elapsed().as_secs_f32());
+        }
+    }
+
+    // Moves waveform driven targets forward. Modules in manual override keep their target.
+    fn run_waveforms(&mut self) {
+        for module in self.modules.iter_mut() {
+            let Some(run) = &mut module.waveform else { continue };
+            run.set_paused(module.manual_override);
+            match run.waveform.target_at(run.elapsed()) {
+                Some(_) if module.manual_override => {}
+                // The limits may have changed since the start; clamp quietly rather than every tick
+                Some(target) => {
+                    module.target = self.limits.quantize(target, self.target_decimals);
+                    module.has_target = true;
+                }
+                None => {
+                    let name = std::mem::take(&mut run.name);
+                    module.waveform = None;
+                    log::info!(target: &module.source(), "Waveform '{}' finished", name);
+                }
+            }
+        }
+    }
+
+    // Loads (or with None, unloads) the OSC mapping script
+    pub fn set_script(&mut self, script: Option<Script>) {
+        match (&script, &self.script) {
+            (Some(new), _) => log::info!(target: "SCRIPT", "Mapping script loaded{}", new.path().map(|p| format!(" from {}", p.display())).unwrap_or_default()),
+            (None, Some(_)) => log::info!(target: "SCRIPT", "Mapping script unloaded, OSC /PeltN sets targets directly"),
+            (None, None) => {}
+        }
+        self.script = script;
+    }
+
+    fn run_script(&mut self) {
+        let held: Vec<bool> = (0..self.modules.len()).map(|index| self.holds_target(index)).collect();
+        let Some(script) = &mut self.script else { return };
+        script.reload_if_changed();
+        let inputs: Vec<ModuleInput<'_>> = self.modules.iter().enumerate().map(|(index, module)| ModuleInput {
+            short_name: &module.short_name,
+            target: module.target,
+            telemetry: module.telemetry,
+            held: held[index],
+        }).collect();
+        // Errors are logged by the script and kept for the GUI (Script::error)
+        let Ok(targets) = script.run(&inputs) else { return };
+        for (name, temp) in targets {
+            if let Some(index) = self.modules.iter().position(|module| module.short_name == name) {
+                self.update_pelt_temp(index as i8, temp);
+            }
+        }
+    }
+
+    // Adds, moves or (with intensity 0) removes a point source and sets the targets of the modules
+    // on the spatial layout. They go through update_pelt_temp, so overrides and limits apply.
+    pub fn apply_point(&mut self, point: SpatialPoint) {
+        if point.intensity == 0.0 {
+            self.spatial_sources.remove(&point.source);
+        } else if self.spatial_sources.len() >= spatial::MAX_SOURCES && !self.spatial_sources.contains_key(&point.source) {
+            log::warn!(target: "OSC", "Point source {} ignored, already {} sources", point.source, spatial::MAX_SOURCES);
+            return;
+        } else {
+            self.spatial_sources.insert(point.source, point);
+        }
+        if self.spatial.positions.is_empty() {
+            log::warn!(target: "OSC", "{} received, but no module is placed on the spatial layout", spatial::POINT_ADDRESS);
+            return;
+        }
+        self.update_spatial_targets();
+    }
+
+    // Removes every point source, positioned modules go back to the neutral temperature
+    pub fn clear_points(&mut self) {
+        self.spatial_sources.clear();
+        self.update_spatial_targets();
+    }
+
+    // Recomputes the targets of the positioned modules, e.g. after the layout was edited
+    pub fn update_spatial_targets(&mut self) {
+        for (name, temp) in self.spatial.targets(&self.spatial_sources) {
+            if let Some(index) = self.modules.iter().position(|module| module.short_name == name) {
+                self.update_pelt_temp(index as i8, temp);
+            }
+        }
+    }
+
+    // Calibration applied to the module's skin readings
+    pub fn calibration(&self, index: usize) -> Option<&Calibration> {
+        self.modules.get(index).and_then(|module| self.calibrations.get(&module.device_key()))
+    }
+
+    // Stores (or with None, removes) the calibration of the device behind a module
+    pub fn set_calibration(&mut self, index: usize, calibration: Option<Calibration>) {
+        let Some(module) = self.modules.get(index) else { return };
+        let (source, key) = (module.source(), module.device_key());
+        match calibration {
+            Some(calibration) => {
+                log::info!(target: &source, "Calibration for {} saved: {}", key, calibration.describe());
+                self.calibrations.insert(key, calibration);
+            }
+            None => {
+                if self.calibrations.remove(&key).is_some() {
+                    log::info!(target: &source, "Calibration for {} removed", key);
+                }
+            }
+        }
+    }
+
+    // Asks the firmware for the gains of both loops; the replies end up in Module::pid
+    pub fn read_pid(&mut self, index: usize) -> bool {
+        PidLoop::ALL.into_iter().all(|pid_loop| self.send_command(index, &pid::read_command(pid_loop)))
+    }
+
+    // Writes the gains of both loops and reads them back. They are lost on restart unless committed.
+    pub fn write_pid(&mut self, index: usize, gains: &PidGains) -> Result<(), String> {
+        let Some(module) = self.modules.get(index) else { return Err(format!("No module #{}", index)) };
+        if !module.connected {
+            return Err(format!("{} not connected", module.source()));
+        }
+        let problems = gains.problems();
+        if !problems.is_empty() {
+            return Err(format!("Invalid gains: {}", problems.join(", ")));
+        }
+        for pid_loop in PidLoop::ALL {
+            if !self.send_command(index, &pid::write_command(pid_loop, gains.get(pid_loop))) {
+                return Err(format!("Failed to write the {} gains", pid_loop.name()));
+            }
+        }
+        self.modules[index].pid_unsaved = true;
+        self.read_pid(index);
+        Ok(())
+    }
+
+    // Stores the gains in the firmware's flash so they survive a restart
+    pub fn commit_pid(&mut self, index: usize) -> bool {
+        self.send_command(index, pid::PID_SAVE)
+    }
+
+    // Puts the module in manual override and steps its target to `target`, recording the response
+    // from telemetry for `duration`
+    pub fn start_step_test(&mut self, index: usize, target: f32, duration: Duration) -> Result<(), String> {
+        let Some(module) = self.modules.get(index) else { return Err(format!("No module #{}", index)) };
+        let source = module.source();
+        if self.experiment_drives(&module.short_name) {
+            return Err(format!("{} is driven by the running protocol", source));
+        }
+        if !module.active {
+            return Err(format!("{} is not active, press START first", source));
+        }
+        let Some(from) = module.telemetry.and_then(|telemetry| telemetry.exterior_temp) else {
+            return Err(format!("No telemetry from {} yet", source));
+        };
+        let Some(target) = self.clamp_target(&source, target) else { return Err(format!("{} is not a temperature", target)) };
+        self.stop_waveform(index);
+        let module = &mut self.modules[index];
+        module.manual_override = true;
+        module.target = target;
+        module.has_target = true;
+        module.step_response = Some(StepResponse::new(from, target, duration));
+        log::info!(target: &source, "Step test: {:.2}°C -> {}°C for {:.0}s (manual override on)", from, target, duration.as_secs_f32());
+        Ok(())
+    }
+
+    // Switches a module off while its skin temperature is outside the skin limits; START and
+    // reconnects leave it off meanwhile. It stays off once the temperature is back; START
+    // activates it again.
+    fn check_skin_temp(&mut self, index: usize) {
+        let limits = self.skin_limits;
+        let module = &mut self.modules[index];
+        let Some(skin) = module.skin_temp else { return };
+        let outside = limits.clamp(skin) != skin;
+        let source = module.source();
+        if outside && !module.skin_alarm {
+            module.skin_alarm = true;
+            if let Some(outlet) = &self.outlet {
+                outlet.push(OutletEvent::marker(outlet::host_now_us(), "SKIN_ALARM", Some(&module.short_name)));
+            }
+            log::error!(target: &source, "Skin temperature {:.2}°C is outside the skin limits [{}, {}], switching the module off", skin, limits.value_min, limits.value_max);
+        } else if !outside && module.skin_alarm {
+            module.skin_alarm = false;
+            if let Some(outlet) = &self.outlet {
+                outlet.push(OutletEvent::marker(outlet::host_now_us(), "SKIN_OK", Some(&module.short_name)));
+            }
+            log::warn!(target: &source, "Skin temperature {:.2}°C is back within the skin limits; press START to reactivate", skin);
+        }
+        if module.skin_alarm && module.active {
+            if let Err(e) = Self::send_active(module, false) {
+                log::error!(target: &source, "Failed to send 'tempActive 0': {}", e);
+            }
+        }
+    }
+
+    // Whether OSC and script targets are ignored: manual override, or a waveform or protocol drives it
+    fn holds_target(&self, index: usize) -> bool {
+        let module = &self.modules[index];
+        module.manual_override || module.waveform.is_some() || self.experiment_drives(&module.short_name)
+    }
+
+    fn experiment_drives(&self, short_name: &str) -> bool {
+        self.experiment.as_ref().is_some_and(|run| run.protocol.modules().iter().any(|name| name == short_name))
+    }
+
+    // Runs `protocol` from the next tick on. Waveforms on the modules it drives are stopped.
+    pub fn start_experiment(&mut self, protocol: Protocol) -> Result<(), String> {
+        if let Some(run) = &self.experiment {
+            return Err(format!("Protocol '{}' is still running", run.protocol.name));
+        }
+        let names: Vec<String> = self.modules.iter().map(|module| module.short_name.clone()).collect();
+        protocol.validate(&names, &self.limits)?;
+        let driven = protocol.modules();
+        for index in 0..self.modules.len() {
+            if driven.contains(&self.modules[index].short_name) {
+                self.stop_waveform(index);
+            }
+        }
+        let seed = protocol.seed.unwrap_or_else(ExperimentRun::random_seed);
+        let run = ExperimentRun::new(protocol, seed);
+        log::info!(target: "EXPERIMENT", "Protocol '{}' started: {} steps, {:.0}s, seed {}", run.protocol.name, run.schedule.len(), run.total_duration().as_secs_f32(), seed);
+        self.experiment = Some(run);
+        Ok(())
+    }
+
+    pub fn pause_experiment(&mut self) {
+        if let Some(run) = &mut self.experiment {
+            run.pause();
+        }
+    }
+
+    pub fn resume_experiment(&mut self) {
+        if let Some(run) = &mut self.experiment {
+            run.resume();
+        }
+    }
+
+    // Targets stay where the protocol left them
+    pub fn abort_experiment(&mut self) {
+        if let Some(mut run) = self.experiment.take() {
+            run.abort();
+        }
+    }
+
+    fn run_experiment(&mut self) {
+        let Some(run) = &mut self.experiment else { return };
+        match run.poll(std::time::Instant::now()) {
+            Some(ExperimentEvent::Step(index)) => {
+                let targets = run.schedule[index].targets.clone();
+                self.stream_marker(outlet::host_now_us(), &format!("STEP {}", index), None);
+                for (name, temp) in targets {
+                    let Some(index) = self.modules.iter().position(|module| module.short_name == name) else { continue };
+                    let source = self.modules[index].source();
+                    if self.modules[index].manual_override {
+                        log::warn!(target: &source, "Manual override: protocol target {}°C not applied", temp);
+                        continue;
+                    }
+                    if let Some(temp) = self.clamp_target(&source, temp) {
+                        self.modules[index].target = temp;
+                        self.modules[index].has_target = true;
+                    }
+                }
+            }
+            Some(ExperimentEvent::Finished) => {
+                self.experiment = None;
+                self.stream_marker(outlet::host_now_us(), "EXPERIMENT_END", None);
+            }
+            None => {}
+        }
+    }
+
+    // Queues a raw command for one module. Returns false if it could not be queued.
+    pub fn send_command(&mut self, index: usize, command: &str) -> bool {
+        let Some(module) = self.modules.get(index) else { return false };
+        let source = module.source();
+        let result = match &module.command_sender {
+            Some(sender) if module.connected => sender.send(EspCommand::SendCommand(command.to_string())).map_err(|e| format!("{}", e)),
+            _ => Err(format!("{} not connected", source)),
+        };
+        match result {
+            Ok(()) => {
+                log::info!(target: &source, "Sent command: {}", command);
+                true
+            }
+            Err(e) => {
+                log::warn!(target: &source, "Failed to send '{}': {}", command, e);
+                false
+            }
+        }
+    }
+
+    // Console input, written to the link without line ending or framing
+    pub fn send_raw(&mut self, index: usize, bytes: Vec<u8>) -> bool {
+        let Some(module) = self.modules.get(index) else { return false };
+        let source = module.source();
+        let result = match &module.command_sender {
+            Some(sender) if module.connected => sender.send(EspCommand::SendRaw(bytes)).map_err(|e| format!("{}", e)),
+            _ => Err(format!("{} not connected", source)),
+        };
+        if let Err(e) = &result {
+            log::warn!(target: &source, "Failed to send console input: {}", e);
+        }
+        result.is_ok()
+    }
+
+    // Whether the workers report the raw RX/TX stream to the module consoles
+    pub fn set_raw_capture(&mut self, enabled: bool) {
+        if self.raw_capture == enabled {
+            return;
+        }
+        self.raw_capture = enabled;
+        for module in self.modules.iter() {
+            if let Some(sender) = &module.command_sender {
+                sender.send(EspCommand::CaptureRaw(enabled)).ok();
+            }
+        }
+    }
+
+    pub fn raw_capture(&self) -> bool {
+        self.raw_capture
+    }
+
+    pub fn connect(&mut self, index: usize) {
+        let Some(module) = self.modules.get_mut(index) else { return };
+        if module.is_worker_running() {
+            return;
+        }
+        if module.flash.as_ref().is_some_and(FlashJob::is_running) {
+            module.status_message = format!("{}: Firmware update in progress.", module.source());
+            log::warn!(target: &module.source(), "Cannot connect while the firmware is being updated");
+            return;
+        }
+        let (command_s, command_r) = mpsc::channel();
+        let (status_s, status_r) = mpsc::channel();
+        let descriptor = TransportDescriptor::from_port_name(&module.port, module.baud_rate);
+        let connect_msg = format!("Attempting to connect to {} @ {}...", module.source(), descriptor);
+
+        module.thread_handle = Some(thread::spawn(move || {
+            esp_worker_thread(command_r, status_s);
+        }));
+        if self.raw_capture {
+            command_s.send(EspCommand::CaptureRaw(true)).ok();
+        }
+        if let Err(e) = command_s.send(EspCommand::Connect(descriptor, module.protocol)) {
+            let source = module.source();
+            module.status_message = format!("{}: Failed to send connect cmd: {}", source, e);
+            module.join_worker();
+            log::error!(target: &source, "Failed to send connect cmd: {}", e);
+            return;
+        }
+        module.command_sender = Some(command_s);
+        module.status_receiver = Some(status_r);
+        log::info!(target: &module.source(), "{}", connect_msg);
+        module.status_message = connect_msg;
+    }
+
+    pub fn disconnect(&mut self, index: usize) {
+        let Some(module) = self.modules.get_mut(index) else { return };
+        let source = module.source();
+        let Some(sender) = &module.command_sender else { return };
+        if let Err(e) = sender.send(EspCommand::Disconnect) {
+            module.status_message = format!("{}: Failed to send disconnect cmd: {}", source, e);
+            log::error!(target: &source, "Failed to send disconnect cmd: {}", e);
+        } else {
+            module.status_message = format!("{}: Disconnect command sent.", source);
+            log::info!(target: &source, "Disconnect command sent.");
+        }
+    }
+
+    // Writes a firmware image through the ESP's bootloader, in the background (see flasher.rs).
+    // The module has to be disconnected, the flasher opens its port itself.
+    pub fn start_flash(&mut self, index: usize, image: Vec<u8>, options: FlashOptions) -> Result<(), String> {
+        let Some(module) = self.modules.get_mut(index) else { return Err(format!("No module #{}", index)) };
+        let source = module.source();
+        if module.is_worker_running() {
+            return Err(format!("Disconnect {} before updating its firmware", source));
+        }
+        if module.flash.as_ref().is_some_and(FlashJob::is_running) {
+            return Err(format!("{} is already being updated", source));
+        }
+        let job = FlashJob::spawn(&module.port, module.baud_rate, image, options)?;
+        log::info!(target: &source, "Firmware update started: {}", job.image.describe());
+        module.flash = Some(job);
+        Ok(())
+    }
+
+    fn poll_flash(&mut self, index: usize) -> bool {
+        let module = &mut self.modules[index];
+        let source = module.source();
+        let Some(job) = &mut module.flash else { return false };
+        let mut stage = job.progress.map(|progress| progress.stage);
+        let events = job.poll();
+        for event in &events {
+            match event {
+                // Once per stage, not for every block written
+                FlashEvent::Progress(progress) if Some(progress.stage) != stage => {
+                    stage = Some(progress.stage);
+                    log::info!(target: &source, "Firmware update: {}", progress.describe());
+                }
+                FlashEvent::Progress(_) => {}
+                FlashEvent::Finished(Ok(report)) => {
+                    log::info!(target: &source, "Firmware updated: {} bytes in {:.1}s{}", report.bytes, report.elapsed.as_secs_f32(), if report.verified { ", verified" } else { ", not verified" });
+                    module.status_message = format!("{}: Firmware updated.", source);
+                }
+                FlashEvent::Finished(Err(e)) => {
+                    log::error!(target: &source, "Firmware update failed: {}", e);
+                    module.status_message = format!("{}: Firmware update failed.", source);
+                }
+            }
+        }
+        !events.is_empty()
+    }
+
+    // Sends tempActive to one module and tracks the result in Module::active.
+    // A module with a skin alarm is not activated.
+    fn send_active(module: &mut Module, active: bool) -> Result<(), String> {
+        if active && module.skin_alarm {
+            return Err(SKIN_ALARM_REFUSAL.to_string());
+        }
+        let command = if active { "tempActive 1" } else { "tempActive 0" };
+        match &module.command_sender {
+            Some(sender) if module.connected => {
+                sender.send(EspCommand::SendCommand(command.to_string())).map_err(|e| e.to_string())?;
+                module.active = active;
+                Ok(())
+            }
+            _ => Err("not connected".to_string()),
+        }
+    }
+
+    // Like send_active, but the worker holds the command until `due` minus the time it takes to
+    // reach the device, measured by the clock sync where it runs (see sync.rs). Returns that time.
+    fn schedule_active(module: &mut Module, active: bool, due: Instant) -> Result<Duration, String> {
+        if active && module.skin_alarm {
+            return Err(SKIN_ALARM_REFUSAL.to_string());
+        }
+        let command = if active { "tempActive 1" } else { "tempActive 0" };
+        let protocol = module.active_protocol.unwrap_or(WireProtocol::Ascii);
+        let delay = sync::link_delay(&module.clock, &module.port, module.baud_rate, protocol, command);
+        match &module.command_sender {
+            Some(sender) if module.connected => {
+                sender.send(EspCommand::SendAt(command.to_string(), due - delay)).map_err(|e| e.to_string())?;
+                module.active = active;
+                Ok(delay)
+            }
+            _ => Err("not connected".to_string()),
+        }
+    }
+
+    // With more than one module connected, tempActive is scheduled for the same instant on all of
+    // them and the skew achieved is reported once the workers have written it
+    fn set_active(&mut self, active: bool) {
+        let label = if active { "START" } else { "STOP" };
+        self.finish_sync(true);
+        let synchronized = self.modules.iter().filter(|module| module.connected).count() > 1;
+        let mut run = SyncRun::new(label, Instant::now() + sync::SYNC_LEAD);
+        for module in self.modules.iter_mut() {
+            let source = module.source();
+            let result = if synchronized {
+                Self::schedule_active(module, active, run.due).map(|delay| run.expect(&module.short_name, delay))
+            } else {
+                Self::send_active(module, active)
+            };
+            let message = match result {
+                Ok(()) => {
+                    log::info!(target: &source, "{} command sent.", label);
+                    format!("{} command sent.", label)
+                }
+                Err(e) => {
+                    log::warn!(target: &source, "Cannot {}, {}.", label, e);
+                    format!("Cannot {}, {}.", label, e)
+                }
+            };
+            module.status_message = format!("{}: {}", source, message);
+        }
+        let at = if synchronized { run.due } else { Instant::now() };
+        self.stream_marker(clock::host_micros(at) as i64, label, None);
+        if run.expected() > 1 {
+            self.sync_run = Some(run);
+        }
+    }
+
+    // Reports the pending synchronized command once every worker wrote it, or with `force`
+    // or after a timeout, with whatever was reported so far
+    fn finish_sync(&mut self, force: bool) {
+        let Some(run) = &self.sync_run else { return };
+        if !(force || run.is_complete() || run.is_overdue(Instant::now())) {
+            return;
+        }
+        let report = run.report();
+        if report.missing.is_empty() {
+            log::info!(target: "SYNC", "{}", report.describe());
+        } else {
+            log::warn!(target: "SYNC", "{}", report.describe());
+        }
+        self.last_sync = Some(report);
+        self.sync_run = None;
+    }
+
+    pub fn start(&mut self) {
+        self.is_running = true;
+        self.set_active(true);
+    }
+
+    pub fn stop(&mut self) {
+        self.is_running = false;
+        self.set_active(false);
+    }
+
+    // Activates each connected module once per link as soon as it has a target, instead of all
+    // of them on START: a module nothing asked a temperature of yet would be driven to the
+    // lower limit. Used by the headless bridge without --start.
+    pub fn activate_targeted(&mut self) {
+        for module in self.modules.iter_mut() {
+            if !module.has_target || module.active_protocol.is_none() || module.activated_on_target {
+                continue;
+            }
+            module.activated_on_target = true;
+            let source = module.source();
+            match Self::send_active(module, true) {
+                Ok(()) => log::info!(target: &source, "Target {}°C received, activating.", module.target),
+                Err(e) => log::warn!(target: &source, "Failed to send 'tempActive 1': {}", e),
+            }
+        }
+    }
+
+    // Processes OSC input and ESP status, and sends changed targets.
+    // Returns true if anything happened (the GUI uses this to decide whether to repaint).
+    pub fn tick(&mut self) -> bool {
+        let mut activity = false;
+
+        // Process ALL available OSC messages
+        let mut osc_inputs = Vec::new();
+        if let Some(receiver) = &self.osc_receiver {
+            loop {
+                match receiver.try_recv() {
+                    Ok(input) => osc_inputs.push(input),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.osc_receiver = None;
+                        break;
+                    }
+                }
+            }
+        }
+        for input in osc_inputs {
+            activity = true;
+            match (&mut self.script, input) {
+                (Some(script), OscInput::Target(id, temp)) => script.set_osc(&format!("/Pelt{}", i16::from(id) + 1), temp),
+                (Some(script), OscInput::Sensation(id, value)) => script.set_osc(&format!("/Sensation{}", i16::from(id) + 1), value),
+                (Some(script), OscInput::Point(point)) => {
+                    // The script sees the latest point as /TempSense/point/x, .../y, .../intensity and .../radius
+                    for (field, value) in [("x", point.x), ("y", point.y), ("intensity", point.intensity), ("radius", point.radius)] {
+                        script.set_osc(&format!("{}/{}", spatial::POINT_ADDRESS, field), value);
+                    }
+                }
+                (Some(script), OscInput::Value(address, value)) => script.set_osc(&address, value),
+                (None, OscInput::Target(id, temp)) => self.update_pelt_temp(id, temp),
+                (None, OscInput::Sensation(id, value)) => self.set_sensation(id, value),
+                (None, OscInput::Point(point)) => self.apply_point(point),
+                (None, OscInput::Value(address, value)) => {
+                    log::warn!(target: "OSC", "Address '{}' did not match specific /PeltX. Defaulting id to 0.", address);
+                    self.update_pelt_temp(0, value * 100.0);
+                }
+            }
+        }
+
+        self.run_script();
+        self.run_comfort();
+        self.run_waveforms();
+        self.run_experiment();
+        for index in 0..self.modules.len() {
+            activity |= self.process_module_status(index);
+            activity |= self.poll_flash(index);
+            self.send_pending_target(index);
+            self.stream_override(index);
+        }
+        self.finish_sync(false);
+        activity
+    }
+
+    // Starts streaming on outlet_settings.address, replacing a running outlet. Returns the address
+    // listened on.
+    pub fn start_outlet(&mut self) -> Result<std::net::SocketAddr, String> {
+        self.outlet = None; // Frees the address first
+        let modules = self.modules.iter().map(|module| module.short_name.clone()).collect();
+        let outlet = Outlet::bind(&self.outlet_settings.address, modules)?;
+        let address = outlet.local_addr();
+        self.outlet = Some(outlet);
+        Ok(address)
+    }
+
+    pub fn stop_outlet(&mut self) {
+        self.outlet = None;
+    }
+
+    pub fn outlet(&self) -> Option<&Outlet> {
+        self.outlet.as_ref()
+    }
+
+    fn stream_marker(&self, host_us: i64, marker: &str, module: Option<&str>) {
+        if let Some(outlet) = &self.outlet {
+            outlet.push(OutletEvent::marker(host_us, marker, module));
+        }
+    }
+
+    // Manual override can be switched from anywhere (GUI, step tests), so changes are picked up here
+    fn stream_override(&mut self, index: usize) {
+        let module = &mut self.modules[index];
+        if module.manual_override == module.override_streamed {
+            return;
+        }
+        module.override_streamed = module.manual_override;
+        if let Some(outlet) = &self.outlet {
+            let marker = if module.manual_override { "OVERRIDE_ON" } else { "OVERRIDE_OFF" };
+            outlet.push(OutletEvent::marker(outlet::host_now_us(), marker, Some(&module.short_name)));
+        }
+    }
+
+    fn send_pending_target(&mut self, index: usize) {
+        let module = &mut self.modules[index];
+        let source = module.source();
+        let decimals = module.target_decimals(self.target_decimals);
+        let target = self.limits.quantize(module.target, decimals);
+        if module.connected && module.sent_target != Some(target) {
+            module.sent_target = Some(target);
+            module.last_target = module.target;
+            self.send_command(index, &format_target(target, decimals));
+        } else if !module.connected && module.target != module.last_target { // only log if temp changed
+            module.last_target = module.target;
+            module.status_message = format!("{}: Not connected.", source);
+            log::warn!(target: &source, "Attempted to send command while {} not connected.", source);
+        }
+    }
+
+    fn process_module_status(&mut self, index: usize) -> bool {
+        let Some(receiver) = self.modules[index].status_receiver.take() else { return false };
+        let source = self.modules[index].source();
+        let mut activity = false;
+        let mut worker_gone = false;
+
+        loop {
+            let status = match receiver.try_recv() {
+                Ok(status) => status,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    worker_gone = true;
+                    break;
+                }
+            };
+            activity = true;
+            match status {
+                EspStatus::Connected => {
+                    let module = &mut self.modules[index];
+                    module.connected = true;
+                    module.capabilities = None;
+                    module.serial = transport::usb_serial_number(&module.port);
+                    module.corrupt_frames = 0;
+                    module.clock = ClockSync::default();
+                    module.sent_target = None; // Send the current target once the link is up
+                    module.activated_on_target = false;
+                    module.status_message = format!("{} Connected.", source);
+                    log::info!(target: &source, "Connected.");
+                }
+                EspStatus::ProtocolSelected(protocol) => {
+                    self.modules[index].active_protocol = Some(protocol);
+                    log::info!(target: &source, "Using {:?} protocol.", protocol);
+                    // Whole degree targets are sent until the firmware reports float support
+                    self.send_command(index, CAPABILITIES_REQUEST);
+                    self.send_command(index, SERIAL_REQUEST);
+                    if let Some(sender) = &self.modules[index].command_sender {
+                        sender.send(EspCommand::ClockSync(true)).ok();
+                    }
+                    // A module (re)connecting while the system runs joins in
+                    if self.is_running {
+                        match Self::send_active(&mut self.modules[index], true) {
+                            Ok(()) => log::info!(target: &source, "Sent command: tempActive 1"),
+                            Err(e) => log::warn!(target: &source, "Failed to send 'tempActive 1': {}", e),
+                        }
+                    }
+                }
+                EspStatus::CommandLatency(latency) => {
+                    self.modules[index].command_latency = Some(latency);
+                }
+                EspStatus::ScheduledSent(sent) => {
+                    log::debug!(target: &source, "Sent command: {} (scheduled)", sent.command);
+                    if let Some(run) = &mut self.sync_run {
+                        run.record(&self.modules[index].short_name, sent.written);
+                    }
+                }
+                EspStatus::ClockSample(sample) => {
+                    let clock = &mut self.modules[index].clock;
+                    clock.add(sample);
+                    if clock.rtt.count == 1 {
+                        log::info!(target: &source, "Device clock synchronized: offset {:.3}s, round trip {:.2} ms", sample.offset_us() as f64 / 1e6, sample.rtt_us() as f64 / 1000.0);
+                    }
+                }
+                EspStatus::ClockUnsupported => {
+                    self.modules[index].clock.supported = Some(false);
+                    log::info!(target: &source, "Firmware does not support clock sync, telemetry is timestamped on arrival");
+                }
+                EspStatus::Raw(chunk) => self.modules[index].console.push(chunk),
+                EspStatus::CorruptFrame(total, reason) => {
+                    self.modules[index].corrupt_frames = total;
+                    log::warn!(target: &source, "Dropped frame ({} corrupt or unexpected so far): {}", total, reason);
+                }
+                EspStatus::Disconnected(reason) => {
+                    let msg = reason.unwrap_or_else(|| "Disconnected by worker.".to_string());
+                    log::info!(target: &source, "{}", msg);
+                    self.modules[index].status_message = format!("{}: {}", source, msg);
+                    worker_gone = true;
+                }
+                EspStatus::Error(err_msg) => {
+                    log::error!(target: &source, "{}", err_msg);
+                    self.modules[index].status_message = format!("{}: Error: {}", source, err_msg);
+                }
+                EspStatus::Message(msg) => {
+                    self.parse_esp_message_and_update_state(index, &msg);
+                }
+            }
+        }
+
+        if worker_gone {
+            let module = &mut self.modules[index];
+            module.connected = false;
+            module.active = false;
+            module.active_protocol = None;
+            module.capabilities = None;
+            module.serial = None;
+            module.pid.clear();
+            module.pid_unsaved = false;
+            module.command_latency = None;
+            module.clock = ClockSync::default();
+            if let Some(e) = module.join_worker() {
+                log::error!(target: &source, "{}", e);
+            }
+        } else {
+            self.modules[index].status_receiver = Some(receiver);
+        }
+        activity
+    }
+
+    // Telemetry goes to telemetry_log, everything else the device says is an event
+    fn parse_esp_message_and_update_state(&mut self, index: usize, msg: &str) {
+        let source = self.modules[index].source();
+        for line in msg.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match Telemetry::parse(line) {
+                Some(Ok(mut telemetry)) => {
+                    let calibration = self.calibrations.get(&self.modules[index].device_key());
+                    let module = &mut self.modules[index];
+                    if let Some(raw) = telemetry.skin_temp_smoothed {
+                        let skin = calibration.as_ref().map_or(raw, |calibration| calibration.apply(raw));
+                        telemetry.skin_temp_smoothed = Some(skin);
+                        module.raw_skin_temp = Some(raw);
+                        module.skin_temp = Some(skin);
+                    }
+                    module.telemetry = Some(telemetry);
+                    let host_us = telemetry.device_us.and_then(|device_us| module.clock.to_host_us(device_us));
+                    if let Some(step) = module.step_response.as_mut().filter(|step| !step.is_finished()) {
+                        step.record(&telemetry);
+                        if step.is_finished() {
+                            match step.metrics() {
+                                Some(metrics) => log::info!(target: &source, "Step test finished: rise time {}, overshoot {:.2}°C, settling time {}",
+                                    seconds(metrics.rise_time), metrics.overshoot, seconds(metrics.settling_time)),
+                                None => log::info!(target: &source, "Step test finished, the step was too small to measure"),
+                            }
+                        }
+                    }
+                    self.telemetry_log.push_back(TelemetryLine {
+                        timestamp: host_us.map_or_else(chrono::Local::now, clock::host_datetime),
+                        source: source.clone(),
+                        line: line.to_string(),
+                        calibrated_skin: telemetry.skin_temp_smoothed.filter(|_| calibration.is_some()),
+                        device_time: host_us.is_some(),
+                    });
+                    if self.telemetry_log.len() > MAX_TELEMETRY_LINES {
+                        self.telemetry_log.pop_front();
+                    }
+                    if let Some(outlet) = &self.outlet {
+                        let module = &self.modules[index];
+                        let at = host_us.unwrap_or_else(outlet::host_now_us);
+                        outlet.push(OutletEvent::sample(at, &module.short_name, module.target, telemetry.target_temp, telemetry.skin_temp_smoothed, host_us.is_some()));
+                    }
+                    self.check_skin_temp(index);
+                }
+                Some(Err(e)) => log::warn!(target: &source, "{}", e),
+                None => self.parse_device_line(index, line),
+            }
+        }
+    }
+
+    // Replies and other device output
+    fn parse_device_line(&mut self, index: usize, line: &str) {
+        let source = self.modules[index].source();
+        if let Some(capabilities) = Capabilities::parse(line) {
+            self.set_capabilities(index, capabilities);
+        } else if let Some(serial) = parse_serial(line) {
+            let calibrated = self.calibrations.contains_key(&serial);
+            log::info!(target: &source, "Device serial number {}{}", serial, if calibrated { ", calibration applied" } else { ", not calibrated" });
+            self.modules[index].serial = Some(serial);
+        } else if let Some(reply) = pid::parse_reply(line) {
+            match reply {
+                Ok((pid_loop, settings)) => {
+                    log::info!(target: &source, "{} PID: {}", pid_loop.name(), settings.to_fields());
+                    self.modules[index].pid.insert(pid_loop, settings);
+                }
+                Err(e) => log::warn!(target: &source, "{}", e),
+            }
+        } else if line == format!("OK {}", pid::PID_SAVE) {
+            log::info!(target: &source, "PID gains committed to flash");
+            self.modules[index].pid_unsaved = false;
+        } else if line.starts_with("ERR") && line.contains(pid::PID_REQUEST) {
+            log::warn!(target: &source, "Firmware does not support remote PID tuning");
+        } else if line.starts_with("ERR") && (line.contains(CAPABILITIES_REQUEST) || line.contains(SERIAL_REQUEST)) {
+            // Older firmware: whole degree targets, calibration by USB serial number or port
+            log::info!(target: &source, "Firmware does not support {}", if line.contains(CAPABILITIES_REQUEST) { CAPABILITIES_REQUEST } else { SERIAL_REQUEST });
+        } else if line.starts_with("ERR") {
+            log::warn!(target: &source, "Device: {}", line);
+        } else {
+            log::debug!(target: &source, "Device: {}", line);
+        }
+    }
+
+    fn set_capabilities(&mut self, index: usize, capabilities: Capabilities) {
+        let module = &mut self.modules[index];
+        let source = module.source();
+        if capabilities.float_targets() {
+            log::info!(target: &source, "Firmware accepts sub-degree targets ({})", capabilities);
+            module.sent_target = None; // Resend the target with full resolution
+        } else {
+            log::info!(target: &source, "Firmware only accepts whole degree targets ({})", capabilities);
+        }
+        module.capabilities = Some(capabilities);
+    }
+
+    // Deactivates connected modules, then stops and joins every worker thread.
+    // Commands are handled in order by the workers, so tempActive 0 is on the wire before they stop.
+    pub fn disconnect_all(&mut self) {
+        self.is_running = false;
+        for index in 0..self.modules.len() {
+            let module = &mut self.modules[index];
+            let source = module.source();
+            if let Some(sender) = &module.command_sender {
+                if module.connected {
+                    let _ = sender.send(EspCommand::SendCommand("tempActive 0".to_string()));
+                }
+                // Attempt to send StopThread, ignore error if channel already closed (e.g., worker already exited)
+                let _ = sender.send(EspCommand::StopThread);
+            }
+            module.connected = false;
+            module.active = false;
+            module.active_protocol = None;
+            module.capabilities = None;
+            module.serial = None;
+            module.pid.clear();
+            module.pid_unsaved = false;
+            module.command_latency = None;
+            module.clock = ClockSync::default();
+            module.status_message = format!("{}: Not connected.", source);
+            if let Some(e) = module.join_worker() {
+                log::error!(target: &source, "{}", e);
+            }
+        }
+    }
+
+    // Stops every worker thread when the application exits
+    pub fn shutdown(&mut self) {
+        log::info!(target: "APP", "Application exiting. Stopping ESP workers.");
+        self.disconnect_all();
+    }
+}
+
+// "1.2s", or "-" when not reached
+fn seconds(value: Option<f32>) -> String {
+    value.map_or("-".to_string(), |secs| format!("{:.1}s", secs))
+}
@@ -1,17 +1,66 @@
 // src/esp_comm.rs
 
 use std::io::{self, Write, Read};
-use std::sync::mpsc::{Sender, Receiver, TryRecvError};
-use std::thread;
-use std::time::Duration;
-use serialport::SerialPort;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub mod bootloader_sim;
+pub mod clock;
+pub mod flasher;
+pub mod framing;
+pub mod pid_gains;
+pub mod sim;
+pub mod telemetry;
+pub mod transport;
+
+use clock::ClockSample;
+use framing::{Frame, FrameDecoder, FrameError, FrameType};
+use transport::{Transport, TransportDescriptor};
+
+// How long to wait for the firmware to acknowledge binary framing before falling back to ASCII
+const PROTOCOL_NEGOTIATION_TIMEOUT: Duration = Duration::from_millis(750);
+const PROTOCOL_REQUEST: &str = "setProto bin";
+const PROTOCOL_ACK: &str = "PROTO:BIN";
+// Keepalive pings are sent with the normal command encoding; the device is considered gone
+// after this many keepalive intervals without receiving anything.
+const KEEPALIVE_COMMAND: &str = "PING";
+const KEEPALIVE_MAX_MISSED: u32 = 3;
+// Reads block in their own thread; this only bounds how long closing the link can take
+const READER_POLL_TIMEOUT: Duration = Duration::from_millis(200);
+// Sent by the controller once the link is up. Firmware that knows it replies "CAPS:" followed by
+// a comma separated list of features; older firmware rejects it or stays silent.
+pub const CAPABILITIES_REQUEST: &str = "getCaps";
+const CAPABILITIES_PREFIX: &str = "CAPS:";
+pub const CAPABILITY_FLOAT_TARGETS: &str = "FLOAT_TEMP"; // setTemp accepts decimals ("setTemp 20.5")
+// Sent along with the capability request; the reply is "SERIAL:" and the board's serial number
+pub const SERIAL_REQUEST: &str = "getSerial";
+const SERIAL_PREFIX: &str = "SERIAL:";
+// ASCII input is passed on a whole line at a time; a longer run without a newline is passed on as is
+const MAX_LINE_LENGTH: usize = 4096;
+// Scheduling is meant for synchronized START/STOP; commands further ahead are refused
+pub const MAX_SCHEDULE_AHEAD: Duration = Duration::from_secs(1);
+
+// Wire protocol used on the ESP link. Binary is only a request: it is negotiated on connect
+// and the worker falls back to ASCII when the firmware does not acknowledge it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum WireProtocol {
+    Ascii,  // Newline terminated text, as understood by all firmware versions
+    Binary, // SLIP framed, typed and CRC16 protected (see framing.rs)
+}
 
 // Commands that can be sent from the GUI thread to the ESP worker thread
 #[derive(Debug)]
 pub enum EspCommand {
-    Connect(String, u32), // port_name, baud_rate
+    Connect(TransportDescriptor, WireProtocol), // where to connect, requested protocol
     Disconnect,
     SendCommand(String),
+    SendAt(String, Instant), // Command held back until the instant, see sync.rs. Other commands still go out meanwhile.
+    SendRaw(Vec<u8>),    // Written as is, without line ending or framing (console)
+    CaptureRaw(bool),    // Report every chunk read and written as EspStatus::Raw
+    ClockSync(bool),     // Ping the device regularly for clock samples, see clock.rs
     StopThread,          // To gracefully shut down the thread
 }
 
@@ -22,63 +71,473 @@ pub enum EspStatus {
     Disconnected(Option<String>), // Optional message for why (e.g., user action, error)
     Error(String),
     Message(String), // For data received from ESP or general info
+    ProtocolSelected(WireProtocol), // Result of the protocol negotiation after connecting
+    CorruptFrame(u32, String), // Total corrupt or unexpected frames on this link so far, reason for the latest one
+    CommandLatency(CommandLatency), // Updated after every command written to the link
+    ScheduledSent(ScheduledSend), // A SendAt command was written
+    Raw(RawChunk), // Only while raw capture is on, see EspCommand::CaptureRaw
+    ClockSample(ClockSample), // Reply to a clock ping, see EspCommand::ClockSync
+    ClockUnsupported, // The firmware answered a clock ping without its clock; pinging stopped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawDirection {
+    Rx,
+    Tx,
+}
+
+// Bytes as they went over the link, stamped by the worker
+#[derive(Debug, Clone)]
+pub struct RawChunk {
+    pub direction: RawDirection,
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub bytes: Vec<u8>,
+}
+
+impl RawChunk {
+    fn now(direction: RawDirection, bytes: &[u8]) -> Self {
+        Self { direction, timestamp: chrono::Local::now(), bytes: bytes.to_vec() }
+    }
+}
+
+// Features the firmware reported in reply to CAPABILITIES_REQUEST
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(Vec<String>);
+
+impl Capabilities {
+    // Parses a "CAPS:FLOAT_TEMP,..." reply, None for any other line
+    pub fn parse(line: &str) -> Option<Self> {
+        let list = line.trim().strip_prefix(CAPABILITIES_PREFIX)?;
+        Some(Self(list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect()))
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|capability| capability == name)
+    }
+
+    pub fn float_targets(&self) -> bool {
+        self.has(CAPABILITY_FLOAT_TARGETS)
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", self.0.join(", "))
+        }
+    }
+}
+
+// Serial number from a "SERIAL:..." reply, None for any other line
+pub fn parse_serial(line: &str) -> Option<String> {
+    let serial = line.trim().strip_prefix(SERIAL_PREFIX)?.trim();
+    (!serial.is_empty()).then(|| serial.to_string())
+}
+
+// When a SendAt command was really written
+#[derive(Debug, Clone)]
+pub struct ScheduledSend {
+    pub command: String,
+    pub due: Instant,
+    pub written: Instant, // Writing started
+}
+
+// Time from a command being queued by the GUI until it has been written and flushed to the link
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CommandLatency {
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+    pub count: u32,
+}
+
+impl CommandLatency {
+    pub fn record(&mut self, latency: Duration) {
+        self.last = latency;
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.count
+        }
+    }
+}
+
+// Everything the worker reacts to, merged into one channel so it can block instead of polling
+enum WorkerEvent {
+    Command(EspCommand, Instant), // Stamped when taken off the command channel
+    CommandChannelClosed,
+    Received(Vec<u8>, Instant), // Stamped by the reader thread, for clock samples
+    ReadFailed(io::Error),
+}
+
+// An open link: the worker writes through `writer` while a reader thread blocks on a clone of it
+struct Link {
+    writer: Box<dyn Transport>,
+    reader_stop: Arc<AtomicBool>,
+    reader_handle: JoinHandle<()>,
+}
+
+impl Link {
+    fn open(transport: Box<dyn Transport>, event_tx: Sender<WorkerEvent>) -> io::Result<Self> {
+        let mut reader = transport.try_clone_transport()?;
+        reader.set_timeout(READER_POLL_TIMEOUT)?;
+        let reader_stop = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&reader_stop);
+        let reader_handle = thread::spawn(move || {
+            let mut read_buffer: [u8; 1024] = [0; 1024];
+            while !stop.load(Ordering::Relaxed) {
+                match reader.read(&mut read_buffer) {
+                    Ok(bytes_read) if bytes_read > 0 => {
+                        if event_tx.send(WorkerEvent::Received(read_buffer[..bytes_read].to_vec(), Instant::now())).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => { /* 0 bytes read, no new data */ }
+                    Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                        // Expected with a read timeout if no data is available
+                    }
+                    Err(e) => {
+                        if !stop.load(Ordering::Relaxed) {
+                            event_tx.send(WorkerEvent::ReadFailed(e)).ok();
+                        }
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { writer: transport, reader_stop, reader_handle })
+    }
+
+    // Stops the reader and waits for it, so the port is really closed once this returns
+    fn close(self) {
+        self.reader_stop.store(true, Ordering::Relaxed);
+        drop(self.writer);
+        self.reader_handle.join().ok();
+    }
+}
+
+fn close_link(link: &mut Option<Link>) {
+    if let Some(open_link) = link.take() {
+        open_link.close();
+    }
+}
+
+fn report_link_failure(status_tx: &Sender<EspStatus>, error_msg: String) {
+    status_tx.send(EspStatus::Error(error_msg.clone())).ok();
+    status_tx.send(EspStatus::Disconnected(Some(error_msg))).ok();
+}
+
+// Asks the firmware to switch to binary framing. Older firmware ignores (or rejects) the request,
+// in which case we stay on ASCII. Any other text received meanwhile is forwarded as messages.
+// Returns whether binary was acknowledged plus any bytes that followed the acknowledgement (or,
+// when staying on ASCII, the start of an unterminated line).
+fn negotiate_binary_protocol(
+    port: &mut dyn Transport,
+    status_tx: &Sender<EspStatus>,
+) -> io::Result<(bool, Vec<u8>)> {
+    port.write_all(format!("{}\n", PROTOCOL_REQUEST).as_bytes())?;
+    port.flush()?;
+
+    let original_timeout = port.timeout();
+    port.set_timeout(Duration::from_millis(50))?;
+
+    let deadline = Instant::now() + PROTOCOL_NEGOTIATION_TIMEOUT;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut read_buffer = [0u8; 256];
+    let mut result = (false, Vec::new());
+
+    'negotiation: while Instant::now() < deadline {
+        match port.read(&mut read_buffer) {
+            Ok(bytes_read) => pending.extend_from_slice(&read_buffer[..bytes_read]),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => {
+                port.set_timeout(original_timeout).ok();
+                return Err(e);
+            }
+        }
+        while let Some(newline) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if line == PROTOCOL_ACK {
+                result = (true, std::mem::take(&mut pending));
+                break 'negotiation;
+            }
+            if !line.is_empty() {
+                status_tx.send(EspStatus::Message(line)).ok();
+            }
+        }
+    }
+
+    if !result.0 {
+        result.1 = pending;
+    }
+    port.set_timeout(original_timeout)?;
+    Ok(result)
+}
+
+// Pings the device hasn't answered yet. Plain pings (keepalives, PING typed in the console) get
+// a plain PONG from any firmware, so only a reply that can't be theirs tells that a clock ping
+// wasn't understood.
+#[derive(Default)]
+struct PendingPings {
+    plain: u32,
+    clock: bool,
+}
+
+// Forwards received text as a message, except for clock ping replies which become samples
+fn forward_text(text: &str, received_at: Instant, status_tx: &Sender<EspStatus>, clock_sync: &mut bool, pings: &mut PendingPings) {
+    let mut lines = Vec::new();
+    let mut clock_replies = 0;
+    for line in text.lines() {
+        if let Some((host_sent_us, device_us)) = clock::parse_pong(line) {
+            let sample = ClockSample { host_sent_us, device_us, host_received_us: clock::host_micros(received_at) };
+            status_tx.send(EspStatus::ClockSample(sample)).ok();
+            pings.clock = false;
+            clock_replies += 1;
+            continue;
+        }
+        if clock::is_plain_pong(line) && pings.plain > 0 {
+            pings.plain -= 1;
+        } else if *clock_sync && pings.clock && clock::is_unsupported_reply(line) {
+            *clock_sync = false;
+            pings.clock = false;
+            status_tx.send(EspStatus::ClockUnsupported).ok();
+        }
+        lines.push(line);
+    }
+    let message = lines.join("\n");
+    if clock_replies == 0 || !message.trim().is_empty() {
+        status_tx.send(EspStatus::Message(message.trim().to_string())).ok();
+    }
+}
+
+// Text of a decoded frame. The device only sends Telemetry and Text frames; anything else (a
+// Command frame echoed back by the adapter, say) is rejected like a corrupt one, never parsed.
+fn frame_text(frame: Result<Frame, FrameError>) -> Result<String, String> {
+    match frame {
+        Ok(frame) if matches!(frame.frame_type, FrameType::Telemetry | FrameType::Text) => Ok(String::from_utf8_lossy(&frame.payload).into_owned()),
+        Ok(frame) => Err(format!("unexpected {:?} frame from the device", frame.frame_type)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Removes the complete lines from `buffer` and returns them, None while no line is complete
+fn take_lines(buffer: &mut Vec<u8>) -> Option<String> {
+    let end = match buffer.iter().rposition(|&b| b == b'\n') {
+        Some(newline) => newline + 1,
+        None if buffer.len() > MAX_LINE_LENGTH => buffer.len(),
+        None => return None,
+    };
+    let lines: Vec<u8> = buffer.drain(..end).collect();
+    Some(String::from_utf8_lossy(&lines).into_owned())
+}
+
+// Writes and flushes an encoded command, mirroring it to the raw capture when enabled
+fn write_encoded(link: &mut Link, encoded: &[u8], raw_capture: bool, status_tx: &Sender<EspStatus>) -> Result<(), String> {
+    if raw_capture {
+        status_tx.send(EspStatus::Raw(RawChunk::now(RawDirection::Tx, encoded))).ok();
+    }
+    link.writer.write_all(encoded).map_err(|e| format!("Failed to send command: {}. Disconnecting.", e))?;
+    link.writer.flush().map_err(|e| format!("Failed to flush transport: {}. Disconnecting.", e))
+}
+
+// "tempActive 1" and "tempActive 0" set the same thing
+fn same_setting(a: &str, b: &str) -> bool {
+    a.split_whitespace().next() == b.split_whitespace().next()
+}
+
+pub fn encode_command(protocol: WireProtocol, command_str: &str) -> Vec<u8> {
+    match protocol {
+        WireProtocol::Ascii => format!("{}\n", command_str).into_bytes(),
+        WireProtocol::Binary => framing::encode_frame(FrameType::Command, command_str.as_bytes()),
+    }
 }
 
 pub fn esp_worker_thread(
     command_rx: Receiver<EspCommand>,
     status_tx: Sender<EspStatus>,
 ) {
-    let mut serial_port: Option<Box<dyn SerialPort>> = None;
-    let mut read_buffer: [u8; 1024] = [0; 1024];
+    let (event_tx, event_rx) = mpsc::channel();
+
+    // Forward GUI commands into the event channel. This thread sits in recv(), so the stamp is
+    // effectively the time the command was queued and the latency below includes any wait.
+    let command_event_tx = event_tx.clone();
+    thread::spawn(move || {
+        while let Ok(cmd) = command_rx.recv() {
+            if command_event_tx.send(WorkerEvent::Command(cmd, Instant::now())).is_err() {
+                return;
+            }
+        }
+        command_event_tx.send(WorkerEvent::CommandChannelClosed).ok();
+    });
+
+    let mut link: Option<Link> = None;
+    let mut protocol = WireProtocol::Ascii;
+    let mut frame_decoder = FrameDecoder::new();
+    let mut line_buffer: Vec<u8> = Vec::new(); // ASCII input up to the next newline
+    let mut corrupt_frames: u32 = 0;
+    let mut latency = CommandLatency::default();
+    let mut last_rx = Instant::now();
+    let mut last_keepalive = Instant::now();
+    let mut raw_capture = false;
+    let mut clock_sync = false;
+    let mut pings = PendingPings::default();
+    let mut clock_pings: u32 = 0;
+    let mut next_clock_ping = Instant::now();
+    let mut scheduled: Vec<(String, Instant)> = Vec::new(); // SendAt commands by due instant
 
-    loop {
-        match command_rx.try_recv() {
-            Ok(cmd) => {
+    'worker: loop {
+        // Block until there is something to do. Only links with a keepalive or clock sync need a timer.
+        let keepalive_interval = link.as_ref().and_then(|l| l.writer.keepalive_interval());
+        let mut timeout = keepalive_interval.map(|interval| interval / 2);
+        if clock_sync && link.is_some() {
+            let until_ping = next_clock_ping.saturating_duration_since(Instant::now());
+            timeout = Some(timeout.map_or(until_ping, |t| t.min(until_ping)));
+        }
+        if let Some((_, due)) = scheduled.first() {
+            let until_due = due.saturating_duration_since(Instant::now());
+            timeout = Some(timeout.map_or(until_due, |t| t.min(until_due)));
+        }
+        let event = match timeout {
+            Some(timeout) => match event_rx.recv_timeout(timeout) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match event_rx.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+        };
+
+        match event {
+            Some(WorkerEvent::Command(cmd, queued_at)) => {
                 match cmd {
-                    EspCommand::Connect(port_name, baud_rate) => {
-                        if serial_port.is_some() {
+                    EspCommand::Connect(descriptor, requested_protocol) => {
+                        if link.is_some() {
                             status_tx.send(EspStatus::Error("Already connected or connection attempt in progress.".to_string())).ok();
                             continue;
                         }
-                        match serialport::new(&port_name, baud_rate)
-                            .timeout(Duration::from_millis(1000))
-                            .open()
-                        {
-                            Ok(port) => {
-                                serial_port = Some(port);
+                        match descriptor.open() {
+                            Ok(mut port) => {
                                 status_tx.send(EspStatus::Connected).ok();
+                                status_tx.send(EspStatus::Message(format!("Opened {}", port.describe()))).ok();
+                                protocol = WireProtocol::Ascii;
+                                frame_decoder = FrameDecoder::new();
+                                line_buffer.clear();
+                                corrupt_frames = 0;
+                                latency = CommandLatency::default();
+                                last_rx = Instant::now();
+                                last_keepalive = Instant::now();
+                                clock_sync = false;
+                                pings = PendingPings::default();
+                                if requested_protocol == WireProtocol::Binary {
+                                    match negotiate_binary_protocol(port.as_mut(), &status_tx) {
+                                        Ok((true, leftover)) => {
+                                            protocol = WireProtocol::Binary;
+                                            // Anything after the acknowledgement is already framed
+                                            for frame in frame_decoder.push(&leftover) {
+                                                match frame_text(frame) {
+                                                    Ok(text) => forward_text(&text, Instant::now(), &status_tx, &mut clock_sync, &mut pings),
+                                                    Err(reason) => {
+                                                        corrupt_frames = corrupt_frames.saturating_add(1);
+                                                        status_tx.send(EspStatus::CorruptFrame(corrupt_frames, reason)).ok();
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        Ok((false, partial_line)) => {
+                                            line_buffer = partial_line;
+                                            status_tx.send(EspStatus::Message("Firmware did not acknowledge binary framing, falling back to ASCII.".to_string())).ok();
+                                        }
+                                        Err(e) => {
+                                            report_link_failure(&status_tx, format!("Protocol negotiation failed: {}. Disconnecting.", e));
+                                            break;
+                                        }
+                                    }
+                                }
+                                match Link::open(port, event_tx.clone()) {
+                                    Ok(opened) => {
+                                        status_tx.send(EspStatus::ProtocolSelected(protocol)).ok();
+                                        link = Some(opened);
+                                    }
+                                    Err(e) => {
+                                        report_link_failure(&status_tx, format!("Failed to start reader: {}. Disconnecting.", e));
+                                        break;
+                                    }
+                                }
                             }
                             Err(e) => {
-                                serial_port = None;
-                                status_tx.send(EspStatus::Error(format!("Failed to connect to {}: {}", port_name, e))).ok();
+                                status_tx.send(EspStatus::Error(format!("Failed to connect to {}: {}", descriptor, e))).ok();
                                 // No break needed here as the thread didn't establish a working state to break from.
                             }
                         }
                     }
-                    EspCommand::SendCommand(command_str) => {
-                        if let Some(port) = serial_port.as_mut() {
-                            let cmd_with_newline = format!("{}\n", command_str);
-                            if let Err(e) = port.write_all(cmd_with_newline.as_bytes()) {
-                                let error_msg = format!("Failed to send command: {}. Disconnecting.", e);
-                                status_tx.send(EspStatus::Error(error_msg.clone())).ok();
-                                serial_port.take(); 
-                                status_tx.send(EspStatus::Disconnected(Some(error_msg))).ok();
-                                break;
-                            } else {
-                                if let Err(e) = port.flush() {
-                                     let error_msg = format!("Failed to flush serial port: {}. Disconnecting.", e);
-                                     status_tx.send(EspStatus::Error(error_msg.clone())).ok();
-                                     serial_port.take(); 
-                                     status_tx.send(EspStatus::Disconnected(Some(error_msg))).ok();
-                                     break;
+                    EspCommand::SendAt(command_str, due) => {
+                        let wait = due.saturating_duration_since(Instant::now());
+                        if link.is_none() {
+                            status_tx.send(EspStatus::Error("Not connected to ESP. Cannot send command.".to_string())).ok();
+                        } else if wait > MAX_SCHEDULE_AHEAD {
+                            status_tx.send(EspStatus::Error(format!("'{}' is scheduled {:.1}s ahead, at most {:.1}s is supported. Not sent.", command_str, wait.as_secs_f32(), MAX_SCHEDULE_AHEAD.as_secs_f32()))).ok();
+                        } else {
+                            let position = scheduled.partition_point(|(_, other)| *other <= due);
+                            scheduled.insert(position, (command_str, due));
+                        }
+                    }
+                    EspCommand::SendCommand(_) | EspCommand::SendRaw(_) => {
+                        if let Some(open_link) = link.as_mut() {
+                            let encoded = match cmd {
+                                EspCommand::SendCommand(command_str) => {
+                                    // A stop sent meanwhile must not be undone by a start that is still due
+                                    scheduled.retain(|(pending, _)| {
+                                        let superseded = same_setting(pending, &command_str);
+                                        if superseded {
+                                            status_tx.send(EspStatus::Message(format!("Scheduled '{}' cancelled by '{}'", pending, command_str))).ok();
+                                        }
+                                        !superseded
+                                    });
+                                    if command_str.trim() == KEEPALIVE_COMMAND {
+                                        pings.plain += 1;
+                                    }
+                                    encode_command(protocol, &command_str)
                                 }
+                                EspCommand::SendRaw(bytes) => {
+                                    if String::from_utf8_lossy(&bytes).trim() == KEEPALIVE_COMMAND {
+                                        pings.plain += 1;
+                                    }
+                                    bytes
+                                }
+                                _ => unreachable!(),
+                            };
+                            if let Err(e) = write_encoded(open_link, &encoded, raw_capture, &status_tx) {
+                                close_link(&mut link);
+                                report_link_failure(&status_tx, e);
+                                break;
                             }
+                            latency.record(queued_at.elapsed());
+                            status_tx.send(EspStatus::CommandLatency(latency)).ok();
                         } else {
                             status_tx.send(EspStatus::Error("Not connected to ESP. Cannot send command.".to_string())).ok();
                         }
                     }
+                    EspCommand::CaptureRaw(enabled) => raw_capture = enabled,
+                    EspCommand::ClockSync(enabled) => {
+                        clock_sync = enabled;
+                        pings.clock = false;
+                        clock_pings = 0;
+                        next_clock_ping = Instant::now();
+                    }
                     EspCommand::Disconnect => {
-                        if serial_port.take().is_some() { 
+                        if let Some(open_link) = link.take() {
+                            open_link.close();
                             status_tx.send(EspStatus::Disconnected(Some("Disconnected by user.".to_string()))).ok();
                         } else {
                             status_tx.send(EspStatus::Message("Already disconnected.".to_string())).ok();
@@ -86,38 +545,104 @@ pub fn esp_worker_thread(
                         break;
                     }
                     EspCommand::StopThread => {
-                        serial_port.take(); 
+                        close_link(&mut link);
                         status_tx.send(EspStatus::Disconnected(Some("ESP worker thread stopped.".to_string()))).ok();
                         break; // Exit the loop, thread will terminate
                     }
                 }
             }
-            Err(TryRecvError::Empty) => {
-                if let Some(port) = serial_port.as_mut() {
-                    match port.read(&mut read_buffer) {
-                        Ok(bytes_read) if bytes_read > 0 => {
-                            let message = String::from_utf8_lossy(&read_buffer[..bytes_read]).to_string();
-                            status_tx.send(EspStatus::Message(message.trim().to_string())).ok();
-                        }
-                        Ok(_) => { /* 0 bytes read, no new data */ }
-                        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
-                            // This is expected with a read timeout if no data is available
+            Some(WorkerEvent::CommandChannelClosed) => {
+                close_link(&mut link);
+                break;
+            }
+            Some(WorkerEvent::Received(bytes, received_at)) => {
+                last_rx = Instant::now();
+                if raw_capture {
+                    status_tx.send(EspStatus::Raw(RawChunk::now(RawDirection::Rx, &bytes))).ok();
+                }
+                match protocol {
+                    WireProtocol::Ascii => {
+                        line_buffer.extend_from_slice(&bytes);
+                        if let Some(lines) = take_lines(&mut line_buffer) {
+                            forward_text(&lines, received_at, &status_tx, &mut clock_sync, &mut pings);
                         }
-                        Err(e) => {
-                            let error_msg = format!("Serial read error: {}. Disconnecting.", e);
-                            status_tx.send(EspStatus::Error(error_msg.clone())).ok();
-                            serial_port.take(); 
-                            status_tx.send(EspStatus::Disconnected(Some(error_msg))).ok();
-                            break;
+                    }
+                    WireProtocol::Binary => {
+                        // Corrupted and unexpected frames are counted and reported, never parsed
+                        for frame in frame_decoder.push(&bytes) {
+                            match frame_text(frame) {
+                                Ok(text) => forward_text(&text, received_at, &status_tx, &mut clock_sync, &mut pings),
+                                Err(reason) => {
+                                    corrupt_frames = corrupt_frames.saturating_add(1);
+                                    status_tx.send(EspStatus::CorruptFrame(corrupt_frames, reason)).ok();
+                                }
+                            }
                         }
                     }
                 }
             }
-            Err(TryRecvError::Disconnected) => {
-                serial_port.take(); 
-                break; 
+            Some(WorkerEvent::ReadFailed(e)) => {
+                close_link(&mut link);
+                report_link_failure(&status_tx, format!("Read error: {}. Disconnecting.", e));
+                break;
+            }
+            None => {}
+        }
+
+        // Scheduled commands that are due. The wait was on purpose, so it isn't reported as latency.
+        while let (Some(open_link), Some((_, due))) = (link.as_mut(), scheduled.first()) {
+            if *due > Instant::now() {
+                break;
+            }
+            let (command, due) = scheduled.remove(0);
+            let written = Instant::now();
+            if let Err(e) = write_encoded(open_link, &encode_command(protocol, &command), raw_capture, &status_tx) {
+                close_link(&mut link);
+                report_link_failure(&status_tx, e);
+                break 'worker;
+            }
+            status_tx.send(EspStatus::ScheduledSent(ScheduledSend { command, due, written })).ok();
+        }
+
+        // Network links can vanish without a read error, so ping idle devices and give up on silent ones
+        if let (Some(open_link), Some(interval)) = (link.as_mut(), keepalive_interval) {
+            if last_rx.elapsed() > interval * KEEPALIVE_MAX_MISSED {
+                let error_msg = format!("No data from {} for {:.1}s (keepalive timed out). Disconnecting.", open_link.writer.describe(), last_rx.elapsed().as_secs_f32());
+                close_link(&mut link);
+                report_link_failure(&status_tx, error_msg);
+                break;
+            }
+            if last_rx.elapsed() > interval && last_keepalive.elapsed() > interval {
+                last_keepalive = Instant::now();
+                let encoded = encode_command(protocol, KEEPALIVE_COMMAND);
+                if raw_capture {
+                    status_tx.send(EspStatus::Raw(RawChunk::now(RawDirection::Tx, &encoded))).ok();
+                }
+                if let Err(e) = open_link.writer.write_all(&encoded) {
+                    close_link(&mut link);
+                    report_link_failure(&status_tx, format!("Failed to send keepalive: {}. Disconnecting.", e));
+                    break;
+                }
+                pings.plain += 1;
+            }
+        }
+
+        // The ping carries the host clock, the reply is stamped by the reader thread
+        if let Some(open_link) = link.as_mut().filter(|_| clock_sync && Instant::now() >= next_clock_ping) {
+            clock_pings = clock_pings.saturating_add(1);
+            let interval = if clock_pings < clock::CLOCK_SYNC_FAST_PINGS { clock::CLOCK_SYNC_FAST_INTERVAL } else { clock::CLOCK_SYNC_INTERVAL };
+            next_clock_ping = Instant::now() + interval;
+            let encoded = encode_command(protocol, &clock::ping_command(clock::host_micros(Instant::now())));
+            if raw_capture {
+                status_tx.send(EspStatus::Raw(RawChunk::now(RawDirection::Tx, &encoded))).ok();
+            }
+            if let Err(e) = open_link.writer.write_all(&encoded).and_then(|_| open_link.writer.flush()) {
+                close_link(&mut link);
+                report_link_failure(&status_tx, format!("Failed to send clock ping: {}. Disconnecting.", e));
+                break;
             }
+            pings.clock = true;
+            last_keepalive = Instant::now(); // Serves as one
         }
-        thread::sleep(Duration::from_millis(20));
     }
 }
@@ -0,0 +1,207 @@
+// src/esp_comm/bootloader_sim.rs
+
+// Simulated ESP32 ROM bootloader for flasher.rs: follows the DTR/RTS auto-reset circuit, answers
+// SYNC, SPI_ATTACH, CHANGE_BAUDRATE, FLASH_BEGIN/DATA/END and SPI_FLASH_MD5 against an in-memory
+// flash, and can be told to fail in the ways real boards do.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use super::flasher::{self, BootloaderLink, SlipDecoder, FLASH_SIZE};
+
+struct FlashWrite {
+    offset: usize,
+    blocks: u32,
+    block_size: usize,
+    next_seq: u32,
+}
+
+pub struct SimulatedBootloader {
+    pub flash: Vec<u8>,
+    pub in_bootloader: bool,
+    pub baud_rate: u32,
+    pub app_starts: u32, // Times the application was started by a reset
+    pub auto_reset: bool, // False: DTR/RTS not wired, the board never enters the bootloader
+    pub fail_block: Option<u32>, // Answer this FLASH_DATA block with a flash write error
+    pub corrupt_block: Option<u32>, // Silently store this block with a flipped byte
+    dtr: bool,
+    rts: bool,
+    decoder: SlipDecoder,
+    outbox: VecDeque<u8>,
+    write: Option<FlashWrite>,
+}
+
+impl Default for SimulatedBootloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedBootloader {
+    pub fn new() -> Self {
+        Self {
+            flash: vec![0xFF; FLASH_SIZE],
+            in_bootloader: false,
+            baud_rate: 115200,
+            app_starts: 0,
+            auto_reset: true,
+            fail_block: None,
+            corrupt_block: None,
+            dtr: false,
+            rts: false,
+            decoder: SlipDecoder::default(),
+            outbox: VecDeque::new(),
+            write: None,
+        }
+    }
+
+    // EN released: boots into the bootloader while IO0 is held low, else into the application
+    fn release_reset(&mut self) {
+        self.write = None;
+        self.in_bootloader = self.dtr;
+        if self.in_bootloader {
+            self.outbox.extend(b"rst:0x1 (POWERON_RESET),boot:0x3 (DOWNLOAD_BOOT(UART0/UART1/SDIO_REI_REO_V2))\r\nwaiting for download\r\n");
+        } else {
+            self.app_starts += 1;
+            self.outbox.extend(b"rst:0x1 (POWERON_RESET),boot:0x13 (SPI_FAST_FLASH_BOOT)\r\n");
+        }
+    }
+
+    fn reply(&mut self, op: u8, value: u32, data: &[u8], error: Option<u8>) {
+        let mut body = data.to_vec();
+        body.extend_from_slice(&[u8::from(error.is_some()), error.unwrap_or(0), 0, 0]);
+        self.outbox.extend(flasher::encode_packet(0x01, op, value, &body));
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) {
+        if packet.len() < 8 || packet[0] != 0x00 {
+            return;
+        }
+        let op = packet[1];
+        let size = usize::from(u16::from_le_bytes([packet[2], packet[3]]));
+        let checksum = u32::from_le_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        let Some(data) = packet.get(8..8 + size) else {
+            return self.reply(op, 0, &[], Some(0x05));
+        };
+        let word = |index: usize| data.get(index * 4..index * 4 + 4).map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        match op {
+            flasher::OP_SYNC => {
+                // The ROM answers a sync several times
+                for _ in 0..8 {
+                    self.reply(op, 0x2012_0707, &[], None);
+                }
+            }
+            flasher::OP_SPI_ATTACH => self.reply(op, 0, &[], None),
+            flasher::OP_CHANGE_BAUDRATE => {
+                self.baud_rate = word(0);
+                self.reply(op, 0, &[], None);
+            }
+            flasher::OP_FLASH_BEGIN => {
+                let (erase_size, blocks, block_size, offset) = (word(0) as usize, word(1), word(2) as usize, word(3) as usize);
+                if offset + erase_size > self.flash.len() || block_size == 0 {
+                    return self.reply(op, 0, &[], Some(0x06));
+                }
+                let end = (offset + blocks as usize * block_size).min(self.flash.len());
+                self.flash[offset..end].fill(0xFF);
+                self.write = Some(FlashWrite { offset, blocks, block_size, next_seq: 0 });
+                self.reply(op, 0, &[], None);
+            }
+            flasher::OP_FLASH_DATA => {
+                let (len, seq) = (word(0) as usize, word(1));
+                let block = data.get(16..16 + len).unwrap_or_default();
+                let error = match &self.write {
+                    None => Some(0x06),
+                    Some(write) if seq != write.next_seq || seq >= write.blocks || block.len() != write.block_size => Some(0x05),
+                    Some(_) if flasher::checksum(block) != checksum => Some(0x07),
+                    Some(_) if self.fail_block == Some(seq) => Some(0x08),
+                    Some(_) => None,
+                };
+                if let (None, Some(write)) = (error, &mut self.write) {
+                    let start = write.offset + seq as usize * write.block_size;
+                    let end = (start + block.len()).min(self.flash.len());
+                    self.flash[start..end].copy_from_slice(&block[..end - start]);
+                    if self.corrupt_block == Some(seq) {
+                        self.flash[start] ^= 0x01;
+                    }
+                    write.next_seq += 1;
+                }
+                self.reply(op, 0, &[], error);
+            }
+            flasher::OP_FLASH_END => {
+                self.write = None;
+                self.reply(op, 0, &[], None);
+                if word(0) == 0 {
+                    self.dtr = false;
+                    self.release_reset();
+                }
+            }
+            flasher::OP_SPI_FLASH_MD5 => {
+                let (offset, size) = (word(0) as usize, word(1) as usize);
+                match self.flash.get(offset..offset + size) {
+                    Some(region) => {
+                        let digest = flasher::hex(&flasher::md5(region));
+                        self.reply(op, 0, digest.as_bytes(), None);
+                    }
+                    None => self.reply(op, 0, &[], Some(0x0A)),
+                }
+            }
+            _ => self.reply(op, 0, &[], Some(0x05)),
+        }
+    }
+}
+
+impl Read for SimulatedBootloader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.outbox.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "simulated bootloader read timed out"));
+        }
+        let count = buf.len().min(self.outbox.len());
+        for (slot, byte) in buf.iter_mut().zip(self.outbox.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for SimulatedBootloader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The application ignores bootloader packets
+        if self.in_bootloader {
+            for packet in self.decoder.push(buf) {
+                self.handle_packet(&packet);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BootloaderLink for SimulatedBootloader {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.dtr = level;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        let released = self.rts && !level;
+        self.rts = level;
+        if released && self.auto_reset {
+            self.release_reset();
+        }
+        Ok(())
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+// src/esp_comm/clock.rs
+
+// Device clock synchronization. Firmware that supports it answers
+//   PING <host_us>  ->  PONG <host_us> <device_us>
+// echoing the host timestamp and adding its own clock (microseconds since boot); older firmware
+// answers a plain PONG or rejects the argument. Once asked to (EspCommand::ClockSync), the
+// worker pings regularly and the reader thread stamps the replies, so every sample gives a round
+// trip time and the offset of the device clock at its midpoint (assuming symmetric delays). The
+// estimate follows the sample with the shortest round trip of the last CLOCK_WINDOW, the one
+// least delayed by queueing, which also tracks the drift of the device's crystal. Telemetry with
+// a Device_us field is then timestamped with the host time it was sampled at.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+pub const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+// The first pings go out faster, so an estimate is available soon after connecting
+pub const CLOCK_SYNC_FAST_INTERVAL: Duration = Duration::from_millis(100);
+pub const CLOCK_SYNC_FAST_PINGS: u32 = 5;
+pub const CLOCK_WINDOW: usize = 16;
+const PING_COMMAND: &str = "PING";
+const PONG_PREFIX: &str = "PONG";
+
+// Host clock: an Instant and the wall clock time it corresponds to, taken once per process
+fn epoch() -> &'static (Instant, chrono::DateTime<chrono::Local>) {
+    static EPOCH: OnceLock<(Instant, chrono::DateTime<chrono::Local>)> = OnceLock::new();
+    EPOCH.get_or_init(|| (Instant::now(), chrono::Local::now()))
+}
+
+// Microseconds on the host clock
+pub fn host_micros(at: Instant) -> u64 {
+    at.saturating_duration_since(epoch().0).as_micros() as u64
+}
+
+// Wall clock time of a host clock value
+pub fn host_datetime(host_us: i64) -> chrono::DateTime<chrono::Local> {
+    epoch().1 + chrono::Duration::microseconds(host_us)
+}
+
+// "PING 1234567"
+pub fn ping_command(host_us: u64) -> String {
+    format!("{} {}", PING_COMMAND, host_us)
+}
+
+// Host and device clock of a "PONG <host_us> <device_us>" reply, None for any other line
+// (including the plain PONG of older firmware)
+pub fn parse_pong(line: &str) -> Option<(u64, u64)> {
+    let mut parts = line.split_whitespace();
+    if parts.next()? != PONG_PREFIX {
+        return None;
+    }
+    let host_us = parts.next()?.parse().ok()?;
+    let device_us = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((host_us, device_us))
+}
+
+// The reply to a ping without a timestamp: a keepalive, a console PING, or a clock ping to
+// firmware without clock sync
+pub fn is_plain_pong(line: &str) -> bool {
+    line.trim() == PONG_PREFIX
+}
+
+// A plain PONG, or an error about the ping, from firmware without clock sync. Only meaningful
+// while a clock ping is unanswered, since plain pings are answered the same way.
+pub fn is_unsupported_reply(line: &str) -> bool {
+    let line = line.trim();
+    line == PONG_PREFIX || (line.starts_with("ERR") && line.contains(PING_COMMAND))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub host_sent_us: u64,
+    pub device_us: u64,
+    pub host_received_us: u64,
+}
+
+impl ClockSample {
+    pub fn rtt_us(&self) -> u64 {
+        self.host_received_us.saturating_sub(self.host_sent_us)
+    }
+
+    // Device clock minus host clock
+    pub fn offset_us(&self) -> i64 {
+        let midpoint = self.host_sent_us + self.rtt_us() / 2;
+        self.device_us as i64 - midpoint as i64
+    }
+}
+
+// Round trip times of every sample on the current link
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RttStats {
+    pub last_us: u64,
+    pub min_us: u64,
+    pub max_us: u64,
+    pub count: u32,
+    mean: f64,
+    m2: f64, // Sum of squared differences from the mean (Welford)
+}
+
+impl RttStats {
+    pub fn record(&mut self, rtt_us: u64) {
+        self.min_us = if self.count == 0 { rtt_us } else { self.min_us.min(rtt_us) };
+        self.max_us = self.max_us.max(rtt_us);
+        self.last_us = rtt_us;
+        self.count += 1;
+        let delta = rtt_us as f64 - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (rtt_us as f64 - self.mean);
+    }
+
+    pub fn mean_us(&self) -> f64 {
+        self.mean
+    }
+
+    // Standard deviation
+    pub fn jitter_us(&self) -> f64 {
+        if self.count < 2 { 0.0 } else { (self.m2 / f64::from(self.count - 1)).sqrt() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClockSync {
+    pub supported: Option<bool>, // None until the firmware answered a ping
+    pub rtt: RttStats,
+    samples: VecDeque<ClockSample>, // The last CLOCK_WINDOW
+}
+
+impl ClockSync {
+    pub fn add(&mut self, sample: ClockSample) {
+        self.supported = Some(true);
+        self.rtt.record(sample.rtt_us());
+        if self.samples.len() == CLOCK_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn best(&self) -> Option<&ClockSample> {
+        self.samples.iter().min_by_key(|sample| sample.rtt_us())
+    }
+
+    // Device clock minus host clock, None before the first sample
+    pub fn offset_us(&self) -> Option<i64> {
+        self.best().map(ClockSample::offset_us)
+    }
+
+    // How wrong the offset can be: half the round trip of the sample it comes from
+    pub fn uncertainty_us(&self) -> Option<u64> {
+        self.best().map(|sample| sample.rtt_us() / 2)
+    }
+
+    // One way delay to the device, estimated as half the shortest recent round trip
+    pub fn one_way_delay(&self) -> Option<Duration> {
+        self.uncertainty_us().map(Duration::from_micros)
+    }
+
+    // Host clock at a device clock value
+    pub fn to_host_us(&self, device_us: u64) -> Option<i64> {
+        self.offset_us().map(|offset| device_us as i64 - offset)
+    }
+}
@@ -0,0 +1,556 @@
+// src/esp_comm/flasher.rs
+
+// Firmware updates over the serial port, without esptool. The ESP32 is reset into its ROM
+// bootloader with the DTR/RTS auto-reset circuit of the dev boards, then the application image is
+// written with the ROM's SLIP protocol:
+//   request  [0x00][op][size: u16][checksum: u32][data]
+//   response [0x01][op][size: u16][value: u32][data][status][error][0][0]
+// (little endian, SLIP delimited, see framing.rs for the escaping). Only the application is
+// written (at 0x10000 by default); bootloader and partition table stay as they are.
+// After writing, the flash is checked with the ROM's MD5 command and the board is restarted.
+//
+// A failure before the erase leaves the old firmware in place and the board is restarted into it.
+// After that the old image is (partly) gone: the board is left in the bootloader and the error
+// says to retry or flash the previous .bin to roll back.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::bootloader_sim::SimulatedBootloader;
+use super::framing::{slip_escape_into, SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC};
+use super::sim::is_simulated_port;
+use super::transport::{TCP_PORT_PREFIX, UDP_PORT_PREFIX};
+
+pub const DEFAULT_APP_OFFSET: u32 = 0x10000;
+pub const DEFAULT_FLASH_BAUD_RATE: u32 = 460800;
+pub const FLASH_SIZE: usize = 4 * 1024 * 1024; // Of the wristband's ESP32 module
+pub const BLOCK_SIZE: usize = 0x400; // What the ROM accepts per FLASH_DATA
+
+pub const OP_FLASH_BEGIN: u8 = 0x02;
+pub const OP_FLASH_DATA: u8 = 0x03;
+pub const OP_FLASH_END: u8 = 0x04;
+pub const OP_SYNC: u8 = 0x08;
+pub const OP_SPI_ATTACH: u8 = 0x0D;
+pub const OP_CHANGE_BAUDRATE: u8 = 0x0F;
+pub const OP_SPI_FLASH_MD5: u8 = 0x13;
+
+pub const CHECKSUM_SEED: u8 = 0xEF;
+const IMAGE_MAGIC: u8 = 0xE9;
+const APP_DESC_MAGIC: u32 = 0xABCD_5432;
+const SYNC_ATTEMPTS: u32 = 5;
+const SYNC_TIMEOUT: Duration = Duration::from_millis(100);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(3);
+const ERASE_TIMEOUT_PER_MB: Duration = Duration::from_secs(30);
+const MD5_TIMEOUT_PER_MB: Duration = Duration::from_secs(8);
+const READ_POLL_TIMEOUT: Duration = Duration::from_millis(20);
+
+// A serial port with the modem lines the auto-reset circuit is wired to
+pub trait BootloaderLink: Read + Write + Send {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()>; // Pulls IO0 low
+    fn set_rts(&mut self, level: bool) -> io::Result<()>; // Pulls EN low (holds the chip in reset)
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()>;
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl BootloaderLink for Box<dyn serialport::SerialPort> {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.write_data_terminal_ready(level).map_err(io::Error::from)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.write_request_to_send(level).map_err(io::Error::from)
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        serialport::SerialPort::set_baud_rate(self.as_mut(), baud_rate).map_err(io::Error::from)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+}
+
+// Opens the port of a module for flashing. "sim..." ports flash a simulated bootloader.
+pub fn open_link(port_name: &str, baud_rate: u32) -> io::Result<Box<dyn BootloaderLink>> {
+    let port_name = port_name.trim();
+    if is_simulated_port(port_name) {
+        return Ok(Box::new(SimulatedBootloader::new()));
+    }
+    if port_name.starts_with(TCP_PORT_PREFIX) || port_name.starts_with(UDP_PORT_PREFIX) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "firmware can only be flashed over a serial port"));
+    }
+    let port = serialport::new(port_name, baud_rate).timeout(READ_POLL_TIMEOUT).open().map_err(io::Error::from)?;
+    Ok(Box::new(port))
+}
+
+// --- Images ---
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageInfo {
+    pub size: usize,
+    pub segments: u8,
+    pub version: Option<String>, // From the ESP-IDF app description, when the image has one
+    pub project: Option<String>,
+    pub md5: String,
+}
+
+impl ImageInfo {
+    pub fn describe(&self) -> String {
+        let name = match (&self.project, &self.version) {
+            (Some(project), Some(version)) => format!("{} {}, ", project, version),
+            (None, Some(version)) => format!("version {}, ", version),
+            _ => String::new(),
+        };
+        format!("{}{} bytes, {} segments, MD5 {}", name, self.size, self.segments, self.md5)
+    }
+}
+
+// Checks that `image` is an ESP32 application image that fits at `offset`
+pub fn inspect_image(image: &[u8], offset: u32) -> Result<ImageInfo, String> {
+    if image.len() < 24 {
+        return Err(format!("Not a firmware image: only {} bytes", image.len()));
+    }
+    if image[0] != IMAGE_MAGIC {
+        return Err(format!("Not an ESP32 application image: starts with 0x{:02X} instead of 0x{:02X}", image[0], IMAGE_MAGIC));
+    }
+    let segments = image[1];
+    if !(1..=16).contains(&segments) {
+        return Err(format!("Not an ESP32 application image: {} segments", segments));
+    }
+    let chip_id = u16::from_le_bytes([image[12], image[13]]);
+    if chip_id != 0 {
+        return Err(format!("Image was built for another chip (ID {}), the wristband has an ESP32", chip_id));
+    }
+    if offset as usize + image.len() > FLASH_SIZE {
+        return Err(format!("Image of {} bytes does not fit at 0x{:X} in {} MB of flash", image.len(), offset, FLASH_SIZE / (1024 * 1024)));
+    }
+    // esp_app_desc_t follows the first segment header: magic, 12 bytes, version[32], project_name[32]
+    let has_desc = image.len() >= 112 && u32_at(image, 32) == APP_DESC_MAGIC;
+    let text = |range: std::ops::Range<usize>| {
+        let bytes = &image[range];
+        let text = String::from_utf8_lossy(&bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())]).trim().to_string();
+        (!text.is_empty()).then_some(text)
+    };
+    Ok(ImageInfo {
+        size: image.len(),
+        segments,
+        version: if has_desc { text(48..80) } else { None },
+        project: if has_desc { text(80..112) } else { None },
+        md5: hex(&md5(image)),
+    })
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// MD5 (RFC 1321), what the ROM verifies flash contents with
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let k: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32).collect();
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for chunk in message.chunks_exact(64) {
+        let words: Vec<u32> = (0..16).map(|i| u32_at(chunk, i * 4)).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[(i / 16) * 4 + i % 4]));
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+    let mut digest = [0u8; 16];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+// --- Packets ---
+
+// XOR of the data with CHECKSUM_SEED, only checked for FLASH_DATA
+pub fn checksum(data: &[u8]) -> u32 {
+    u32::from(data.iter().fold(CHECKSUM_SEED, |acc, b| acc ^ b))
+}
+
+// SLIP delimited request or response
+pub fn encode_packet(direction: u8, op: u8, value: u32, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![direction, op];
+    body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    body.extend_from_slice(&value.to_le_bytes());
+    body.extend_from_slice(data);
+    let mut out = vec![SLIP_END];
+    slip_escape_into(&body, &mut out);
+    out.push(SLIP_END);
+    out
+}
+
+// Splits the byte stream into SLIP packets. Text outside packets (the ROM's boot messages) is dropped.
+#[derive(Debug, Default)]
+pub struct SlipDecoder {
+    buffer: Vec<u8>,
+    in_packet: bool,
+    escaping: bool,
+}
+
+impl SlipDecoder {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        for &byte in bytes {
+            match (byte, self.in_packet) {
+                (SLIP_END, false) => self.in_packet = true,
+                (SLIP_END, true) if self.buffer.is_empty() => {} // Back to back delimiters
+                (SLIP_END, true) => {
+                    packets.push(std::mem::take(&mut self.buffer));
+                    self.in_packet = false;
+                    self.escaping = false;
+                }
+                (_, false) => {}
+                (SLIP_ESC, true) => self.escaping = true,
+                (byte, true) => {
+                    let byte = match (self.escaping, byte) {
+                        (true, SLIP_ESC_END) => SLIP_END,
+                        (true, SLIP_ESC_ESC) => SLIP_ESC,
+                        (_, byte) => byte,
+                    };
+                    self.escaping = false;
+                    self.buffer.push(byte);
+                }
+            }
+        }
+        packets
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub op: u8,
+    pub value: u32,
+    pub data: Vec<u8>, // Without the status bytes
+}
+
+// Parses a response packet, checking the status the ROM appends
+pub fn parse_response(packet: &[u8]) -> Option<Result<Response, String>> {
+    if packet.len() < 8 || packet[0] != 0x01 {
+        return None;
+    }
+    let op = packet[1];
+    let size = usize::from(u16::from_le_bytes([packet[2], packet[3]]));
+    let data = packet.get(8..8 + size)?;
+    if data.len() < 4 {
+        return Some(Err(format!("Short reply to 0x{:02X}", op)));
+    }
+    let (data, status) = data.split_at(data.len() - 4);
+    if status[0] != 0 {
+        return Some(Err(format!("Bootloader rejected 0x{:02X}: {}", op, rom_error(status[1]))));
+    }
+    Some(Ok(Response { op, value: u32_at(packet, 4), data: data.to_vec() }))
+}
+
+fn rom_error(code: u8) -> String {
+    match code {
+        0x05 => "invalid message".to_string(),
+        0x06 => "failed to act on the message".to_string(),
+        0x07 => "invalid checksum".to_string(),
+        0x08 => "flash write error".to_string(),
+        0x09 => "flash read error".to_string(),
+        0x0A => "flash read length error".to_string(),
+        code => format!("error 0x{:02X}", code),
+    }
+}
+
+// --- Flashing ---
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlashOptions {
+    pub offset: u32,
+    pub flash_baud_rate: Option<u32>, // Switched to after syncing, None stays at the port's baud rate
+    pub verify: bool,
+}
+
+impl Default for FlashOptions {
+    fn default() -> Self {
+        Self { offset: DEFAULT_APP_OFFSET, flash_baud_rate: Some(DEFAULT_FLASH_BAUD_RATE), verify: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashStage {
+    Syncing,   // `done` of `total` attempts
+    Erasing,
+    Writing,   // `done` of `total` bytes
+    Verifying,
+    Restarting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlashProgress {
+    pub stage: FlashStage,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl FlashProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 { 0.0 } else { self.done as f32 / self.total as f32 }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.stage {
+            FlashStage::Syncing => format!("Connecting to the bootloader (attempt {}/{})", self.done, self.total),
+            FlashStage::Erasing => "Erasing".to_string(),
+            FlashStage::Writing => format!("Writing {}/{} bytes", self.done, self.total),
+            FlashStage::Verifying => "Verifying".to_string(),
+            FlashStage::Restarting => "Restarting".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashReport {
+    pub bytes: usize,
+    pub elapsed: Duration,
+    pub md5: String,
+    pub verified: bool,
+}
+
+// Failure of one step; `erased` once the old firmware may be gone
+struct FlashError {
+    message: String,
+    erased: bool,
+}
+
+struct Rom<'a> {
+    link: &'a mut dyn BootloaderLink,
+    decoder: SlipDecoder,
+    packets: Vec<Vec<u8>>,
+}
+
+impl Rom<'_> {
+    fn command(&mut self, op: u8, data: &[u8], checksum: u32, timeout: Duration) -> Result<Response, String> {
+        let packet = encode_packet(0x00, op, checksum, data);
+        self.link.write_all(&packet).and_then(|()| self.link.flush()).map_err(|e| format!("Write failed: {}", e))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Replies to other commands (the ROM answers every SYNC several times) are skipped
+            for packet in std::mem::take(&mut self.packets) {
+                match parse_response(&packet) {
+                    Some(Ok(response)) if response.op == op => return Ok(response),
+                    Some(Err(e)) if packet[1] == op => return Err(e),
+                    _ => {}
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("No reply to 0x{:02X} within {:.1}s", op, timeout.as_secs_f32()));
+            }
+            let mut buf = [0u8; 512];
+            match self.link.read(&mut buf) {
+                Ok(count) => self.packets = self.decoder.push(&buf[..count]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {}
+                Err(e) => return Err(format!("Read failed: {}", e)),
+            }
+        }
+    }
+
+    // DTR/RTS sequence of the dev board auto-reset circuit: EN low, then released with IO0 low
+    fn reset_into_bootloader(&mut self) -> io::Result<()> {
+        self.link.set_dtr(false)?;
+        self.link.set_rts(true)?;
+        thread::sleep(Duration::from_millis(100));
+        self.link.set_dtr(true)?;
+        self.link.set_rts(false)?;
+        thread::sleep(Duration::from_millis(50));
+        self.link.set_dtr(false)
+    }
+
+    // Pulses EN with IO0 high, which starts the application
+    fn hard_reset(&mut self) -> io::Result<()> {
+        self.link.set_dtr(false)?;
+        self.link.set_rts(true)?;
+        thread::sleep(Duration::from_millis(100));
+        self.link.set_rts(false)
+    }
+
+    fn sync(&mut self, progress: &mut dyn FnMut(FlashProgress)) -> Result<(), String> {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.extend([0x55; 32]);
+        for attempt in 1..=SYNC_ATTEMPTS {
+            progress(FlashProgress { stage: FlashStage::Syncing, done: attempt as usize, total: SYNC_ATTEMPTS as usize });
+            self.reset_into_bootloader().map_err(|e| format!("Could not reset the board: {}", e))?;
+            for _ in 0..3 {
+                if self.command(OP_SYNC, &data, 0, SYNC_TIMEOUT).is_ok() {
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!("No answer from the bootloader after {} attempts; check the cable, or hold BOOT while pressing EN on boards without auto-reset", SYNC_ATTEMPTS))
+    }
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+// Longer operations get a timeout that grows with the size
+fn timeout_for(per_mb: Duration, bytes: usize) -> Duration {
+    per_mb.mul_f64(bytes as f64 / (1024.0 * 1024.0)).max(COMMAND_TIMEOUT)
+}
+
+// Writes `image` through the ROM bootloader, see the top of the file
+pub fn flash_image(link: &mut dyn BootloaderLink, image: &[u8], options: &FlashOptions, progress: &mut dyn FnMut(FlashProgress)) -> Result<FlashReport, String> {
+    let started = Instant::now();
+    let info = inspect_image(image, options.offset)?;
+    link.set_timeout(READ_POLL_TIMEOUT).map_err(|e| e.to_string())?;
+    let mut rom = Rom { link, decoder: SlipDecoder::default(), packets: Vec::new() };
+    match write_and_verify(&mut rom, image, &info, options, progress) {
+        Ok(verified) => {
+            progress(FlashProgress { stage: FlashStage::Restarting, done: 0, total: 0 });
+            rom.hard_reset().map_err(|e| format!("Firmware written, but the board could not be restarted ({}); press EN/RESET", e))?;
+            Ok(FlashReport { bytes: image.len(), elapsed: started.elapsed(), md5: info.md5, verified })
+        }
+        Err(FlashError { message, erased: false }) => {
+            let restarted = rom.hard_reset().is_ok();
+            Err(format!("{}. The firmware on the module was not changed{}.", message, if restarted { " and it was restarted" } else { "; press EN/RESET to restart it" }))
+        }
+        Err(FlashError { message, erased: true }) => Err(format!(
+            "{}. The previous firmware was (partly) overwritten, so the module stays in the bootloader until an image is flashed successfully: retry, or flash the previous .bin to roll back.",
+            message
+        )),
+    }
+}
+
+// Returns whether the flash contents were verified
+fn write_and_verify(rom: &mut Rom<'_>, image: &[u8], info: &ImageInfo, options: &FlashOptions, progress: &mut dyn FnMut(FlashProgress)) -> Result<bool, FlashError> {
+    let kept = |message: String| FlashError { message, erased: false };
+    let erased = |message: String| FlashError { message, erased: true };
+
+    rom.sync(progress).map_err(kept)?;
+    rom.command(OP_SPI_ATTACH, &[0; 8], 0, COMMAND_TIMEOUT).map_err(kept)?;
+    if let Some(baud_rate) = options.flash_baud_rate {
+        rom.command(OP_CHANGE_BAUDRATE, &words(&[baud_rate, 0]), 0, COMMAND_TIMEOUT).map_err(kept)?;
+        rom.link.set_baud_rate(baud_rate).map_err(|e| kept(format!("Could not switch to {} baud: {}", baud_rate, e)))?;
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    progress(FlashProgress { stage: FlashStage::Erasing, done: 0, total: image.len() });
+    let blocks = image.len().div_ceil(BLOCK_SIZE);
+    let begin = words(&[image.len() as u32, blocks as u32, BLOCK_SIZE as u32, options.offset]);
+    // The erase may have started even if the ROM never answers
+    rom.command(OP_FLASH_BEGIN, &begin, 0, timeout_for(ERASE_TIMEOUT_PER_MB, image.len())).map_err(erased)?;
+
+    for (seq, chunk) in image.chunks(BLOCK_SIZE).enumerate() {
+        let mut block = chunk.to_vec();
+        block.resize(BLOCK_SIZE, 0xFF);
+        let mut data = words(&[BLOCK_SIZE as u32, seq as u32, 0, 0]);
+        data.extend_from_slice(&block);
+        rom.command(OP_FLASH_DATA, &data, checksum(&block), COMMAND_TIMEOUT)
+            .map_err(|e| erased(format!("Writing block {}/{} failed: {}", seq + 1, blocks, e)))?;
+        progress(FlashProgress { stage: FlashStage::Writing, done: (seq * BLOCK_SIZE + chunk.len()), total: image.len() });
+    }
+
+    if options.verify {
+        progress(FlashProgress { stage: FlashStage::Verifying, done: 0, total: 0 });
+        let reply = rom.command(OP_SPI_FLASH_MD5, &words(&[options.offset, image.len() as u32, 0, 0]), 0, timeout_for(MD5_TIMEOUT_PER_MB, image.len()))
+            .map_err(|e| erased(format!("Verification failed: {}", e)))?;
+        let flash_md5 = String::from_utf8_lossy(&reply.data).trim().to_ascii_lowercase();
+        if flash_md5 != info.md5 {
+            return Err(erased(format!("Verification failed: flash MD5 {} does not match the image ({})", flash_md5, info.md5)));
+        }
+    }
+    // Stay in the bootloader (1); the board is restarted through EN afterwards
+    rom.command(OP_FLASH_END, &words(&[1]), 0, COMMAND_TIMEOUT).map_err(erased)?;
+    Ok(options.verify)
+}
+
+// --- Background job ---
+
+#[derive(Debug, Clone)]
+pub enum FlashEvent {
+    Progress(FlashProgress),
+    Finished(Result<FlashReport, String>),
+}
+
+// Flashing in its own thread, polled by the controller (see Module::flash)
+pub struct FlashJob {
+    pub image: ImageInfo,
+    pub progress: Option<FlashProgress>,
+    pub result: Option<Result<FlashReport, String>>,
+    receiver: Receiver<FlashEvent>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FlashJob {
+    pub fn spawn(port_name: &str, baud_rate: u32, image: Vec<u8>, options: FlashOptions) -> Result<Self, String> {
+        let info = inspect_image(&image, options.offset)?;
+        let port_name = port_name.trim().to_string();
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let result = open_link(&port_name, baud_rate)
+                .map_err(|e| format!("Could not open {}: {}. The firmware on the module was not changed.", port_name, e))
+                .and_then(|mut link| flash_image(link.as_mut(), &image, &options, &mut |progress| {
+                    sender.send(FlashEvent::Progress(progress)).ok();
+                }));
+            sender.send(FlashEvent::Finished(result)).ok();
+        });
+        Ok(Self { image: info, progress: None, result: None, receiver, handle: Some(handle) })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.result.is_none()
+    }
+
+    // Applies what the flashing thread reported since the last call and returns it
+    pub fn poll(&mut self) -> Vec<FlashEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => {
+                    match &event {
+                        FlashEvent::Progress(progress) => self.progress = Some(*progress),
+                        FlashEvent::Finished(result) => self.result = Some(result.clone()),
+                    }
+                    events.push(event);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.result.is_none() {
+                        let result = Err("Flashing thread stopped unexpectedly".to_string());
+                        self.result = Some(result.clone());
+                        events.push(FlashEvent::Finished(result));
+                    }
+                    break;
+                }
+            }
+        }
+        if !self.is_running() {
+            if let Some(handle) = self.handle.take() {
+                handle.join().ok();
+            }
+        }
+        events
+    }
+}
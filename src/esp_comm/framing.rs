@@ -0,0 +1,174 @@
+// src/esp_comm/framing.rs
+
+// Optional binary framing for the ESP link.
+// Every frame is SLIP delimited and carries: [type: u8][payload...][crc16: u16 big endian].
+// The CRC is CRC-16/CCITT-FALSE computed over the type byte and the payload.
+
+use std::fmt;
+
+pub const SLIP_END: u8 = 0xC0;
+pub const SLIP_ESC: u8 = 0xDB;
+pub const SLIP_ESC_END: u8 = 0xDC;
+pub const SLIP_ESC_ESC: u8 = 0xDD;
+
+// Frames larger than this are treated as line noise and dropped.
+pub const MAX_FRAME_LEN: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Command = 0x01,   // host -> ESP, payload is the ASCII command ("setTemp 20")
+    Telemetry = 0x02, // ESP -> host, payload is the ASCII telemetry line
+    Text = 0x03,      // ESP -> host, free form text (replies, debug output)
+}
+
+impl FrameType {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(FrameType::Command),
+            0x02 => Some(FrameType::Telemetry),
+            0x03 => Some(FrameType::Text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub frame_type: FrameType,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    TooShort(usize),
+    BadCrc { expected: u16, actual: u16 },
+    UnknownType(u8),
+    BadEscape(u8),
+    Oversized,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::TooShort(len) => write!(f, "frame too short ({} bytes)", len),
+            FrameError::BadCrc { expected, actual } => write!(f, "CRC mismatch (expected {:04X}, got {:04X})", expected, actual),
+            FrameError::UnknownType(byte) => write!(f, "unknown frame type 0x{:02X}", byte),
+            FrameError::BadEscape(byte) => write!(f, "invalid SLIP escape 0x{:02X}", byte),
+            FrameError::Oversized => write!(f, "frame exceeds {} bytes", MAX_FRAME_LEN),
+        }
+    }
+}
+
+// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF, no reflection, no final xor)
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+// Appends `bytes` to `out` with SLIP escaping applied.
+pub fn slip_escape_into(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        match byte {
+            SLIP_END => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            _ => out.push(byte),
+        }
+    }
+}
+
+// Builds a complete frame ready to be written to the wire.
+// A leading END flushes any noise the receiver may have buffered.
+pub fn encode_frame(frame_type: FrameType, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(payload.len() + 3);
+    body.push(frame_type as u8);
+    body.extend_from_slice(payload);
+    let crc = crc16(&body);
+    body.extend_from_slice(&crc.to_be_bytes());
+
+    let mut out = Vec::with_capacity(body.len() + 4);
+    out.push(SLIP_END);
+    slip_escape_into(&body, &mut out);
+    out.push(SLIP_END);
+    out
+}
+
+// Checks the CRC and type of an unescaped frame body.
+pub fn decode_body(body: &[u8]) -> Result<Frame, FrameError> {
+    if body.len() < 3 {
+        return Err(FrameError::TooShort(body.len()));
+    }
+    let (content, crc_bytes) = body.split_at(body.len() - 2);
+    let expected = u16::from_be_bytes([crc_bytes[0], crc_bytes[1]]);
+    let actual = crc16(content);
+    if expected != actual {
+        return Err(FrameError::BadCrc { expected, actual });
+    }
+    let frame_type = FrameType::from_byte(content[0]).ok_or(FrameError::UnknownType(content[0]))?;
+    Ok(Frame { frame_type, payload: content[1..].to_vec() })
+}
+
+// Incremental SLIP decoder. Bytes can be pushed in arbitrary chunks as they arrive
+// from the port; complete frames (or the reason they were rejected) are returned.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    escaping: bool,
+    error: Option<FrameError>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Frame, FrameError>> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if byte == SLIP_END {
+                if let Some(err) = self.error.take() {
+                    frames.push(Err(err));
+                } else if !self.buffer.is_empty() {
+                    frames.push(decode_body(&self.buffer));
+                }
+                self.buffer.clear();
+                self.escaping = false;
+                continue;
+            }
+            if self.error.is_some() {
+                continue; // Skip the rest of a broken frame until the next END
+            }
+            let decoded = if self.escaping {
+                self.escaping = false;
+                match byte {
+                    SLIP_ESC_END => SLIP_END,
+                    SLIP_ESC_ESC => SLIP_ESC,
+                    other => {
+                        self.error = Some(FrameError::BadEscape(other));
+                        continue;
+                    }
+                }
+            } else if byte == SLIP_ESC {
+                self.escaping = true;
+                continue;
+            } else {
+                byte
+            };
+            if self.buffer.len() >= MAX_FRAME_LEN {
+                self.error = Some(FrameError::Oversized);
+                continue;
+            }
+            self.buffer.push(decoded);
+        }
+        frames
+    }
+}
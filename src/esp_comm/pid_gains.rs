@@ -0,0 +1,163 @@
+// src/esp_comm/pid_gains.rs
+
+// The firmware's PID loops on the wire (see pid.rs for the protocol and the tuning on top of it)
+// and the loop itself, as the simulated device runs it.
+
+pub const PID_REQUEST: &str = "getPid";
+pub const PID_WRITE: &str = "setPid";
+pub const PID_SAVE: &str = "savePid";
+const PID_PREFIX: &str = "PID:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PidLoop {
+    Heat,
+    Cool,
+}
+
+impl PidLoop {
+    pub const ALL: [PidLoop; 2] = [PidLoop::Heat, PidLoop::Cool];
+
+    // Name on the wire
+    pub fn name(self) -> &'static str {
+        match self {
+            PidLoop::Heat => "heat",
+            PidLoop::Cool => "cool",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|pid_loop| pid_loop.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PidSettings {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub output_min: f32, // %
+    pub output_max: f32, // %
+    pub deadband: f32,   // °C
+}
+
+impl Default for PidSettings {
+    // What the firmware ships with
+    fn default() -> Self {
+        Self { kp: 12.0, ki: 0.0, kd: 0.0, output_min: 0.0, output_max: 100.0, deadband: 0.0 }
+    }
+}
+
+impl PidSettings {
+    // "kp=12,ki=0.5,kd=0,min=0,max=100,deadband=0.1"
+    pub fn to_fields(&self) -> String {
+        format!("kp={},ki={},kd={},min={},max={},deadband={}", self.kp, self.ki, self.kd, self.output_min, self.output_max, self.deadband)
+    }
+
+    // Parses the fields of a reply or command. Every field is required.
+    pub fn parse_fields(text: &str) -> Result<Self, String> {
+        let mut values: [Option<f32>; 6] = [None; 6];
+        const KEYS: [&str; 6] = ["kp", "ki", "kd", "min", "max", "deadband"];
+        for field in text.trim().split(',') {
+            let (key, value) = field.split_once('=').ok_or_else(|| format!("expected key=value, found '{}'", field))?;
+            let slot = KEYS.iter().position(|k| *k == key.trim()).ok_or_else(|| format!("unknown PID field '{}'", key.trim()))?;
+            values[slot] = Some(value.trim().parse().map_err(|_| format!("{}: '{}' is not a number", key.trim(), value.trim()))?);
+        }
+        if let Some(missing) = KEYS.iter().zip(values).find(|(_, value)| value.is_none()) {
+            return Err(format!("missing PID field '{}'", missing.0));
+        }
+        let [kp, ki, kd, output_min, output_max, deadband] = values.map(Option::unwrap_or_default);
+        Ok(Self { kp, ki, kd, output_min, output_max, deadband })
+    }
+
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in [("kp", self.kp), ("ki", self.ki), ("kd", self.kd), ("deadband", self.deadband)] {
+            if !(value.is_finite() && value >= 0.0) {
+                problems.push(format!("{}: must be 0 or more, found {}", name, value));
+            }
+        }
+        for (name, value) in [("output_min", self.output_min), ("output_max", self.output_max)] {
+            if !(0.0..=100.0).contains(&value) {
+                problems.push(format!("{}: must be between 0 and 100 %, found {}", name, value));
+            }
+        }
+        if self.output_min > self.output_max {
+            problems.push(format!("output_min: {} is above output_max {}", self.output_min, self.output_max));
+        }
+        problems
+    }
+}
+
+// "getPid heat"
+pub fn read_command(pid_loop: PidLoop) -> String {
+    format!("{} {}", PID_REQUEST, pid_loop.name())
+}
+
+// "setPid heat kp=..."
+pub fn write_command(pid_loop: PidLoop, settings: &PidSettings) -> String {
+    format!("{} {} {}", PID_WRITE, pid_loop.name(), settings.to_fields())
+}
+
+// Parses a "PID:heat kp=..." reply, None for any other line
+pub fn parse_reply(line: &str) -> Option<Result<(PidLoop, PidSettings), String>> {
+    let rest = line.trim().strip_prefix(PID_PREFIX)?;
+    let (name, fields) = rest.split_once(' ').unwrap_or((rest, ""));
+    let Some(pid_loop) = PidLoop::parse(name.trim()) else {
+        return Some(Err(format!("Unknown PID loop '{}'", name.trim())));
+    };
+    Some(PidSettings::parse_fields(fields).map(|settings| (pid_loop, settings)).map_err(|e| format!("Invalid {} PID reply: {}", name, e)))
+}
+
+// Gains of both loops
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PidGains {
+    pub heat: PidSettings,
+    pub cool: PidSettings,
+}
+
+impl PidGains {
+    pub fn get(&self, pid_loop: PidLoop) -> &PidSettings {
+        match pid_loop {
+            PidLoop::Heat => &self.heat,
+            PidLoop::Cool => &self.cool,
+        }
+    }
+
+    pub fn get_mut(&mut self, pid_loop: PidLoop) -> &mut PidSettings {
+        match pid_loop {
+            PidLoop::Heat => &mut self.heat,
+            PidLoop::Cool => &mut self.cool,
+        }
+    }
+
+    // Problems of both loops, prefixed with the loop ("heat.kp: ...")
+    pub fn problems(&self) -> Vec<String> {
+        PidLoop::ALL.into_iter()
+            .flat_map(|pid_loop| self.get(pid_loop).problems().into_iter().map(move |problem| format!("{}.{}", pid_loop.name(), problem)))
+            .collect()
+    }
+}
+
+// State of one loop between steps
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PidState {
+    integral: f32,
+    last_error: f32,
+}
+
+impl PidState {
+    // Output in % for `error` °C after `dt` seconds, with the integral clamped to the output
+    // range (anti-windup). Errors within the deadband count as 0.
+    pub fn update(&mut self, settings: &PidSettings, error: f32, dt: f32) -> f32 {
+        let error = if error.abs() <= settings.deadband { 0.0 } else { error };
+        if settings.ki > 0.0 {
+            self.integral = (self.integral + error * dt).clamp(settings.output_min / settings.ki, settings.output_max / settings.ki);
+        }
+        let derivative = if dt > 0.0 { (error - self.last_error) / dt } else { 0.0 };
+        self.last_error = error;
+        (settings.kp * error + settings.ki * self.integral + settings.kd * derivative).clamp(settings.output_min, settings.output_max)
+    }
+}
@@ -0,0 +1,282 @@
+// src/esp_comm/sim.rs
+
+// Simulated ESP32 wristband. It speaks the same protocol as the firmware (setTemp, tempActive,
+// PING, setProto, getCaps, getPid/setPid/savePid) and emits telemetry lines from a simple first order thermal model, so the GUI
+// and esp_worker_thread can be exercised without hardware.
+// It implements Read + Write, which makes it usable as an in-memory transport from tests;
+// transport::SimulatedTransport shares one device between the worker's reader and writer.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::framing::{FrameDecoder, FrameType, encode_frame};
+use super::pid_gains::{self, PidGains, PidLoop, PidSettings, PidState};
+
+// Port names starting with this prefix open a simulated device instead of a serial port ("sim", "sim:L", ...)
+pub const SIMULATED_PORT_PREFIX: &str = "sim";
+
+// "sim:L:legacy" simulates older firmware: no getCaps, whole degree targets only, no remote PID
+// tuning, no clock sync
+pub const LEGACY_FIRMWARE_SUFFIX: &str = ":legacy";
+
+pub fn is_simulated_port(port_name: &str) -> bool {
+    port_name.trim().to_ascii_lowercase().starts_with(SIMULATED_PORT_PREFIX)
+}
+
+pub fn is_legacy_simulated_port(port_name: &str) -> bool {
+    is_simulated_port(port_name) && port_name.trim().to_ascii_lowercase().ends_with(LEGACY_FIRMWARE_SUFFIX)
+}
+
+const BODY_CORE_TEMP: f32 = 34.0; // What the skin relaxes to without stimulation
+const PELTIER_GAIN: f32 = 0.08; // °C/s per % of PID output
+const PELTIER_LEAK: f32 = 0.05; // 1/s towards ambient
+const SKIN_COUPLING: f32 = 0.15; // 1/s towards the peltier surface
+const BODY_COUPLING: f32 = 0.03; // 1/s towards core temperature
+
+pub struct SimulatedEsp {
+    pub ambient_temp: f32,
+    pub exterior_temp: f32,
+    pub skin_temp: f32,
+    pub skin_temp_smoothed: f32,
+    pub target_temp: f32,
+    pub active: bool,
+    pub float_targets: bool, // False behaves like firmware before getCaps and decimal setTemp
+    pub pid_tuning: bool,    // False behaves like firmware before getPid/setPid
+    pub clock_sync: bool,    // False answers PING with a plain PONG and sends no Device_us
+    pub pid: PidGains,
+    pub flashed_pid: PidGains, // What savePid stored
+    pub heat_output: f32,
+    pub cool_output: f32,
+    loops: [PidState; 2], // Heat, cool
+    pub telemetry_interval: Duration,
+    read_timeout: Duration,
+    binary_framing: bool,
+    line_buffer: Vec<u8>,
+    frame_decoder: FrameDecoder,
+    outbox: VecDeque<u8>,
+    booted: Instant, // The device clock counts from here
+    last_step: Instant,
+    next_telemetry: Instant,
+}
+
+impl Default for SimulatedEsp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedEsp {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            ambient_temp: 22.0,
+            exterior_temp: 22.0,
+            skin_temp: BODY_CORE_TEMP - 2.0,
+            skin_temp_smoothed: BODY_CORE_TEMP - 2.0,
+            target_temp: 0.0,
+            active: false,
+            float_targets: true,
+            pid_tuning: true,
+            clock_sync: true,
+            pid: PidGains::default(),
+            flashed_pid: PidGains::default(),
+            heat_output: 0.0,
+            cool_output: 0.0,
+            loops: [PidState::default(); 2],
+            telemetry_interval: Duration::from_millis(200),
+            read_timeout: Duration::from_millis(1000),
+            binary_framing: false,
+            line_buffer: Vec::new(),
+            frame_decoder: FrameDecoder::new(),
+            outbox: VecDeque::new(),
+            booted: now,
+            last_step: now,
+            next_telemetry: now,
+        }
+    }
+
+    // Older firmware, see LEGACY_FIRMWARE_SUFFIX
+    pub fn legacy() -> Self {
+        Self { float_targets: false, pid_tuning: false, clock_sync: false, ..Self::new() }
+    }
+
+    // Advances the thermal model by `dt` seconds.
+    pub fn step(&mut self, dt: f32) {
+        if self.active {
+            let (error, gains) = (self.target_temp - self.exterior_temp, self.pid);
+            self.heat_output = self.loops[0].update(&gains.heat, error, dt);
+            self.cool_output = self.loops[1].update(&gains.cool, -error, dt);
+        } else {
+            self.heat_output = 0.0;
+            self.cool_output = 0.0;
+            self.loops = [PidState::default(); 2];
+        }
+        self.exterior_temp += ((self.heat_output - self.cool_output) * PELTIER_GAIN
+            + (self.ambient_temp - self.exterior_temp) * PELTIER_LEAK) * dt;
+        self.skin_temp += ((self.exterior_temp - self.skin_temp) * SKIN_COUPLING
+            + (BODY_CORE_TEMP - self.skin_temp) * BODY_COUPLING) * dt;
+        let alpha = (dt * 2.0).min(1.0);
+        self.skin_temp_smoothed += (self.skin_temp - self.skin_temp_smoothed) * alpha;
+    }
+
+    // Microseconds since the simulated device booted
+    pub fn device_micros(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.booted).as_micros() as u64
+    }
+
+    // Stamped with the device clock of the last model step when clock sync is on
+    pub fn telemetry_line(&self) -> String {
+        let mut line = format!(
+            "Skin_Temp_Smoothed:{:.2},Exterior_Temp:{:.2},Target_Temp:{:.1},Heat_PID_output:{:.1},Cool_PID_output:{:.1},Ambient:{:.1}",
+            self.skin_temp_smoothed, self.exterior_temp, self.target_temp, self.heat_output, self.cool_output, self.ambient_temp
+        );
+        if self.clock_sync {
+            line.push_str(&format!(",Device_us:{}", self.device_micros(self.last_step)));
+        }
+        line
+    }
+
+    // Executes one command line, queueing the reply (if any) for the host to read.
+    pub fn handle_line(&mut self, line: &str) {
+        let mut parts = line.split_whitespace();
+        let reply = match (parts.next(), parts.next()) {
+            (Some("PING"), Some(host_us)) if self.clock_sync => format!("PONG {} {}", host_us, self.device_micros(Instant::now())),
+            (Some("PING"), _) => "PONG".to_string(),
+            (Some("getCaps"), _) if self.float_targets => "CAPS:FLOAT_TEMP".to_string(),
+            (Some("setTemp"), Some(value)) if !self.float_targets => match value.parse::<i32>() {
+                Ok(temp) => {
+                    self.target_temp = temp as f32;
+                    format!("OK setTemp {}", value)
+                }
+                Err(_) => format!("ERR invalid temperature '{}'", value),
+            },
+            (Some("setTemp"), Some(value)) => match value.parse::<f32>() {
+                Ok(temp) => {
+                    self.target_temp = temp;
+                    format!("OK setTemp {}", value)
+                }
+                Err(_) => format!("ERR invalid temperature '{}'", value),
+            },
+            (Some(pid_gains::PID_REQUEST), Some(name)) if self.pid_tuning => match PidLoop::parse(name) {
+                Some(pid_loop) => format!("PID:{} {}", name, self.pid.get(pid_loop).to_fields()),
+                None => format!("ERR unknown PID loop '{}'", name),
+            },
+            (Some(pid_gains::PID_WRITE), Some(name)) if self.pid_tuning => {
+                let settings = PidSettings::parse_fields(parts.next().unwrap_or_default())
+                    .and_then(|settings| match settings.problems().first() {
+                        Some(problem) => Err(problem.clone()),
+                        None => Ok(settings),
+                    });
+                match (PidLoop::parse(name), settings) {
+                    (Some(pid_loop), Ok(settings)) => {
+                        *self.pid.get_mut(pid_loop) = settings;
+                        format!("OK setPid {}", name)
+                    }
+                    (None, _) => format!("ERR unknown PID loop '{}'", name),
+                    (_, Err(e)) => format!("ERR invalid PID settings: {}", e),
+                }
+            }
+            (Some(pid_gains::PID_SAVE), _) if self.pid_tuning => {
+                self.flashed_pid = self.pid;
+                "OK savePid".to_string()
+            }
+            (Some("tempActive"), Some(value)) => {
+                self.active = value == "1";
+                format!("OK tempActive {}", if self.active { 1 } else { 0 })
+            }
+            (Some("setProto"), Some("bin")) => {
+                // The acknowledgement is still plain text, everything after it is framed
+                self.queue_text("PROTO:BIN", FrameType::Text);
+                self.binary_framing = true;
+                return;
+            }
+            (None, _) => return,
+            _ => format!("ERR unknown command '{}'", line.trim()),
+        };
+        self.queue_text(&reply, FrameType::Text);
+    }
+
+    // Steps the model up to `now` and queues telemetry when it is due.
+    pub fn poll(&mut self, now: Instant) {
+        let dt = now.saturating_duration_since(self.last_step).as_secs_f32();
+        self.last_step = now;
+        self.step(dt);
+        if now >= self.next_telemetry {
+            let line = self.telemetry_line();
+            self.queue_text(&line, FrameType::Telemetry);
+            self.next_telemetry = now + self.telemetry_interval;
+        }
+    }
+
+    // Moves queued output into `buf` without blocking, returning how many bytes were copied.
+    pub fn take_output(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.outbox.len());
+        for (slot, byte) in buf.iter_mut().zip(self.outbox.drain(..count)) {
+            *slot = byte;
+        }
+        count
+    }
+
+    pub fn next_telemetry_at(&self) -> Instant {
+        self.next_telemetry
+    }
+
+    fn queue_text(&mut self, text: &str, frame_type: FrameType) {
+        if self.binary_framing {
+            self.outbox.extend(encode_frame(frame_type, text.as_bytes()));
+        } else {
+            self.outbox.extend(text.as_bytes());
+            self.outbox.push_back(b'\n');
+        }
+    }
+}
+
+impl Read for SimulatedEsp {
+    // Behaves like a serial port with a read timeout: blocks until telemetry or a reply is available.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.read_timeout;
+        loop {
+            self.poll(Instant::now());
+            let count = self.take_output(buf);
+            if count > 0 {
+                return Ok(count);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "simulated read timed out"));
+            }
+            thread::sleep(self.next_telemetry.min(deadline).saturating_duration_since(now));
+        }
+    }
+}
+
+impl Write for SimulatedEsp {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.binary_framing {
+            let frames = self.frame_decoder.push(buf);
+            for frame in frames.into_iter().flatten() {
+                if frame.frame_type == FrameType::Command {
+                    let line = String::from_utf8_lossy(&frame.payload).to_string();
+                    self.handle_line(&line);
+                }
+            }
+        } else {
+            for &byte in buf {
+                if byte == b'\n' {
+                    let line = String::from_utf8_lossy(&self.line_buffer).to_string();
+                    self.line_buffer.clear();
+                    self.handle_line(line.trim());
+                } else {
+                    self.line_buffer.push(byte);
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
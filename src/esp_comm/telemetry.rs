@@ -0,0 +1,55 @@
+// src/esp_comm/telemetry.rs
+
+// Parsed telemetry line from the firmware, e.g.
+// "Skin_Temp_Smoothed:12.12,Exterior_Temp:18.73,Target_Temp:10.0,Heat_PID_output:0.0,Cool_PID_output:64.4,Ambient:22.0,Device_us:81234567"
+// Fields older firmware does not send stay None. Device_us is the device clock when the line was
+// sampled, see clock.rs.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Telemetry {
+    pub skin_temp_smoothed: Option<f32>,
+    pub exterior_temp: Option<f32>,
+    pub target_temp: Option<f32>,
+    pub heat_output: Option<f32>, // PID output, 0-100 %
+    pub cool_output: Option<f32>, // PID output, 0-100 %
+    pub ambient_temp: Option<f32>,
+    pub device_us: Option<u64>,
+}
+
+impl Telemetry {
+    // Parses one line. Returns None for lines without any known key (replies, debug output).
+    // A known key with an unparsable value is an error.
+    pub fn parse(line: &str) -> Option<Result<Self, String>> {
+        let mut telemetry = Self::default();
+        let mut any_known_key = false;
+        for part in line.trim().split(',') {
+            let mut kv_iterator = part.splitn(2, ':');
+            let (Some(key_raw), Some(value_raw)) = (kv_iterator.next(), kv_iterator.next()) else { continue };
+            // The only integer field; as f32 it would lose microseconds after a few seconds of uptime
+            if key_raw.trim() == "Device_us" {
+                any_known_key = true;
+                match value_raw.trim().parse::<u64>() {
+                    Ok(value) => telemetry.device_us = Some(value),
+                    Err(_) => return Some(Err(format!("Failed to parse Device_us value: '{}'", value_raw.trim()))),
+                }
+                continue;
+            }
+            let slot = match key_raw.trim() {
+                "Skin_Temp_Smoothed" => &mut telemetry.skin_temp_smoothed,
+                "Exterior_Temp" => &mut telemetry.exterior_temp,
+                "Target_Temp" => &mut telemetry.target_temp,
+                "Heat_PID_output" => &mut telemetry.heat_output,
+                "Cool_PID_output" => &mut telemetry.cool_output,
+                "Ambient" => &mut telemetry.ambient_temp,
+                _ => continue,
+            };
+            any_known_key = true;
+            let value_str = value_raw.trim();
+            match value_str.parse::<f32>() {
+                Ok(value) => *slot = Some(value),
+                Err(_) => return Some(Err(format!("Failed to parse {} value: '{}'", key_raw.trim(), value_str))),
+            }
+        }
+        any_known_key.then_some(Ok(telemetry))
+    }
+}
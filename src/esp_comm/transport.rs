@@ -0,0 +1,538 @@
+// src/esp_comm/transport.rs
+
+// Byte transports the ESP worker can talk through. The worker only needs Read + Write with a
+// read timeout, so serial ports, TCP sockets, the simulated device and the in-memory mock used
+// by the tests all look the same to it.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serialport::SerialPort;
+
+use super::sim::{SimulatedEsp, is_legacy_simulated_port, is_simulated_port};
+
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(1000);
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// Network links can drop without an error (wearer walks out of range), so the worker pings when idle
+pub const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(2);
+pub const TCP_PORT_PREFIX: &str = "tcp://";
+pub const UDP_PORT_PREFIX: &str = "udp://";
+pub const UDP_LOCAL_PORT_OPTION: &str = "?local="; // "udp://192.168.4.1:4210?local=4211"
+
+pub trait Transport: Read + Write + Send {
+    // Human readable description used in status messages ("/dev/ttyUSB0 @ 115200", ...)
+    fn describe(&self) -> String;
+    fn timeout(&self) -> Duration;
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+    // A second handle to the same link, so the worker can block on reads in one thread
+    // while commands are written from another.
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>>;
+    // When set, the worker pings the device if nothing was received for this long and
+    // disconnects when it stays silent (see esp_worker_thread).
+    fn keepalive_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// What to connect to. Sent to the worker with EspCommand::Connect.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub enum TransportDescriptor {
+    Serial { port: String, baud_rate: u32 },
+    Tcp { address: String, connect_timeout_ms: u64 },
+    Udp { device_address: String, local_port: u16 }, // Telemetry arrives on local_port (0: any free one), commands go to device_address
+    Simulated,
+    SimulatedLegacy, // Simulated older firmware, see sim::LEGACY_FIRMWARE_SUFFIX
+    #[serde(skip)]
+    Mock(MockTransport),
+}
+
+impl TransportDescriptor {
+    // Interprets the text typed into the port field: "sim..." opens the simulated device
+    // ("sim...:legacy" one behaving like older firmware),
+    // "tcp://host:port" a TCP connection, "udp://host:port" a UDP link and anything else a serial
+    // port. UDP telemetry is received on the device's port number unless "?local=PORT" follows.
+    pub fn from_port_name(port_name: &str, baud_rate: u32) -> Self {
+        let port_name = port_name.trim();
+        if is_legacy_simulated_port(port_name) {
+            TransportDescriptor::SimulatedLegacy
+        } else if is_simulated_port(port_name) {
+            TransportDescriptor::Simulated
+        } else if let Some(address) = port_name.strip_prefix(TCP_PORT_PREFIX) {
+            TransportDescriptor::Tcp {
+                address: address.to_string(),
+                connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT.as_millis() as u64,
+            }
+        } else if let Some(address) = port_name.strip_prefix(UDP_PORT_PREFIX) {
+            let (device_address, local_port) = match address.split_once(UDP_LOCAL_PORT_OPTION) {
+                Some((device_address, local_port)) => (device_address, local_port.parse().ok()),
+                None => (address, address.rsplit(':').next().and_then(|port| port.parse().ok())),
+            };
+            match local_port {
+                Some(local_port) => TransportDescriptor::Udp { device_address: device_address.to_string(), local_port },
+                // Left to fail when opened, with the whole address in the message
+                None => TransportDescriptor::Udp { device_address: address.to_string(), local_port: 0 },
+            }
+        } else {
+            TransportDescriptor::Serial { port: port_name.to_string(), baud_rate }
+        }
+    }
+
+    pub fn open(&self) -> io::Result<Box<dyn Transport>> {
+        match self {
+            TransportDescriptor::Serial { port, baud_rate } => {
+                let serial = serialport::new(port, *baud_rate)
+                    .timeout(DEFAULT_READ_TIMEOUT)
+                    .open()
+                    .map_err(io::Error::from)?;
+                Ok(Box::new(SerialTransport { port: serial, description: format!("{} @ {}", port, baud_rate) }))
+            }
+            TransportDescriptor::Tcp { address, connect_timeout_ms } => {
+                Ok(Box::new(TcpTransport::connect(address, Duration::from_millis(*connect_timeout_ms))?))
+            }
+            TransportDescriptor::Udp { device_address, local_port } => Ok(Box::new(UdpTransport::open(device_address, *local_port)?)),
+            TransportDescriptor::Simulated => Ok(Box::new(SimulatedTransport::new(SimulatedEsp::new()))),
+            TransportDescriptor::SimulatedLegacy => Ok(Box::new(SimulatedTransport::new(SimulatedEsp::legacy()))),
+            TransportDescriptor::Mock(mock) => mock.open(),
+        }
+    }
+}
+
+impl fmt::Debug for TransportDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportDescriptor::Serial { port, baud_rate } => write!(f, "Serial({} @ {})", port, baud_rate),
+            TransportDescriptor::Tcp { address, connect_timeout_ms } => write!(f, "Tcp({}, timeout {} ms)", address, connect_timeout_ms),
+            TransportDescriptor::Udp { device_address, local_port } => write!(f, "Udp({}, local port {})", device_address, local_port),
+            TransportDescriptor::Simulated => write!(f, "Simulated"),
+            TransportDescriptor::SimulatedLegacy => write!(f, "SimulatedLegacy"),
+            TransportDescriptor::Mock(_) => write!(f, "Mock"),
+        }
+    }
+}
+
+impl fmt::Display for TransportDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportDescriptor::Serial { port, baud_rate } => write!(f, "{} ({} baud)", port, baud_rate),
+            TransportDescriptor::Tcp { address, .. } => write!(f, "{}{}", TCP_PORT_PREFIX, address),
+            TransportDescriptor::Udp { device_address, local_port } => write!(f, "{}{}{}{}", UDP_PORT_PREFIX, device_address, UDP_LOCAL_PORT_OPTION, local_port),
+            TransportDescriptor::Simulated => write!(f, "simulated device"),
+            TransportDescriptor::SimulatedLegacy => write!(f, "simulated device (legacy firmware)"),
+            TransportDescriptor::Mock(_) => write!(f, "mock transport"),
+        }
+    }
+}
+
+// --- Serial ---
+
+pub struct SerialTransport {
+    port: Box<dyn SerialPort>,
+    description: String,
+}
+
+impl Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl Transport for SerialTransport {
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.port.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.port.set_timeout(timeout).map_err(io::Error::from)
+    }
+
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        let port = self.port.try_clone().map_err(io::Error::from)?;
+        Ok(Box::new(SerialTransport { port, description: self.description.clone() }))
+    }
+}
+
+// Serial number of the USB adapter behind a serial port, if the OS reports one
+pub fn usb_serial_number(port_name: &str) -> Option<String> {
+    if !matches!(TransportDescriptor::from_port_name(port_name, 0), TransportDescriptor::Serial { .. }) {
+        return None;
+    }
+    serialport::available_ports().ok()?.into_iter()
+        .find(|port| port.port_name == port_name.trim())
+        .and_then(|port| match port.port_type {
+            serialport::SerialPortType::UsbPort(info) => info.serial_number,
+            _ => None,
+        })
+        .filter(|serial| !serial.trim().is_empty())
+}
+
+// --- TCP ---
+
+pub struct TcpTransport {
+    stream: TcpStream,
+    address: String,
+    timeout: Duration,
+}
+
+fn resolve(address: &str) -> io::Result<SocketAddr> {
+    address
+        .to_socket_addrs()
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not resolve '{}': {}", address, e)))?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Could not resolve '{}'", address)))
+}
+
+impl TcpTransport {
+    pub fn connect(address: &str, connect_timeout: Duration) -> io::Result<Self> {
+        let stream = TcpStream::connect_timeout(&resolve(address)?, connect_timeout)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(DEFAULT_READ_TIMEOUT))?;
+        Ok(Self { stream, address: address.to_string(), timeout: DEFAULT_READ_TIMEOUT })
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            // A closed connection is an error for the worker, not "no data yet"
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed by peer")),
+            // Read timeouts surface as WouldBlock on unix; the worker expects TimedOut like serial ports
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::Error::new(io::ErrorKind::TimedOut, e)),
+            other => other,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn describe(&self) -> String {
+        format!("{}{}", TCP_PORT_PREFIX, self.address)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpTransport { stream: self.stream.try_clone()?, address: self.address.clone(), timeout: self.timeout }))
+    }
+
+    fn keepalive_interval(&self) -> Option<Duration> {
+        Some(NETWORK_KEEPALIVE_INTERVAL)
+    }
+}
+
+// --- UDP ---
+
+// Each write is sent as one datagram to the device; each datagram received from it is
+// returned by read, so the existing line based text protocol works unchanged. The socket is not
+// connected: firmware often sends telemetry from another socket than the one commands go to, so
+// datagrams are accepted from any port of the device's IP address, and others are dropped.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    device: SocketAddr,
+    device_address: String,
+    timeout: Duration,
+}
+
+impl UdpTransport {
+    pub fn open(device_address: &str, local_port: u16) -> io::Result<Self> {
+        let device = resolve(device_address)?;
+        let socket = UdpSocket::bind(("0.0.0.0", local_port))?;
+        socket.set_read_timeout(Some(DEFAULT_READ_TIMEOUT))?;
+        Ok(Self { socket, device, device_address: device_address.to_string(), timeout: DEFAULT_READ_TIMEOUT })
+    }
+}
+
+impl Read for UdpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.socket.recv_from(buf) {
+                Ok((count, from)) if from.ip() == self.device.ip() => return Ok(count),
+                Ok(_) => continue,
+                // ICMP "port unreachable" while the board is offline (reported as a reset on
+                // Windows) is left to the keepalive to judge
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Write for UdpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send_to(buf, self.device)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for UdpTransport {
+    fn describe(&self) -> String {
+        let local_port = self.socket.local_addr().map(|address| address.port()).unwrap_or(0);
+        format!("{}{} (listening on port {})", UDP_PORT_PREFIX, self.device_address, local_port)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(UdpTransport { socket: self.socket.try_clone()?, device: self.device, device_address: self.device_address.clone(), timeout: self.timeout }))
+    }
+
+    fn keepalive_interval(&self) -> Option<Duration> {
+        Some(NETWORK_KEEPALIVE_INTERVAL)
+    }
+}
+
+// --- Simulated device ---
+
+// One simulated device shared by all handles, so the reader and writer see the same state
+#[derive(Clone)]
+pub struct SimulatedTransport {
+    device: Arc<Mutex<SimulatedEsp>>,
+    timeout: Duration,
+}
+
+impl SimulatedTransport {
+    pub fn new(device: SimulatedEsp) -> Self {
+        Self { device: Arc::new(Mutex::new(device)), timeout: DEFAULT_READ_TIMEOUT }
+    }
+}
+
+impl Read for SimulatedTransport {
+    // Same behaviour as SimulatedEsp::read, but the lock is released while waiting
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let next_telemetry = {
+                let mut device = self.device.lock().unwrap();
+                device.poll(Instant::now());
+                let count = device.take_output(buf);
+                if count > 0 {
+                    return Ok(count);
+                }
+                device.next_telemetry_at()
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "simulated read timed out"));
+            }
+            // Wake up regularly so replies to commands written meanwhile are picked up quickly
+            let wait = next_telemetry.min(deadline).saturating_duration_since(now);
+            thread::sleep(wait.min(Duration::from_millis(5)));
+        }
+    }
+}
+
+impl Write for SimulatedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.device.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SimulatedTransport {
+    fn describe(&self) -> String {
+        "simulated device".to_string()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+// --- In-memory mock ---
+
+#[derive(Default)]
+struct MockState {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+    fail_open: Option<io::ErrorKind>,
+    fail_next_read: Option<io::ErrorKind>,
+    fail_next_write: Option<io::ErrorKind>,
+    open_count: u32,
+    keepalive_interval: Option<Duration>,
+}
+
+// In-memory transport for tests. Clones share state, so a test keeps one handle to feed bytes
+// and inspect what the worker wrote while the worker owns the opened transport.
+#[derive(Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+    timeout: Duration,
+}
+
+// Like a serial port's, so a reader waiting for data doesn't spin
+const MOCK_READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self { state: Arc::default(), timeout: MOCK_READ_TIMEOUT }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Transport>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(kind) = state.fail_open {
+            return Err(io::Error::new(kind, "mock open failure"));
+        }
+        state.open_count += 1;
+        Ok(Box::new(self.clone()))
+    }
+
+    // Bytes the device "sends"; returned by subsequent reads
+    pub fn push_rx(&self, bytes: &[u8]) {
+        self.state.lock().unwrap().rx.extend(bytes);
+    }
+
+    // Everything written by the host so far, draining it
+    pub fn take_tx(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state.lock().unwrap().tx)
+    }
+
+    pub fn set_fail_open(&self, kind: Option<io::ErrorKind>) {
+        self.state.lock().unwrap().fail_open = kind;
+    }
+
+    pub fn fail_next_read(&self, kind: io::ErrorKind) {
+        self.state.lock().unwrap().fail_next_read = Some(kind);
+    }
+
+    pub fn fail_next_write(&self, kind: io::ErrorKind) {
+        self.state.lock().unwrap().fail_next_write = Some(kind);
+    }
+
+    pub fn open_count(&self) -> u32 {
+        self.state.lock().unwrap().open_count
+    }
+
+    // Makes the worker ping like it does on a network link
+    pub fn set_keepalive_interval(&self, interval: Option<Duration>) {
+        self.state.lock().unwrap().keepalive_interval = interval;
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(kind) = state.fail_next_read.take() {
+                    return Err(io::Error::new(kind, "mock read failure"));
+                }
+                if !state.rx.is_empty() {
+                    let count = buf.len().min(state.rx.len());
+                    for (slot, byte) in buf.iter_mut().zip(state.rx.drain(..count)) {
+                        *slot = byte;
+                    }
+                    return Ok(count);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "mock read timed out"));
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(kind) = state.fail_next_write.take() {
+            return Err(io::Error::new(kind, "mock write failure"));
+        }
+        state.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn describe(&self) -> String {
+        "mock transport".to_string()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn keepalive_interval(&self) -> Option<Duration> {
+        self.state.lock().unwrap().keepalive_interval
+    }
+}
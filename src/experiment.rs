@@ -0,0 +1,320 @@
+// src/experiment.rs
+
+// Scripted experiment protocols: timed sequences of per-module targets, e.g.
+// "baseline 32°C for 60s, then 10 randomized repetitions of cold L / cold R, 5s each with 30s rest".
+//
+// A protocol (JSON) is a list of blocks. Each block repeats its trials `repetitions` times,
+// optionally in a new random order every repetition; the steps inside a trial keep their order.
+// The order only depends on the seed, so a session can be reproduced exactly.
+//
+// The runner (Controller::start_experiment) logs every step transition and can send OSC markers
+// to /TempSense/marker so a VR scene can stay in sync:
+//   event (start, step, pause, resume, abort, end), block, repetition, trial, step, step index
+
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use rosc::{OscMessage, OscPacket, OscType};
+
+use crate::controller::SafetyLimits;
+
+pub const MARKER_ADDRESS: &str = "/TempSense/marker";
+const LOG_TARGET: &str = "EXPERIMENT";
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Protocol {
+    pub name: String,
+    #[serde(default)]
+    pub seed: Option<u64>, // None picks one at start (and logs it)
+    #[serde(default)]
+    pub markers: Option<String>, // "IP:PORT" to send OSC markers to
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Block {
+    pub name: String,
+    #[serde(default = "one")]
+    pub repetitions: u32,
+    #[serde(default)]
+    pub randomize: bool, // Shuffle the trial order in every repetition
+    pub trials: Vec<Trial>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Trial {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: String,
+    pub duration_secs: f32,
+    #[serde(default)]
+    pub targets: BTreeMap<String, f32>, // Module short name -> °C; other modules keep their target
+}
+
+// One step of the flattened, ordered protocol
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledStep {
+    pub block: String,
+    pub repetition: u32, // 1 based
+    pub trial: String,
+    pub step: String,
+    pub duration: Duration,
+    pub targets: BTreeMap<String, f32>,
+}
+
+impl Protocol {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| format!("Invalid protocol: {}", e))
+    }
+
+    pub fn load_file(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        Self::from_json(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Checks the protocol against the modules it will drive. Reports every problem, like Config::validate.
+    pub fn validate(&self, module_names: &[String], limits: &SafetyLimits) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.blocks.is_empty() {
+            errors.push("blocks: at least one block is required".to_string());
+        }
+        if let Some(markers) = &self.markers {
+            if markers.parse::<std::net::SocketAddr>().is_err() {
+                errors.push(format!("markers: '{}' is not an IP:PORT address", markers));
+            }
+        }
+        for (b, block) in self.blocks.iter().enumerate() {
+            if block.repetitions == 0 {
+                errors.push(format!("blocks[{}].repetitions: must be at least 1", b));
+            }
+            if block.trials.is_empty() {
+                errors.push(format!("blocks[{}].trials: at least one trial is required", b));
+            }
+            for (t, trial) in block.trials.iter().enumerate() {
+                if trial.steps.is_empty() {
+                    errors.push(format!("blocks[{}].trials[{}].steps: at least one step is required", b, t));
+                }
+                for (s, step) in trial.steps.iter().enumerate() {
+                    let path = format!("blocks[{}].trials[{}].steps[{}]", b, t, s);
+                    if !(step.duration_secs.is_finite() && step.duration_secs > 0.0) {
+                        errors.push(format!("{}.duration_secs: must be greater than 0", path));
+                    }
+                    for (module, &temp) in &step.targets {
+                        if !module_names.contains(module) {
+                            errors.push(format!("{}.targets: no module named '{}'", path, module));
+                        }
+                        if limits.clamp(temp) != temp {
+                            errors.push(format!("{}.targets.{}: {}°C is outside the safety limits [{}, {}]", path, module, temp, limits.value_min, limits.value_max));
+                        }
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid protocol '{}':\n  {}", self.name, errors.join("\n  ")))
+        }
+    }
+
+    // The order steps run in for `seed`
+    pub fn schedule(&self, seed: u64) -> Vec<ScheduledStep> {
+        let mut rng = SplitMix64(seed);
+        let mut schedule = Vec::new();
+        for block in &self.blocks {
+            for repetition in 1..=block.repetitions {
+                let mut order: Vec<usize> = (0..block.trials.len()).collect();
+                if block.randomize {
+                    rng.shuffle(&mut order);
+                }
+                for trial in order.into_iter().map(|index| &block.trials[index]) {
+                    for step in &trial.steps {
+                        schedule.push(ScheduledStep {
+                            block: block.name.clone(),
+                            repetition,
+                            trial: trial.name.clone(),
+                            step: step.name.clone(),
+                            duration: Duration::from_secs_f32(step.duration_secs),
+                            targets: step.targets.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        schedule
+    }
+
+    // Modules the protocol sets targets for
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<String> = self.blocks.iter()
+            .flat_map(|block| &block.trials)
+            .flat_map(|trial| &trial.steps)
+            .flat_map(|step| step.targets.keys().cloned())
+            .collect();
+        modules.sort();
+        modules.dedup();
+        modules
+    }
+}
+
+// Small, well known PRNG so schedules don't depend on a crate's algorithm choice
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Fisher-Yates
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// What the controller has to do after ExperimentRun::poll
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExperimentEvent {
+    Step(usize), // Apply the targets of schedule[index]
+    Finished,
+}
+
+// A protocol being executed, see Controller::start_experiment
+pub struct ExperimentRun {
+    pub protocol: Protocol,
+    pub seed: u64,
+    pub schedule: Vec<ScheduledStep>,
+    current: Option<usize>, // None before the first poll
+    step_started: Instant,
+    paused_at: Option<Instant>,
+    markers: Option<(UdpSocket, String)>,
+}
+
+impl ExperimentRun {
+    pub fn new(protocol: Protocol, seed: u64) -> Self {
+        let markers = protocol.markers.clone().and_then(|addr| match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => Some((socket, addr)),
+            Err(e) => {
+                log::warn!(target: LOG_TARGET, "OSC markers disabled: {}", e);
+                None
+            }
+        });
+        let schedule = protocol.schedule(seed);
+        Self { protocol, seed, schedule, current: None, step_started: Instant::now(), paused_at: None, markers }
+    }
+
+    // A seed for protocols that don't set one
+    pub fn random_seed() -> u64 {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+    }
+
+    pub fn current(&self) -> Option<(usize, &ScheduledStep)> {
+        self.current.map(|index| (index, &self.schedule[index]))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    // Time spent in the current step, not counting pauses
+    pub fn step_elapsed(&self) -> Duration {
+        self.paused_at.unwrap_or_else(Instant::now).saturating_duration_since(self.step_started)
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        self.schedule.iter().map(|step| step.duration).sum()
+    }
+
+    // Advances to the next step when the current one is over
+    pub fn poll(&mut self, now: Instant) -> Option<ExperimentEvent> {
+        if self.paused_at.is_some() {
+            return None;
+        }
+        let next = match self.current {
+            None => {
+                self.marker("start", None);
+                0
+            }
+            Some(index) if now.saturating_duration_since(self.step_started) >= self.schedule[index].duration => index + 1,
+            Some(_) => return None,
+        };
+        if next >= self.schedule.len() {
+            self.marker("end", None);
+            log::info!(target: LOG_TARGET, "Protocol '{}' finished", self.protocol.name);
+            return Some(ExperimentEvent::Finished);
+        }
+        // Steps start back to back so rounding doesn't add up over a long protocol
+        self.step_started = match self.current {
+            Some(index) => self.step_started + self.schedule[index].duration,
+            None => now,
+        };
+        self.current = Some(next);
+        let step = &self.schedule[next];
+        log::info!(target: LOG_TARGET, "Step {}/{}: {} #{} / {} / {} ({:.1}s) {:?}",
+            next + 1, self.schedule.len(), step.block, step.repetition, step.trial, step.step, step.duration.as_secs_f32(), step.targets);
+        self.marker("step", Some(next));
+        Some(ExperimentEvent::Step(next))
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+            log::info!(target: LOG_TARGET, "Protocol '{}' paused", self.protocol.name);
+            self.marker("pause", self.current);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.step_started += paused_at.elapsed();
+            log::info!(target: LOG_TARGET, "Protocol '{}' resumed", self.protocol.name);
+            self.marker("resume", self.current);
+        }
+    }
+
+    pub fn abort(&mut self) {
+        log::warn!(target: LOG_TARGET, "Protocol '{}' aborted", self.protocol.name);
+        self.marker("abort", self.current);
+    }
+
+    fn marker(&self, event: &str, index: Option<usize>) {
+        let Some((socket, addr)) = &self.markers else { return };
+        let step = index.map(|index| &self.schedule[index]);
+        let packet = OscPacket::Message(OscMessage {
+            addr: MARKER_ADDRESS.to_string(),
+            args: vec![
+                OscType::String(event.to_string()),
+                OscType::String(step.map(|s| s.block.clone()).unwrap_or_default()),
+                OscType::Int(step.map_or(0, |s| s.repetition as i32)),
+                OscType::String(step.map(|s| s.trial.clone()).unwrap_or_default()),
+                OscType::String(step.map(|s| s.step.clone()).unwrap_or_default()),
+                OscType::Int(index.map_or(-1, |index| index as i32)),
+            ],
+        });
+        let result = rosc::encoder::encode(&packet)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| socket.send_to(&bytes, addr).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!(target: LOG_TARGET, "Failed to send '{}' marker to {}: {}", event, addr, e);
+        }
+    }
+}
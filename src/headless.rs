@@ -0,0 +1,161 @@
+// src/headless.rs
+
+// `--headless`: the OSC-to-ESP bridge without the egui window, for lab machines.
+// Uses the settings the GUI persisted, logs to stdout and stops every module on SIGINT/SIGTERM.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config};
+use crate::profiles;
+use crate::controller::Controller;
+use crate::logging::{self, LogBuffer};
+use crate::osc::spawn_osc_listener;
+use crate::scripting::Script;
+
+// Exit codes of the bridge and the command line operations (see cli.rs), so scripts can tell a
+// missing board from a silent one, or from bad settings
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_DEVICE_ERROR: i32 = 1; // Port could not be opened, link dropped, I/O error
+pub const EXIT_USAGE: i32 = 2; // Unknown subcommand, bad arguments or invalid settings
+pub const EXIT_TIMEOUT: i32 = 3; // Device connected but did not answer in time
+
+// A module that dropped its connection is retried after this long
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const LOOP_TICK: Duration = Duration::from_millis(50);
+
+// Loads the settings the GUI persisted through eframe (app.ron, see profiles::from_storage):
+// the profile named `profile`, or the active one. Defaults when the GUI never saved anything;
+// an error when the stored settings are invalid or the profile does not exist.
+pub fn load_persisted_config(app_id: &str, profile: Option<&str>) -> Result<Config, String> {
+    let Some(path) = eframe::storage_dir(app_id).map(|dir| dir.join("app.ron")) else {
+        log::warn!("No storage directory for '{}', using default settings.", app_id);
+        return default_profile_config(profile);
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::warn!("No saved settings at {}, using default settings.", path.display());
+            return default_profile_config(profile);
+        }
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    let stored: HashMap<String, String> = ron::from_str(&text).map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+    let stored_profiles = profiles::from_storage(
+        stored.get(profiles::STORAGE_KEY).cloned(),
+        stored.get(config::STORAGE_KEY).cloned(),
+        stored.get(eframe::APP_KEY).cloned(),
+    ).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let Some(stored_profiles) = stored_profiles else {
+        return default_profile_config(profile);
+    };
+    let index = match profile {
+        Some(name) => stored_profiles.find(name).ok_or_else(|| {
+            let names: Vec<&str> = stored_profiles.profiles().iter().map(|p| p.name.as_str()).collect();
+            format!("No profile named '{}' (saved profiles: {})", name, names.join(", "))
+        })?,
+        None => stored_profiles.active_index(),
+    };
+    let selected = &stored_profiles.profiles()[index];
+    log::info!("Loaded profile '{}' from {}", selected.name, path.display());
+    Ok(selected.config.clone())
+}
+
+fn default_profile_config(profile: Option<&str>) -> Result<Config, String> {
+    match profile {
+        Some(name) if name != profiles::DEFAULT_PROFILE_NAME => Err(format!("No profile named '{}'", name)),
+        _ => Ok(Config::default()),
+    }
+}
+
+fn spawn_signal_handler(shutdown: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            #[cfg(unix)]
+            {
+                let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => log::info!("SIGINT received, shutting down."),
+                    _ = sigterm.recv() => log::info!("SIGTERM received, shutting down."),
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                tokio::signal::ctrl_c().await.ok();
+                log::info!("Ctrl-C received, shutting down.");
+            }
+        });
+        shutdown.store(true, Ordering::SeqCst);
+    });
+}
+
+// Headless and CLI output goes to stdout, at info level unless RUST_LOG says otherwise.
+// The returned buffer can mirror the log to a file (LogBuffer::set_file).
+pub fn init_logging() -> LogBuffer {
+    let mut console = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    console.target(env_logger::Target::Stdout);
+    logging::init(console)
+}
+
+// Runs the bridge until SIGINT/SIGTERM. Returns the process exit code: EXIT_USAGE for invalid
+// settings, EXIT_DEVICE_ERROR when the outlet can't be started.
+// Expects logging to be set up by the caller (see init_logging). With a script, OSC input is
+// mapped to targets by it (see scripting.rs). Each module stays inactive until it has a target
+// (Controller::activate_targeted), or with `start` all are activated as soon as their link is up.
+pub fn run(config: Config, script: Option<Script>, start: bool) -> i32 {
+    let mut controller = Controller::default();
+    if let Err(e) = config.validate().and_then(|()| config.apply_to(&mut controller)) {
+        log::error!("{}", e);
+        return EXIT_USAGE;
+    }
+    controller.set_script(script);
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    spawn_signal_handler(Arc::clone(&shutdown));
+
+    let osc_addr = format!("{}:{}", config.osc.ip, config.osc.port);
+    log::info!("Starting OSC listener on {}", osc_addr);
+    controller.set_osc_receiver(spawn_osc_listener(&osc_addr));
+    if config.outlet.enabled {
+        if let Err(e) = controller.start_outlet() {
+            log::error!("{}", e);
+            return EXIT_DEVICE_ERROR;
+        }
+    }
+
+    if start {
+        log::info!("Modules are activated as soon as their link is up (--start).");
+        controller.is_running = true;
+    } else {
+        log::info!("Modules stay inactive until they receive a target (--start activates them on connect).");
+    }
+    let mut last_connect_attempt: Vec<Option<Instant>> = vec![None; controller.modules.len()];
+
+    while !shutdown.load(Ordering::SeqCst) {
+        controller.tick();
+        if !controller.osc_connected() {
+            log::error!("OSC listener stopped.");
+            break;
+        }
+        if !start {
+            controller.activate_targeted();
+        }
+        for (index, last_attempt) in last_connect_attempt.iter_mut().enumerate() {
+            if !controller.modules[index].is_worker_running() && last_attempt.is_none_or(|t| t.elapsed() >= RECONNECT_DELAY) {
+                *last_attempt = Some(Instant::now());
+                controller.connect(index);
+            }
+        }
+        thread::sleep(LOOP_TICK);
+    }
+
+    // Deactivates the peltiers before closing the links
+    log::info!("Stopping modules...");
+    controller.shutdown();
+    log::info!("Bye.");
+    EXIT_OK
+}
@@ -1,5 +1,51 @@
 #![warn(clippy::all, rust_2018_idioms)]
+#![allow(non_snake_case)] // The crate keeps its TempSenseGUI name
+
+//! TempSense control library.
+//!
+//! - [`config`]: versioned configuration files (import/export, migration, validation).
+//! - [`profiles`]: named configurations for different rigs and experiments.
+//! - [`controller`]: modules, targets, safety limits and the ESP links behind them
+//!   ([`Controller`] is what the GUI and the headless bridge drive).
+//! - [`calibration`]: per device skin sensor corrections fitted against a reference thermometer.
+//! - [`comfort`]: perceived-temperature mode, sensations from -1 to +1 relative to the skin temperature.
+//! - [`spatial`]: module positions on a body-part diagram and the `/TempSense/point` input.
+//! - [`pid`]: remote tuning of the firmware's PID loops, gain sets and step tests.
+//! - [`waveform`]: step/ramp/sine/square/pulse stimuli that drive a module's target.
+//! - [`experiment`]: timed, optionally randomized experiment protocols with OSC markers.
+//! - [`sync`]: START/STOP scheduled for the same instant on every module, and the skew achieved (estimated on the host).
+//! - [`esp_comm`]: device connection (transports, wire protocol, worker thread), telemetry and PID gain types, device clock sync and firmware updates.
+//! - [`console`]: the raw per module device console (RX/TX stream, history, hex view).
+//! - [`osc`]: the OSC input used to set targets from Unity/Max/etc.
+//! - [`outlet`]: LSL style JSON-lines stream of telemetry and event markers for recording alongside other signals.
+//! - [`scripting`]: Rhai scripts mapping OSC input and telemetry to targets.
+//! - [`logging`]: the structured application log shown in the GUI and optionally written to a file.
+//! - [`headless`]: the complete OSC-to-ESP bridge without a window.
+//! - [`TemplateApp`]: the egui front end.
+//!
+//! See `examples/` for embedding the controller in another program.
 
 mod app;
 pub use app::TemplateApp;
+pub mod calibration;
+pub mod comfort;
+pub mod config;
+pub mod console;
+pub mod controller;
 pub mod esp_comm;
+pub mod experiment;
+pub mod headless;
+pub mod logging;
+pub mod osc;
+pub mod outlet;
+pub mod pid;
+pub mod profiles;
+pub mod scripting;
+pub mod spatial;
+pub mod sync;
+pub mod waveform;
+
+pub use controller::{Controller, Module, SafetyLimits};
+pub use esp_comm::telemetry::Telemetry;
+pub use esp_comm::transport::TransportDescriptor;
+pub use esp_comm::{EspCommand, EspStatus, RawChunk, RawDirection, WireProtocol};
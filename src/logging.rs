@@ -0,0 +1,235 @@
+// src/logging.rs
+
+// Application event log. Everything goes through the `log` crate; AppLogger forwards records to
+// the console (env_logger, RUST_LOG as usual) and keeps structured entries in a LogBuffer for
+// the GUI log page, optionally mirrored to a rotating log file.
+// Raw telemetry is not logged here, see Controller::telemetry_log.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+const MAX_ENTRIES: usize = 5000;
+
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+pub const DEFAULT_MAX_FILES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub level: Level,
+    pub source: String, // "APP", "OSC", "ESP L", or the last path segment of a module target
+    pub message: String,
+}
+
+impl LogEntry {
+    // One line, as written to the log file and copied to the clipboard
+    pub fn format(&self) -> String {
+        format!("{} {:<5} [{}] {}", self.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"), self.level, self.source, self.message)
+    }
+}
+
+// What the log page shows; empty strings match everything
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    pub min_level: Level,
+    pub source: String,
+    pub search: String, // Case insensitive, in source and message
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self { min_level: Level::Info, source: String::new(), search: String::new() }
+    }
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if entry.level > self.min_level {
+            return false;
+        }
+        if !self.source.is_empty() && entry.source != self.source {
+            return false;
+        }
+        let search = self.search.trim().to_lowercase();
+        search.is_empty()
+            || entry.message.to_lowercase().contains(&search)
+            || entry.source.to_lowercase().contains(&search)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub max_bytes: u64,   // Rotate once the file grows past this
+    pub max_files: usize, // Rotated files kept next to it (path.1 is the newest)
+}
+
+impl LogFileConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), max_bytes: DEFAULT_MAX_FILE_BYTES, max_files: DEFAULT_MAX_FILES }
+    }
+}
+
+struct LogFile {
+    config: LogFileConfig,
+    file: File,
+    written: u64,
+}
+
+impl LogFile {
+    fn open(config: LogFileConfig) -> Result<Self, String> {
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)
+            .map_err(|e| format!("Can't open log file {}: {}", config.path.display(), e))?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self { config, file, written })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 + 1 > self.config.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    // path -> path.1 -> path.2 ...; the oldest one is dropped
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            self.file = File::create(path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(path, self.config.max_files));
+            for n in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(path, n + 1))?;
+                }
+            }
+            fs::rename(path, rotated_path(path, 1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: VecDeque<LogEntry>,
+    file: Option<LogFile>,
+}
+
+// Shared between the logger and whoever displays the log; cheap to clone
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl LogBuffer {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // A panic while logging must not take the log down with it
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn push(&self, entry: LogEntry) {
+        let mut inner = self.lock();
+        if let Some(file) = &mut inner.file {
+            if let Err(e) = file.write_line(&entry.format()) {
+                eprintln!("Log file disabled: {}", e);
+                inner.file = None;
+            }
+        }
+        inner.entries.push_back(entry);
+        if inner.entries.len() > MAX_ENTRIES {
+            inner.entries.pop_front();
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<LogEntry> {
+        self.lock().entries.iter().cloned().collect()
+    }
+
+    // The last `count` entries, oldest first
+    pub fn recent(&self, count: usize) -> Vec<LogEntry> {
+        let inner = self.lock();
+        inner.entries.iter().skip(inner.entries.len().saturating_sub(count)).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    // Starts (or stops, with None) mirroring new entries to a file
+    pub fn set_file(&self, config: Option<LogFileConfig>) -> Result<(), String> {
+        let file = config.map(LogFile::open).transpose()?;
+        self.lock().file = file;
+        Ok(())
+    }
+
+    pub fn file(&self) -> Option<LogFileConfig> {
+        self.lock().file.as_ref().map(|file| file.config.clone())
+    }
+}
+
+struct AppLogger {
+    console: env_logger::Logger,
+    buffer: LogBuffer,
+}
+
+impl AppLogger {
+    // Debug and up from this crate (including "APP", "OSC", "ESP L" targets), warnings from dependencies
+    fn captures(metadata: &Metadata<'_>) -> bool {
+        let target = metadata.target();
+        let ours = !target.contains("::") || target.starts_with("TempSenseGUI");
+        metadata.level() <= if ours { Level::Debug } else { Level::Warn }
+    }
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.console.enabled(metadata) || Self::captures(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if Self::captures(record.metadata()) {
+            self.buffer.push(LogEntry {
+                timestamp: chrono::Local::now(),
+                level: record.level(),
+                source: record.target().rsplit("::").next().unwrap_or_default().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+    }
+}
+
+// Installs the application logger. `console` decides what reaches stderr/stdout, the returned
+// buffer receives the structured entries. Only the first call installs anything.
+pub fn init(mut console: env_logger::Builder) -> LogBuffer {
+    let console = console.build();
+    let max_level = console.filter().max(LevelFilter::Debug);
+    let buffer = LogBuffer::default();
+    let logger = AppLogger { console, buffer: buffer.clone() };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+    buffer
+}
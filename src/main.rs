@@ -1,26 +1,39 @@
 #![warn(clippy::all, rust_2018_idioms)]
+#![allow(non_snake_case)] // The crate keeps its TempSenseGUI name
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-// When compiling natively:
-#[cfg(not(target_arch = "wasm32"))]
-
-
-mod osc;
-mod app;
-mod esp_comm; 
-use crate::osc::osc_listener;
-use std::sync::mpsc::{self};
+mod cli;
+use TempSenseGUI::{TemplateApp, logging};
+
+// Also the eframe app id, which decides where settings are persisted
+const APP_NAME: &str = "TempSense GUI v0.3";
+
+// A release build has no console of its own on Windows (see windows_subsystem above), so the
+// command line operations write to the one of the shell they were started from. Fails, harmlessly,
+// when there is none.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
 
 fn main() -> eframe::Result {
-    env_logger::init();
-    
-    let (sender, receiver) = mpsc::channel();
-    
-    // spawn osc listener in a separate thread
-    std::thread::spawn(move || {
-        let runtime = tokio::runtime::Runtime::new().unwrap();
-        runtime.block_on(osc_listener("127.0.0.1:9000", sender));
-    });
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    #[cfg(windows)]
+    if !args.is_empty() {
+        attach_parent_console();
+    }
+    if let Some(exit_code) = cli::run(&args, APP_NAME) {
+        std::process::exit(exit_code);
+    }
+
+    let log = logging::init(env_logger::Builder::from_default_env());
     
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -33,19 +46,13 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
 
-    let app = app::TemplateApp {
-        osc_receiver: receiver,
-        ..Default::default()
-    };
-    
     eframe::run_native(
-        "TempSense GUI v0.3",
+        APP_NAME,
         native_options,
         Box::new(|cc| {
 
-            let app = app;
-            let mut default_app = app::TemplateApp::new(cc);
-            default_app.osc_receiver = app.osc_receiver;
+            let mut default_app = TemplateApp::new(cc, log);
+            default_app.start_osc_listener(); // On the address from the active profile
             
             Ok(Box::new(default_app))
         }),
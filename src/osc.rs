@@ -1,73 +1,123 @@
 // osc.rs
 use std::net::{SocketAddrV4, UdpSocket};
 use std::str::FromStr;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Receiver, Sender};
 use rosc::{OscPacket, OscType};
 
-pub async fn osc_listener(addr: &str, sender: Sender<(i8, i8)>) {
-    let usage = format!("Usage: {} IP:PORT", addr);
+use crate::spatial::{self, SpatialPoint};
 
+// What the listener forwards to the controller
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscInput {
+    Target(i8, f32),    // /PeltN: peltier id, target °C
+    Sensation(i8, f32), // /SensationN: peltier id, perceived temperature -1..+1 (see comfort.rs)
+    Point(SpatialPoint), // /TempSense/point: a heat or cold source on the body (see spatial.rs)
+    Value(String, f32), // Any other address with a float argument (see scripting.rs)
+}
+
+// Listens for OSC float messages on `addr` (IP:PORT) and forwards them as OscInput.
+// Returns when the socket fails or the receiving side of `sender` is dropped.
+pub async fn osc_listener(addr: &str, sender: Sender<OscInput>) {
     let socket_addr = match SocketAddrV4::from_str(addr) {
         Ok(addr) => addr,
         Err(_) => {
-            eprintln!("{}", usage);
-            std::process::exit(1);
+            log::error!(target: "OSC", "Invalid OSC address '{}', expected IP:PORT", addr);
+            return;
         }
     };
 
-    let sock = UdpSocket::bind(socket_addr).unwrap();
-    println!("Listening on {}", socket_addr);
+    let sock = match UdpSocket::bind(socket_addr) {
+        Ok(sock) => sock,
+        Err(e) => {
+            log::error!(target: "OSC", "Could not listen on {}: {}", socket_addr, e);
+            return;
+        }
+    };
+    log::info!(target: "OSC", "Listening on {}", socket_addr);
 
     let mut buf = [0u8; rosc::decoder::MTU];
 
     loop {
         match sock.recv_from(&mut buf) {
             Ok((size, sender_addr)) => {
-                println!("Received packet with size {} from: {}", size, sender_addr);
+                log::debug!(target: "OSC", "Received packet with size {} from: {}", size, sender_addr);
                 if let Ok((_, packet)) = rosc::decoder::decode_udp(&buf[..size]) {
-                    handle_packet(packet, &sender);
+                    if !handle_packet(packet, &sender) {
+                        break; // Nobody is listening anymore
+                    }
                 } else {
-                    eprintln!("Failed to decode OSC packet");
+                    log::warn!(target: "OSC", "Failed to decode OSC packet");
                 }
             }
             Err(e) => {
-                eprintln!("Error receiving from socket: {}", e);
+                log::error!(target: "OSC", "Error receiving from socket: {}", e);
                 break;
             }
         }
     }
 }
 
-fn handle_packet(packet: OscPacket, sender: &Sender<(i8, i8)>) {
+// Runs osc_listener on its own thread and runtime. The receiver reports Disconnected once the
+// listener has stopped (e.g. the address could not be bound).
+pub fn spawn_osc_listener(addr: &str) -> Receiver<OscInput> {
+    let (sender, receiver) = mpsc::channel();
+    let addr = addr.to_string();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(osc_listener(&addr, sender));
+    });
+    receiver
+}
+
+// Returns false once the receiver is gone
+fn handle_packet(packet: OscPacket, sender: &Sender<OscInput>) -> bool {
     match packet {
         OscPacket::Message(msg) => {
-            println!("OSC address: {}", msg.addr);
-            if let Some(OscType::Float(value)) = msg.args.first() {
-                println!("OSC Value: {}", value);
-                let int_value = (*value * 100.0) as i8; // Convert f32 to i8 FOR TESTING. if we use ints, this needs to be updated. TODO:
-                let id: i8; // Peltier id. We are only using Pelt1 and Pelt2 for now. - David
-                let addr_str = msg.addr.as_str();
-                match addr_str {
-                    "/Pelt1" => id = 0,
-                    "/Pelt2" => id = 1,
-                    "/Pelt3" => id = 2,
-                    "/Pelt4" => id = 3,
-                    "/Pelt5" => id = 4,
-                    "/Pelt6" => id = 5,
-                    "/Pelt7" => id = 6,
-                    "/Pelt8" => id = 7,
-                    _      => {
-                        println!("[osc.rs] WARNING: Address '{}' did not match specific /PeltX. Defaulting id to 0.", addr_str);
-                        id = 0;
+            log::debug!(target: "OSC", "OSC address: {}", msg.addr);
+            if msg.addr == spatial::POINT_ADDRESS {
+                let args: Vec<f32> = msg.args.iter().filter_map(|arg| match arg {
+                    OscType::Float(value) => Some(*value),
+                    OscType::Double(value) => Some(*value as f32),
+                    OscType::Int(value) => Some(*value as f32),
+                    _ => None,
+                }).collect();
+                return match SpatialPoint::from_args(&args) {
+                    Ok(point) => sender.send(OscInput::Point(point)).is_ok(),
+                    Err(e) => {
+                        log::warn!(target: "OSC", "{}", e);
+                        true
                     }
+                };
+            }
+            if let Some(OscType::Float(value)) = msg.args.first() {
+                log::debug!(target: "OSC", "OSC Value: {}", value);
+                // Sensations are sent as they are, /Sensation1 to /Sensation8
+                if let Some(n) = msg.addr.strip_prefix("/Sensation").and_then(|n| n.parse::<i8>().ok()).filter(|n| (1..=8).contains(n)) {
+                    return sender.send(OscInput::Sensation(n - 1, *value)).is_ok();
                 }
+                // Scenes send hundredths of the target: 0.205 is 20.5°C
+                let temp = *value * 100.0;
+                let addr_str = msg.addr.as_str();
+                // Peltier id. We are only using Pelt1 and Pelt2 for now. - David
+                let id: i8 = match addr_str {
+                    "/Pelt1" => 0,
+                    "/Pelt2" => 1,
+                    "/Pelt3" => 2,
+                    "/Pelt4" => 3,
+                    "/Pelt5" => 4,
+                    "/Pelt6" => 5,
+                    "/Pelt7" => 6,
+                    "/Pelt8" => 7,
+                    // Without a mapping script the controller sends these to peltier 0
+                    _ => return sender.send(OscInput::Value(msg.addr.clone(), *value)).is_ok(),
+                };
 
-                let address_msg_tuple: (i8, i8) = (id, int_value);
-                sender.send(address_msg_tuple).unwrap(); 
+                return sender.send(OscInput::Target(id, temp)).is_ok();
             }
         }
         OscPacket::Bundle(bundle) => {
-            println!("OSC Bundle: {:?}", bundle);
+            log::debug!(target: "OSC", "OSC Bundle: {:?}", bundle);
         }
     }
-}
\ No newline at end of file
+    true
+}
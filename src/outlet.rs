@@ -0,0 +1,205 @@
+// src/outlet.rs
+
+// Streaming outlet for recording thermal stimuli next to EEG, eye tracking etc., in the spirit of
+// a Lab Streaming Layer outlet but without the native library: a TCP server (localhost by
+// default) writing one JSON object per line to every connected client.
+//
+//   {"type":"header","stream":"TempSense","format":1,"modules":["L","R"],"clock_zero_unix":1760781234.5}
+//   {"type":"sample","t":12.345678,"unix":1760781246.845678,"module":"L","target":20.0,"device_target":20.0,"skin":31.84,"device_time":true}
+//   {"type":"marker","t":13.0,"unix":1760781247.5,"marker":"START","module":null}
+//
+// The header is sent once per connection. `t` is seconds on the host clock, the monotonic clock
+// device timestamps are converted to (see esp_comm::clock), and `unix` the same instant as wall
+// clock time; `device_time` says whether a sample was stamped by the device or on arrival.
+// `target` is what the controller asks for, `device_target` what the module reported. Markers:
+//   START, STOP               stamped when the command was due at the modules (see sync.rs)
+//   OVERRIDE_ON/OVERRIDE_OFF  manual override of one module
+//   SKIN_ALARM/SKIN_OK        skin temperature left/reentered the skin limits
+//   STEP <n>, EXPERIMENT_END  experiment protocol progress
+// Clients that fall behind or disconnect are dropped, the controller never waits for them.
+
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::esp_comm::clock;
+
+pub const DEFAULT_OUTLET_ADDRESS: &str = "127.0.0.1:16600";
+pub const STREAM_NAME: &str = "TempSense";
+pub const STREAM_FORMAT: u32 = 1; // Bumped when fields change meaning
+const LOG_TARGET: &str = "OUTLET";
+// How often new clients are accepted when nothing is streamed
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+// A client that can't take a line within this long is dropped
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutletSettings {
+    pub enabled: bool, // Started with the GUI or bridge
+    pub address: String, // "IP:PORT" to listen on
+}
+
+impl Default for OutletSettings {
+    fn default() -> Self {
+        Self { enabled: false, address: DEFAULT_OUTLET_ADDRESS.to_string() }
+    }
+}
+
+impl OutletSettings {
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self) -> Vec<String> {
+        match self.address.parse::<SocketAddr>() {
+            Ok(_) => Vec::new(),
+            Err(_) => vec![format!("address: '{}' is not an IP:PORT address", self.address)],
+        }
+    }
+}
+
+// One line of the stream
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutletEvent {
+    Header {
+        stream: String,
+        format: u32,
+        modules: Vec<String>, // Short names
+        clock_zero_unix: f64, // Wall clock at t = 0
+    },
+    Sample {
+        t: f64,
+        unix: f64,
+        module: String,
+        target: f32,
+        device_target: Option<f32>,
+        skin: Option<f32>, // Calibrated
+        device_time: bool,
+    },
+    Marker {
+        t: f64,
+        unix: f64,
+        marker: String,
+        module: Option<String>, // None for all modules
+    },
+}
+
+// The host clock now, what markers without a better instant are stamped with
+pub fn host_now_us() -> i64 {
+    clock::host_micros(Instant::now()) as i64
+}
+
+// Host clock of `host_us` as (t, unix)
+fn times(host_us: i64) -> (f64, f64) {
+    (host_us as f64 / 1e6, clock::host_datetime(host_us).timestamp_micros() as f64 / 1e6)
+}
+
+impl OutletEvent {
+    pub fn header(modules: Vec<String>) -> Self {
+        OutletEvent::Header { stream: STREAM_NAME.to_string(), format: STREAM_FORMAT, modules, clock_zero_unix: times(0).1 }
+    }
+
+    pub fn sample(host_us: i64, module: &str, target: f32, device_target: Option<f32>, skin: Option<f32>, device_time: bool) -> Self {
+        let (t, unix) = times(host_us);
+        OutletEvent::Sample { t, unix, module: module.to_string(), target, device_target, skin, device_time }
+    }
+
+    pub fn marker(host_us: i64, marker: &str, module: Option<&str>) -> Self {
+        let (t, unix) = times(host_us);
+        OutletEvent::Marker { t, unix, marker: marker.to_string(), module: module.map(str::to_string) }
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("outlet events serialize");
+        line.push('\n');
+        line
+    }
+}
+
+// The running server. Dropping it closes the listener and every client.
+pub struct Outlet {
+    address: SocketAddr,
+    sender: Option<Sender<OutletEvent>>,
+    clients: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Outlet {
+    // Listens on `address` (port 0 picks a free one, see local_addr). Clients get a header naming
+    // `modules` when they connect.
+    pub fn bind(address: &str, modules: Vec<String>) -> Result<Self, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+        let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let (sender, receiver) = mpsc::channel::<OutletEvent>();
+        let clients = Arc::new(AtomicUsize::new(0));
+        let client_count = Arc::clone(&clients);
+        let header = OutletEvent::header(modules).to_line();
+        let handle = thread::spawn(move || {
+            let mut streams: Vec<(TcpStream, SocketAddr)> = Vec::new();
+            loop {
+                let line = match receiver.recv_timeout(ACCEPT_POLL) {
+                    Ok(event) => Some(event.to_line()),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                while let Ok((stream, peer)) = listener.accept() {
+                    let setup = stream.set_nonblocking(false)
+                        .and_then(|()| stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)))
+                        .and_then(|()| stream.set_nodelay(true))
+                        .and_then(|()| (&stream).write_all(header.as_bytes()));
+                    match setup {
+                        Ok(()) => {
+                            log::info!(target: LOG_TARGET, "Client {} connected", peer);
+                            streams.push((stream, peer));
+                        }
+                        Err(e) => log::warn!(target: LOG_TARGET, "Client {} dropped: {}", peer, e),
+                    }
+                }
+                if let Some(line) = line {
+                    streams.retain(|(stream, peer)| {
+                        let mut writer = stream;
+                        match writer.write_all(line.as_bytes()) {
+                            Ok(()) => true,
+                            Err(e) => {
+                                log::info!(target: LOG_TARGET, "Client {} disconnected: {}", peer, e);
+                                false
+                            }
+                        }
+                    });
+                }
+                client_count.store(streams.len(), Ordering::Relaxed);
+            }
+        });
+        log::info!(target: LOG_TARGET, "Streaming telemetry and markers on {}", local_addr);
+        Ok(Self { address: local_addr, sender: Some(sender), clients, handle: Some(handle) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Clients connected as of the last line sent
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::Relaxed)
+    }
+
+    pub fn push(&self, event: OutletEvent) {
+        if let Some(sender) = &self.sender {
+            sender.send(event).ok();
+        }
+    }
+}
+
+impl Drop for Outlet {
+    fn drop(&mut self) {
+        self.sender = None; // Ends the server thread
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+        log::info!(target: LOG_TARGET, "Stopped streaming on {}", self.address);
+    }
+}
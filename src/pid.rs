@@ -0,0 +1,96 @@
+// src/pid.rs
+
+// Remote tuning of the firmware's heating and cooling PID loops. The protocol:
+//   getPid heat                  -> PID:heat kp=12,ki=0.5,kd=0,min=0,max=100,deadband=0.1
+//   setPid heat kp=12,ki=0.5,... -> OK setPid heat (takes effect immediately, lost on restart)
+//   savePid                      -> OK savePid (writes both loops to flash)
+// `min`/`max` bound the loop's output in %, errors within `deadband` °C of the target count as 0.
+// Firmware without remote tuning answers ERR. Named gain sets are saved with the config, and a
+// step test records the response to a target step from telemetry (Controller::start_step_test).
+
+use std::time::{Duration, Instant};
+
+use crate::esp_comm::telemetry::Telemetry;
+
+pub use crate::esp_comm::pid_gains::{parse_reply, read_command, write_command, PidGains, PidLoop, PidSettings, PID_REQUEST, PID_SAVE, PID_WRITE};
+
+// A step response has settled once it stays this close to the target
+pub const SETTLING_BAND: f32 = 0.2;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PidPreset {
+    pub name: String,
+    pub gains: PidGains,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepSample {
+    pub t: f32, // Seconds since the step
+    pub exterior_temp: f32,
+    pub heat_output: f32,
+    pub cool_output: f32,
+}
+
+// How a step response went, see StepResponse::metrics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepMetrics {
+    pub rise_time: Option<f32>,     // 10% to 90% of the step, seconds
+    pub overshoot: f32,             // °C beyond the target
+    pub settling_time: Option<f32>, // Seconds until it stays within SETTLING_BAND, None if it never did
+}
+
+// Response of the peltier surface to a target step, recorded from telemetry
+#[derive(Debug, Clone)]
+pub struct StepResponse {
+    pub from: f32, // Exterior temperature when the step was made
+    pub target: f32,
+    pub duration: Duration,
+    pub samples: Vec<StepSample>,
+    started: Instant,
+}
+
+impl StepResponse {
+    pub fn new(from: f32, target: f32, duration: Duration) -> Self {
+        Self { from, target, duration, samples: Vec::new(), started: Instant::now() }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed() >= self.duration
+    }
+
+    // Adds a telemetry line while the test runs. Lines without the exterior temperature are skipped.
+    pub fn record(&mut self, telemetry: &Telemetry) {
+        let (Some(exterior_temp), false) = (telemetry.exterior_temp, self.is_finished()) else { return };
+        self.samples.push(StepSample {
+            t: self.elapsed().as_secs_f32(),
+            exterior_temp,
+            heat_output: telemetry.heat_output.unwrap_or(0.0),
+            cool_output: telemetry.cool_output.unwrap_or(0.0),
+        });
+    }
+
+    // None until there are samples, or for a step too small to measure
+    pub fn metrics(&self) -> Option<StepMetrics> {
+        let step = self.target - self.from;
+        if step.abs() < SETTLING_BAND || self.samples.is_empty() {
+            return None;
+        }
+        let progress = |sample: &StepSample| (sample.exterior_temp - self.from) / step;
+        let first_at = |fraction: f32| self.samples.iter().find(|s| progress(s) >= fraction).map(|s| s.t);
+        let rise_time = first_at(0.1).zip(first_at(0.9)).map(|(start, end)| end - start);
+        let overshoot = self.samples.iter()
+            .map(|s| (s.exterior_temp - self.target) * step.signum())
+            .fold(0.0, f32::max);
+        let outside = |s: &&StepSample| (s.exterior_temp - self.target).abs() > SETTLING_BAND;
+        let settling_time = match self.samples.iter().rposition(|s| outside(&s)) {
+            None => Some(self.samples[0].t),
+            Some(last) => self.samples.get(last + 1).map(|s| s.t),
+        };
+        Some(StepMetrics { rise_time, overshoot, settling_time })
+    }
+}
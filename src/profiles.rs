@@ -0,0 +1,155 @@
+// src/profiles.rs
+
+// Named profiles, one Config per rig or experiment (desk setup, vest prototype, demo booth...).
+// Stored as JSON with the name of the active profile; every profile goes through
+// Config::from_value, so old profiles are migrated and invalid ones reported like config files.
+
+use serde_json::{Value, json};
+
+use crate::config::Config;
+
+// eframe storage key of the profile set
+pub const STORAGE_KEY: &str = "profiles";
+pub const DEFAULT_PROFILE_NAME: &str = "Default";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profiles {
+    profiles: Vec<Profile>, // Never empty
+    active: usize,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl Profiles {
+    // A single "Default" profile, e.g. for settings from before profiles existed
+    pub fn new(config: Config) -> Self {
+        Self { profiles: vec![Profile { name: DEFAULT_PROFILE_NAME.to_string(), config }], active: 0 }
+    }
+
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|profile| profile.name == name)
+    }
+
+    // Stores the current settings in the active profile
+    pub fn update_active(&mut self, config: Config) {
+        self.profiles[self.active].config = config;
+    }
+
+    // Only selects the profile; the caller applies its config (see TemplateApp::switch_profile)
+    pub fn set_active(&mut self, index: usize) -> Result<&Profile, String> {
+        if index >= self.profiles.len() {
+            return Err(format!("No profile #{}", index));
+        }
+        self.active = index;
+        Ok(&self.profiles[index])
+    }
+
+    pub fn create(&mut self, name: &str, config: Config) -> Result<usize, String> {
+        let name = self.check_new_name(name)?;
+        self.profiles.push(Profile { name, config });
+        Ok(self.profiles.len() - 1)
+    }
+
+    pub fn clone_profile(&mut self, index: usize, name: &str) -> Result<usize, String> {
+        let config = self.profiles.get(index).ok_or_else(|| format!("No profile #{}", index))?.config.clone();
+        self.create(name, config)
+    }
+
+    pub fn rename(&mut self, index: usize, name: &str) -> Result<(), String> {
+        if index >= self.profiles.len() {
+            return Err(format!("No profile #{}", index));
+        }
+        if self.profiles[index].name == name.trim() {
+            return Ok(());
+        }
+        self.profiles[index].name = self.check_new_name(name)?;
+        Ok(())
+    }
+
+    // The active profile can't be deleted, switch to another one first
+    pub fn delete(&mut self, index: usize) -> Result<Profile, String> {
+        if index >= self.profiles.len() {
+            return Err(format!("No profile #{}", index));
+        }
+        if index == self.active {
+            return Err(format!("'{}' is the active profile, switch to another one before deleting it.", self.profiles[index].name));
+        }
+        if index < self.active {
+            self.active -= 1;
+        }
+        Ok(self.profiles.remove(index))
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Profile name must not be empty.".to_string());
+        }
+        if self.find(name).is_some() {
+            return Err(format!("A profile named '{}' already exists.", name));
+        }
+        Ok(name.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        let profiles: Vec<Value> = self.profiles.iter().map(|profile| json!({
+            "name": profile.name,
+            "config": profile.config,
+        })).collect();
+        serde_json::to_string_pretty(&json!({
+            "active": self.active().name,
+            "profiles": profiles,
+        })).expect("profiles are always serializable")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| format!("Profiles are not valid JSON: {}", e))?;
+        let active = value.get("active").and_then(Value::as_str).ok_or("Profiles: missing 'active'")?;
+        let entries = value.get("profiles").and_then(Value::as_array).ok_or("Profiles: missing 'profiles'")?;
+
+        let mut profiles = Self { profiles: Vec::new(), active: 0 };
+        for (index, entry) in entries.iter().enumerate() {
+            let name = entry.get("name").and_then(Value::as_str).ok_or_else(|| format!("profiles[{}]: missing 'name'", index))?;
+            let config = entry.get("config").cloned().ok_or_else(|| format!("profiles[{}]: missing 'config'", index))?;
+            let config = Config::from_value(config).map_err(|e| format!("Profile '{}': {}", name, e))?;
+            let name = profiles.check_new_name(name).map_err(|e| format!("profiles[{}]: {}", index, e))?;
+            profiles.profiles.push(Profile { name, config });
+        }
+        if profiles.profiles.is_empty() {
+            return Err("Profiles: at least one profile is required".to_string());
+        }
+        profiles.active = profiles.find(active).ok_or_else(|| format!("Profiles: active profile '{}' does not exist", active))?;
+        Ok(profiles)
+    }
+}
+
+// Reads what the GUI persisted: the profile set, or a single pre-profiles configuration
+// (config::from_storage) wrapped in a default profile. Ok(None) when nothing was stored yet.
+pub fn from_storage(profiles: Option<String>, config: Option<String>, legacy: Option<String>) -> Result<Option<Profiles>, String> {
+    if let Some(text) = profiles {
+        return Profiles::from_json(&text).map(Some);
+    }
+    Ok(crate::config::from_storage(config, legacy)?.map(Profiles::new))
+}
@@ -0,0 +1,228 @@
+// src/scripting.rs
+
+// Custom temperature mappings in Rhai (https://rhai.rs). With a script loaded, OSC input no longer
+// sets targets directly: every tick the script sees
+//   osc       map of OSC address -> latest float, kept between ticks ("/PeltN" holds the
+//             target it would have set without the script)
+//   telemetry map of module short name -> #{ skin, exterior, target }, () before the first reading
+//   time, dt  seconds since the script was loaded / since its previous run
+//   state     map the script can use to remember things between ticks (hysteresis, filters...)
+// and returns a map of module short name -> target °C, e.g. `#{ L: 20, R: osc["/water"] * 10 }`.
+// Modules missing from the map keep their target. Targets then go through the usual path:
+// manual override, waveforms and protocols win, and the safety limits are applied.
+//
+// Scripts run sandboxed: no file or network access, no eval, and bounded operations, call depth
+// and data sizes so a runaway script can't hang the controller. The file is reloaded when it
+// changes; a script that fails to compile keeps the previous version running.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use crate::esp_comm::telemetry::Telemetry;
+
+const LOG_TARGET: &str = "SCRIPT";
+const MAX_OPERATIONS: u64 = 100_000; // Per run
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// What the script gets to see of one module
+pub struct ModuleInput<'a> {
+    pub short_name: &'a str,
+    pub target: f32,
+    pub telemetry: Option<Telemetry>,
+    pub held: bool, // Manual override, waveform or protocol: the module ignores script targets
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    osc: Map,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    last_reload_check: Instant,
+    loaded_at: Instant,
+    last_run: Option<Instant>,
+    last_outputs: HashMap<String, f32>,
+    reload_error: Option<String>, // Cleared by the next successful reload
+    run_error: Option<String>,    // Cleared by the next successful run
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(1_000);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| log::info!(target: LOG_TARGET, "{}", text));
+    engine.on_debug(|text, _, position| log::debug!(target: LOG_TARGET, "{} ({})", text, position));
+    engine
+}
+
+impl Script {
+    pub fn from_source(source: &str) -> Result<Self, String> {
+        let engine = sandboxed_engine();
+        let ast = engine.compile(source).map_err(|e| format!("Script error: {}", e))?;
+        let mut scope = Scope::new();
+        scope.push("state", Map::new());
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            osc: Map::new(),
+            path: None,
+            modified: None,
+            last_reload_check: Instant::now(),
+            loaded_at: Instant::now(),
+            last_run: None,
+            last_outputs: HashMap::new(),
+            reload_error: None,
+            run_error: None,
+        })
+    }
+
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let mut script = Self::from_source(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+        script.modified = modified_time(path);
+        script.path = Some(path.to_path_buf());
+        Ok(script)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // What the GUI shows: a failed reload, else the error of the last run
+    pub fn error(&self) -> Option<&str> {
+        self.reload_error.as_deref().or(self.run_error.as_deref())
+    }
+
+    // Recompiles the file if it changed on disk. `state` and OSC values survive the reload.
+    // Returns true when a new version was loaded.
+    pub fn reload_if_changed(&mut self) -> bool {
+        if self.last_reload_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return false;
+        }
+        self.last_reload_check = Instant::now();
+        let Some(path) = &self.path else { return false };
+        let modified = modified_time(path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        let compiled = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| self.engine.compile(source).map_err(|e| e.to_string()));
+        match compiled {
+            Ok(ast) => {
+                self.ast = ast;
+                self.reload_error = None;
+                self.last_outputs.clear();
+                log::info!(target: LOG_TARGET, "Reloaded {}", path.display());
+                true
+            }
+            Err(e) => {
+                let error = format!("{}: {} (still running the previous version)", path.display(), e);
+                log::error!(target: LOG_TARGET, "{}", error);
+                self.reload_error = Some(error);
+                false
+            }
+        }
+    }
+
+    // Latest value of an OSC address, as seen by the next run
+    pub fn set_osc(&mut self, address: &str, value: f32) {
+        self.osc.insert(address.into(), Dynamic::from(f64::from(value)));
+    }
+
+    // Runs the script once. Returns the targets that changed since the previous run, and for a
+    // module that was held, its target on the first run after it is released.
+    pub fn run(&mut self, modules: &[ModuleInput<'_>]) -> Result<Vec<(String, f32)>, String> {
+        let now = Instant::now();
+        let dt = self.last_run.map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_run = Some(now);
+
+        let mut telemetry = Map::new();
+        for module in modules {
+            let value = match module.telemetry {
+                Some(t) => {
+                    let mut reading = Map::new();
+                    let float = |v: Option<f32>| v.map_or(Dynamic::UNIT, |v| Dynamic::from(f64::from(v)));
+                    reading.insert("skin".into(), float(t.skin_temp_smoothed));
+                    reading.insert("exterior".into(), float(t.exterior_temp));
+                    reading.insert("target".into(), Dynamic::from(f64::from(module.target)));
+                    Dynamic::from(reading)
+                }
+                None => Dynamic::UNIT,
+            };
+            telemetry.insert(module.short_name.into(), value);
+        }
+        self.scope.set_value("osc", self.osc.clone());
+        self.scope.set_value("telemetry", telemetry);
+        self.scope.set_value("time", now.duration_since(self.loaded_at).as_secs_f64());
+        self.scope.set_value("dt", dt);
+
+        // Variables the script declares are dropped after the run; only `state` persists
+        let base = self.scope.len();
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut self.scope, &self.ast);
+        self.scope.rewind(base);
+
+        let outputs = result.map_err(|e| e.to_string()).and_then(|value| parse_targets(value, modules));
+        match outputs {
+            Ok(outputs) => {
+                self.run_error = None;
+                Ok(outputs.into_iter()
+                    .filter(|(name, temp)| {
+                        if modules.iter().any(|module| module.held && module.short_name == name) {
+                            self.last_outputs.remove(name);
+                            return false;
+                        }
+                        self.last_outputs.insert(name.clone(), *temp) != Some(*temp)
+                    })
+                    .collect())
+            }
+            Err(e) => {
+                let e = format!("Script error: {}", e);
+                // Report each distinct error once, not on every tick
+                if self.run_error.as_ref() != Some(&e) {
+                    log::error!(target: LOG_TARGET, "{}", e);
+                }
+                self.run_error = Some(e.clone());
+                Err(e)
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn parse_targets(value: Dynamic, modules: &[ModuleInput<'_>]) -> Result<Vec<(String, f32)>, String> {
+    if value.is_unit() {
+        return Ok(Vec::new());
+    }
+    let type_name = value.type_name();
+    let map = value.try_cast::<Map>().ok_or_else(|| format!("expected a map of module targets, got {}", type_name))?;
+    let mut targets = Vec::new();
+    for (name, temp) in map {
+        if !modules.iter().any(|module| module.short_name == name.as_str()) {
+            return Err(format!("no module named '{}'", name));
+        }
+        let temp = temp.as_float()
+            .or_else(|_| temp.as_int().map(|t| t as f64))
+            .map_err(|_| format!("target for '{}' is a {}, expected a number", name, temp.type_name()))?;
+        if !temp.is_finite() {
+            return Err(format!("target for '{}' is {}", name, temp));
+        }
+        targets.push((name.to_string(), temp as f32));
+    }
+    Ok(targets)
+}
@@ -0,0 +1,172 @@
+// src/spatial.rs
+
+// Spatial mapping for peltier arrays. Modules get a position on a body-part diagram, and the OSC
+// point input
+//   /TempSense/point x y intensity radius [source]
+// puts a heat (intensity > 0) or cold (< 0) source there. Every positioned module then gets
+//   target = neutral + span·clamp(Σ intensity·falloff(distance / radius), -1, 1)
+// x runs along the body part and y across it, both 0..1. Radius and distances are measured in
+// lengths of the body part (y is scaled by its aspect ratio), so a source covers a round area.
+// Up to MAX_SOURCES sources are combined, told apart by `source` (0 when left out); a source
+// with intensity 0 is removed.
+
+use std::collections::{BTreeMap, HashSet};
+
+pub const POINT_ADDRESS: &str = "/TempSense/point";
+pub const MAX_SOURCES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum BodyPart {
+    Forearm,
+    Hand,
+    Torso,
+}
+
+impl BodyPart {
+    pub const ALL: [BodyPart; 3] = [BodyPart::Forearm, BodyPart::Hand, BodyPart::Torso];
+
+    // Width across over length along, what the diagram is drawn with
+    pub fn aspect(self) -> f32 {
+        match self {
+            BodyPart::Forearm => 0.3,
+            BodyPart::Hand => 0.8,
+            BodyPart::Torso => 1.4,
+        }
+    }
+
+    // What x = 0 and x = 1 are, for the diagram
+    pub fn ends(self) -> (&'static str, &'static str) {
+        match self {
+            BodyPart::Forearm => ("elbow", "wrist"),
+            BodyPart::Hand => ("wrist", "fingertips"),
+            BodyPart::Torso => ("neck", "waist"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Falloff {
+    Gaussian, // exp(-2·d²), radius is two standard deviations
+    Linear,   // 1 - d, nothing beyond the radius
+    Cosine,   // Smooth to 0 at the radius
+}
+
+impl Falloff {
+    pub const ALL: [Falloff; 3] = [Falloff::Gaussian, Falloff::Linear, Falloff::Cosine];
+
+    // Weight at `distance` radii from the source, 1 at the source
+    pub fn weight(self, distance: f32) -> f32 {
+        let d = distance.abs();
+        match self {
+            Falloff::Gaussian => (-2.0 * d * d).exp(),
+            Falloff::Linear => (1.0 - d).max(0.0),
+            Falloff::Cosine if d < 1.0 => 0.5 * (1.0 + (std::f32::consts::PI * d).cos()),
+            Falloff::Cosine => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModulePosition {
+    pub module: String, // Short name
+    pub x: f32,         // Along the body part, 0..1
+    pub y: f32,         // Across it, 0..1
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpatialLayout {
+    pub body_part: BodyPart,
+    pub falloff: Falloff,
+    pub neutral: f32, // °C with no source nearby
+    pub span: f32,    // °C away from neutral at full intensity
+    pub positions: Vec<ModulePosition>,
+}
+
+impl Default for SpatialLayout {
+    fn default() -> Self {
+        Self { body_part: BodyPart::Forearm, falloff: Falloff::Gaussian, neutral: 32.0, span: 10.0, positions: Vec::new() }
+    }
+}
+
+// One source from /TempSense/point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpatialPoint {
+    pub x: f32,
+    pub y: f32,
+    pub intensity: f32, // -1 (cold) to +1 (hot)
+    pub radius: f32,    // In lengths of the body part
+    pub source: u32,
+}
+
+impl SpatialPoint {
+    // From the OSC arguments: x, y, intensity, radius and optionally the source id
+    pub fn from_args(args: &[f32]) -> Result<Self, String> {
+        let [x, y, intensity, radius, rest @ ..] = args else {
+            return Err(format!("{} expects x, y, intensity, radius [source], found {} arguments", POINT_ADDRESS, args.len()));
+        };
+        if args.iter().any(|value| !value.is_finite()) {
+            return Err(format!("{} arguments must be numbers, found {:?}", POINT_ADDRESS, args));
+        }
+        if *radius <= 0.0 {
+            return Err(format!("{} radius must be above 0, found {}", POINT_ADDRESS, radius));
+        }
+        let source = match rest {
+            [] => 0,
+            [source] if *source >= 0.0 && source.fract() == 0.0 => *source as u32,
+            _ => return Err(format!("{} source must be a whole number 0 or more, found {:?}", POINT_ADDRESS, rest)),
+        };
+        Ok(Self { x: x.clamp(0.0, 1.0), y: y.clamp(0.0, 1.0), intensity: intensity.clamp(-1.0, 1.0), radius: *radius, source })
+    }
+}
+
+impl SpatialLayout {
+    pub fn position(&self, module: &str) -> Option<&ModulePosition> {
+        self.positions.iter().find(|position| position.module == module)
+    }
+
+    // In lengths of the body part
+    pub fn distance(&self, from: (f32, f32), to: (f32, f32)) -> f32 {
+        (to.0 - from.0).hypot((to.1 - from.1) * self.body_part.aspect())
+    }
+
+    // Combined intensity of the sources at a position, -1..+1
+    pub fn intensity_at(&self, x: f32, y: f32, sources: &BTreeMap<u32, SpatialPoint>) -> f32 {
+        sources.values()
+            .map(|point| point.intensity * self.falloff.weight(self.distance((point.x, point.y), (x, y)) / point.radius))
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    // Target of every positioned module, by short name. The safety limits are left to the controller.
+    pub fn targets(&self, sources: &BTreeMap<u32, SpatialPoint>) -> Vec<(String, f32)> {
+        self.positions.iter()
+            .map(|position| (position.module.clone(), self.neutral + self.span * self.intensity_at(position.x, position.y, sources)))
+            .collect()
+    }
+
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self, module_names: &[&str]) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in [("neutral", self.neutral), ("span", self.span)] {
+            if !value.is_finite() {
+                problems.push(format!("{}: must be a number, found {}", name, value));
+            }
+        }
+        let mut placed = HashSet::new();
+        for (index, position) in self.positions.iter().enumerate() {
+            if !module_names.contains(&position.module.as_str()) {
+                problems.push(format!("positions[{}].module: '{}' is not a module", index, position.module));
+            } else if !placed.insert(position.module.as_str()) {
+                problems.push(format!("positions[{}].module: '{}' is placed twice", index, position.module));
+            }
+            for (name, value) in [("x", position.x), ("y", position.y)] {
+                if !(0.0..=1.0).contains(&value) {
+                    problems.push(format!("positions[{}].{}: must be between 0 and 1, found {}", index, name, value));
+                }
+            }
+        }
+        problems
+    }
+}
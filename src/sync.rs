@@ -0,0 +1,121 @@
+// src/sync.rs
+
+// Synchronized commands across modules. Sent one after the other, START reached the modules
+// tens of milliseconds apart, depending on what each worker was doing. Instead every connected
+// module's worker now gets the command with a due instant a little in the future
+// (EspCommand::SendAt) and holds it until then, so the queues in between don't matter. Each
+// module's instant is brought forward by the time its command needs to reach the device
+// (link_delay: half the shortest recent ping round trip, see esp_comm::clock, or before the first
+// pong the serial transmit time), and the workers report when they really wrote it. The spread of
+// the resulting arrival estimates is the skew reported; it is estimated on the host, the devices
+// don't confirm when they acted.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::esp_comm::{self, WireProtocol};
+use crate::esp_comm::clock::ClockSync;
+use crate::esp_comm::transport::TransportDescriptor;
+
+// How far ahead synchronized commands are scheduled: enough for every worker to have them
+pub const SYNC_LEAD: Duration = Duration::from_millis(30);
+// A run whose reports haven't all arrived by then is reported with the modules missing
+const SYNC_REPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Time a serial link needs for `bytes`, 10 bits per byte (8N1)
+pub fn serial_transmit_time(bytes: usize, baud_rate: u32) -> Duration {
+    if baud_rate == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(bytes as f64 * 10.0 / f64::from(baud_rate))
+}
+
+// Estimated time from `command` being written until the device has all of it: the one way delay
+// measured by the clock sync, else for serial links the transmit time at the baud rate. Network
+// and simulated links without a measurement count as instant.
+pub fn link_delay(clock: &ClockSync, port: &str, baud_rate: u32, protocol: WireProtocol, command: &str) -> Duration {
+    if let Some(delay) = clock.one_way_delay() {
+        return delay;
+    }
+    match TransportDescriptor::from_port_name(port, baud_rate) {
+        TransportDescriptor::Serial { baud_rate, .. } => serial_transmit_time(esp_comm::encode_command(protocol, command).len(), baud_rate),
+        _ => Duration::ZERO,
+    }
+}
+
+// One synchronized command, waiting for the workers to report
+#[derive(Debug, Clone)]
+pub struct SyncRun {
+    pub label: String, // "START"
+    pub due: Instant,  // When the command should reach every module
+    delays: BTreeMap<String, Duration>, // By module short name
+    arrivals: BTreeMap<String, Instant>,
+}
+
+impl SyncRun {
+    pub fn new(label: &str, due: Instant) -> Self {
+        Self { label: label.to_string(), due, delays: BTreeMap::new(), arrivals: BTreeMap::new() }
+    }
+
+    // A module the command was scheduled for, written at `due - delay`
+    pub fn expect(&mut self, module: &str, delay: Duration) {
+        self.delays.insert(module.to_string(), delay);
+    }
+
+    pub fn expected(&self) -> usize {
+        self.delays.len()
+    }
+
+    // The worker of `module` started writing at `written`
+    pub fn record(&mut self, module: &str, written: Instant) {
+        if let Some(delay) = self.delays.get(module) {
+            self.arrivals.insert(module.to_string(), written + *delay);
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.arrivals.len() == self.delays.len()
+    }
+
+    pub fn is_overdue(&self, now: Instant) -> bool {
+        now > self.due + SYNC_REPORT_TIMEOUT
+    }
+
+    pub fn report(&self) -> SyncReport {
+        // Milliseconds after the due instant, negative when early
+        let offset = |arrival: Instant| match arrival.checked_duration_since(self.due) {
+            Some(late) => late.as_secs_f64() * 1000.0,
+            None => -self.due.duration_since(arrival).as_secs_f64() * 1000.0,
+        };
+        let offsets: Vec<(String, f64)> = self.arrivals.iter().map(|(module, arrival)| (module.clone(), offset(*arrival))).collect();
+        let earliest = offsets.iter().map(|(_, ms)| *ms).fold(f64::INFINITY, f64::min);
+        let latest = offsets.iter().map(|(_, ms)| *ms).fold(f64::NEG_INFINITY, f64::max);
+        SyncReport {
+            label: self.label.clone(),
+            skew: if offsets.is_empty() { Duration::ZERO } else { Duration::from_secs_f64((latest - earliest) / 1000.0) },
+            offsets,
+            missing: self.delays.keys().filter(|module| !self.arrivals.contains_key(*module)).cloned().collect(),
+        }
+    }
+}
+
+// How well a synchronized command went, as estimated on the host
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncReport {
+    pub label: String,
+    pub skew: Duration,              // Between the first and last module
+    pub offsets: Vec<(String, f64)>, // Estimated arrival per module, ms after the due instant
+    pub missing: Vec<String>,        // Modules whose worker never reported
+}
+
+impl SyncReport {
+    // "START reached 2 modules within 0.42 ms, host-side estimate (L +0.12 ms, R +0.54 ms)"
+    pub fn describe(&self) -> String {
+        let offsets: Vec<String> = self.offsets.iter().map(|(module, ms)| format!("{} {:+.2} ms", module, ms)).collect();
+        let mut text = format!("{} reached {} modules within {:.2} ms, host-side estimate ({})", self.label, self.offsets.len(), self.skew.as_secs_f64() * 1000.0, offsets.join(", "));
+        if !self.missing.is_empty() {
+            text.push_str(&format!(", no report from {}", self.missing.join(", ")));
+        }
+        text
+    }
+}
@@ -0,0 +1,155 @@
+// src/waveform.rs
+
+// Temperature waveforms for stimulus design: step, ramp, sine, square and pulse trains between
+// two temperatures. A running waveform drives one module's target in place of OSC input
+// (Controller::start_waveform); manual override pauses it, freezing its phase until released, and
+// targets are clamped to the safety limits like any other. Named presets are saved with the config.
+
+use std::time::{Duration, Instant};
+
+use crate::controller::SafetyLimits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum WaveShape {
+    Step,   // `low` for one period, then `high`
+    Ramp,   // Linear from `low` to `high` over one period, then `high`
+    Sine,   // Starts at `low`, peaks at `high` half a period later
+    Square, // `high` for the first half of every period, `low` for the second
+    Pulse,  // `high` for `duty_cycle` of every period, `low` for the rest
+}
+
+impl WaveShape {
+    pub const ALL: [WaveShape; 5] = [WaveShape::Step, WaveShape::Ramp, WaveShape::Sine, WaveShape::Square, WaveShape::Pulse];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Waveform {
+    pub shape: WaveShape,
+    pub low: f32,  // °C
+    pub high: f32, // °C, may be below `low` for cooling stimuli
+    pub period_secs: f32,
+    pub duty_cycle: f32, // Pulse only, fraction of the period spent at `high`
+    pub duration_secs: Option<f32>, // None runs until stopped
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Self { shape: WaveShape::Sine, low: 25.0, high: 35.0, period_secs: 10.0, duty_cycle: 0.2, duration_secs: Some(60.0) }
+    }
+}
+
+impl Waveform {
+    // Temperature `t` seconds after the start, ignoring the duration
+    pub fn value_at(&self, t: f32) -> f32 {
+        let (low, high) = (self.low, self.high);
+        let phase = (t / self.period_secs).rem_euclid(1.0);
+        match self.shape {
+            WaveShape::Step => if t < self.period_secs { low } else { high },
+            WaveShape::Ramp => low + (high - low) * (t / self.period_secs).clamp(0.0, 1.0),
+            WaveShape::Sine => low + (high - low) * (1.0 - (phase * std::f32::consts::TAU).cos()) / 2.0,
+            WaveShape::Square => if phase < 0.5 { high } else { low },
+            WaveShape::Pulse => if phase < self.duty_cycle { high } else { low },
+        }
+    }
+
+    // Target at `elapsed`, None once the duration is over. The controller rounds it to the target resolution.
+    pub fn target_at(&self, elapsed: Duration) -> Option<f32> {
+        let t = elapsed.as_secs_f32();
+        if self.duration_secs.is_some_and(|duration| t >= duration) {
+            return None;
+        }
+        Some(self.value_at(t))
+    }
+
+    // Time span worth previewing: the whole waveform, or three periods of an endless one
+    pub fn preview_secs(&self) -> f32 {
+        self.duration_secs.unwrap_or(self.period_secs * 3.0)
+    }
+
+    // Every problem, without a path prefix (see Config::validate)
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, temp) in [("low", self.low), ("high", self.high)] {
+            if !temp.is_finite() {
+                problems.push(format!("{}: must be a temperature, found {}", name, temp));
+            }
+        }
+        if !(self.period_secs.is_finite() && self.period_secs > 0.0) {
+            problems.push(format!("period_secs: must be greater than 0, found {}", self.period_secs));
+        }
+        if self.shape == WaveShape::Pulse && !(self.duty_cycle > 0.0 && self.duty_cycle < 1.0) {
+            problems.push(format!("duty_cycle: must be between 0 and 1, found {}", self.duty_cycle));
+        }
+        if let Some(duration) = self.duration_secs {
+            if !(duration.is_finite() && duration > 0.0) {
+                problems.push(format!("duration_secs: must be greater than 0, found {}", duration));
+            }
+        }
+        problems
+    }
+
+    // Waveforms reaching outside the limits are refused rather than silently flattened
+    pub fn check_limits(&self, limits: &SafetyLimits) -> Result<(), String> {
+        for temp in [self.low, self.high] {
+            if limits.clamp(temp) != temp {
+                return Err(format!("{}°C is outside the safety limits [{}, {}]", temp, limits.value_min, limits.value_max));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self, limits: &SafetyLimits) -> Result<(), String> {
+        let mut problems = self.problems();
+        if let Err(e) = self.check_limits(limits) {
+            problems.push(e);
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveformPreset {
+    pub name: String,
+    pub waveform: Waveform,
+}
+
+// A waveform driving a module, see Module::waveform
+#[derive(Debug, Clone)]
+pub struct WaveformRun {
+    pub name: String, // Preset name, or "custom"
+    pub waveform: Waveform,
+    started: Instant, // Moved forward by the time spent paused
+    paused_at: Option<Instant>,
+}
+
+impl WaveformRun {
+    pub fn new(name: &str, waveform: Waveform) -> Self {
+        Self { name: name.to_string(), waveform, started: Instant::now(), paused_at: None }
+    }
+
+    // Time into the waveform, not counting pauses
+    pub fn elapsed(&self) -> Duration {
+        self.paused_at.unwrap_or_else(Instant::now).saturating_duration_since(self.started)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Instant::now()),
+            (false, Some(paused_at)) => {
+                self.started += paused_at.elapsed();
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+    }
+}
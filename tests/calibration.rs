@@ -0,0 +1,94 @@
+// Sensor calibration: fitting, storage with the config and use on live telemetry.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::calibration::{Calibration, CalibrationModel, CalibrationPoint};
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::parse_serial;
+
+fn points(f: impl Fn(f32) -> f32) -> Vec<CalibrationPoint> {
+    [20.0, 25.0, 30.0, 35.0].into_iter().map(|raw| CalibrationPoint { raw, reference: f(raw) }).collect()
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+#[test]
+fn corrections_are_fitted_to_the_readings() {
+    let offset = Calibration::fit(CalibrationModel::Offset, &points(|raw| raw - 0.4)).unwrap();
+    assert!(close(offset.apply(31.0), 30.6));
+
+    let linear = Calibration::fit(CalibrationModel::Linear, &points(|raw| 1.02 * raw - 0.5)).unwrap();
+    assert!(close(linear.apply(33.0), 33.16) && linear.rms_error() < 1e-3, "{:?}", linear);
+
+    let curve = |raw: f32| 0.01 * raw * raw + 0.5 * raw + 3.0;
+    let quadratic = Calibration::fit(CalibrationModel::Polynomial(2), &points(curve)).unwrap();
+    assert!(close(quadratic.apply(28.0), curve(28.0)), "{:?}", quadratic);
+    assert!(quadratic.problems().is_empty());
+
+    let same_temperature = vec![CalibrationPoint { raw: 30.0, reference: 30.2 }; 3];
+    let error = Calibration::fit(CalibrationModel::Linear, &same_temperature).unwrap_err();
+    assert!(error.contains("2 different temperatures"), "{}", error);
+    assert!(Calibration::fit(CalibrationModel::Polynomial(5), &points(|raw| raw)).is_err());
+}
+
+#[test]
+fn calibrations_are_saved_with_the_config() {
+    let mut controller = Controller::default();
+    controller.modules[0].serial = Some("TS-0042".to_string());
+    let calibration = Calibration::fit(CalibrationModel::Linear, &points(|raw| raw + 0.3)).unwrap();
+    controller.set_calibration(0, Some(calibration.clone()));
+    assert_eq!(controller.calibrations.keys().collect::<Vec<_>>(), ["TS-0042"]);
+
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!(config.calibrations["TS-0042"], calibration);
+
+    let mut invalid = config.clone();
+    invalid.calibrations.get_mut("TS-0042").unwrap().coefficients.push(1.0);
+    let error = invalid.validate().unwrap_err();
+    assert!(error.contains("calibrations.TS-0042.coefficients"), "{}", error);
+
+    assert_eq!(parse_serial("SERIAL: TS-0042 "), Some("TS-0042".to_string()));
+    assert_eq!(parse_serial("SERIAL:"), None);
+}
+
+#[test]
+fn telemetry_is_calibrated_and_safety_checked() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    // The simulated device has no serial number, so the calibration is stored for its port
+    let hot = Calibration::fit(CalibrationModel::Offset, &[CalibrationPoint { raw: 30.0, reference: 40.0 }]).unwrap();
+    controller.set_calibration(0, Some(hot));
+    assert!(controller.calibrations.contains_key("sim:L"));
+    // Only the skin limits switch a module off, the target limits don't matter
+    controller.skin_limits.value_max = 38.0;
+    controller.limits.value_max = 30.0;
+    controller.connect(0);
+    controller.start();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !controller.modules[0].skin_alarm && Instant::now() < deadline {
+        controller.tick();
+        thread::sleep(Duration::from_millis(10));
+    }
+    let module = &controller.modules[0];
+    let (raw, skin) = (module.raw_skin_temp.unwrap(), module.skin_temp.unwrap());
+    assert!(close(skin, raw + 10.0), "raw {} calibrated {}", raw, skin);
+    assert!(module.skin_alarm && !module.active, "switched off above the skin limits");
+    assert!(controller.telemetry_log.iter().any(|line| line.calibrated_skin.is_some()));
+
+    // START during the alarm leaves the module off
+    controller.start();
+    assert!(!controller.modules[0].active);
+    let until = Instant::now() + Duration::from_millis(200);
+    while Instant::now() < until {
+        controller.tick();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(controller.modules[0].skin_alarm && !controller.modules[0].active);
+    assert!(controller.modules[0].status_message.contains("skin limits"), "{}", controller.modules[0].status_message);
+    controller.shutdown();
+}
@@ -0,0 +1,180 @@
+// Device clock sync: offset and round trip estimates, the ping/pong exchange in the worker and
+// host-time telemetry in the controller.
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::clock::{self, ClockSample, ClockSync, CLOCK_WINDOW};
+use TempSenseGUI::esp_comm::telemetry::Telemetry;
+use TempSenseGUI::esp_comm::transport::{MockTransport, TransportDescriptor};
+use TempSenseGUI::esp_comm::{EspCommand, EspStatus, WireProtocol, esp_worker_thread};
+use common::{WAIT, wait_for};
+
+// The next line the worker writes
+fn next_written_line(mock: &MockTransport) -> String {
+    let deadline = Instant::now() + WAIT;
+    let mut written = Vec::new();
+    while !written.ends_with(b"\n") && Instant::now() < deadline {
+        written.extend(mock.take_tx());
+        thread::sleep(Duration::from_millis(2));
+    }
+    String::from_utf8(written).unwrap()
+}
+
+// Host clock of a clock ping, None for any other line
+fn clock_ping_host_us(line: &str) -> Option<u64> {
+    line.trim().strip_prefix("PING ").and_then(|us| us.parse().ok())
+}
+
+fn sample(host_sent_us: u64, rtt_us: u64, offset_us: u64) -> ClockSample {
+    ClockSample { host_sent_us, device_us: host_sent_us + rtt_us / 2 + offset_us, host_received_us: host_sent_us + rtt_us }
+}
+
+#[test]
+fn offset_follows_the_shortest_round_trip() {
+    assert_eq!(clock::parse_pong("PONG 1500 99000"), Some((1500, 99000)));
+    for line in ["PONG", "PONG 1500", "PONG 1500 x", "PONG 1 2 3", "OK setTemp 20"] {
+        assert_eq!(clock::parse_pong(line), None, "{}", line);
+    }
+    assert!(clock::is_unsupported_reply("PONG") && clock::is_unsupported_reply("ERR unknown command 'PING 12'"));
+    assert!(!clock::is_unsupported_reply("ERR invalid temperature 'x'"));
+
+    let mut sync = ClockSync::default();
+    assert_eq!((sync.offset_us(), sync.supported), (None, None));
+    sync.add(sample(1_000, 4_000, 50_000));
+    sync.add(sample(2_000_000, 1_000, 50_100)); // Least queueing, the estimate
+    sync.add(sample(3_000_000, 9_000, 47_000));
+    assert_eq!((sync.offset_us(), sync.uncertainty_us(), sync.supported), (Some(50_100), Some(500), Some(true)));
+    assert_eq!(sync.to_host_us(1_050_100), Some(1_000_000));
+    assert_eq!((sync.rtt.min_us, sync.rtt.max_us, sync.rtt.last_us, sync.rtt.count), (1_000, 9_000, 9_000, 3));
+    assert!((sync.rtt.mean_us() - 14_000.0 / 3.0).abs() < 1e-6);
+    assert!((sync.rtt.jitter_us() - 4_041.45).abs() < 0.01, "{}", sync.rtt.jitter_us());
+
+    // The best sample ages out of the window, so drift is followed
+    for i in 0..CLOCK_WINDOW as u64 {
+        sync.add(sample(4_000_000 + i * 1_000_000, 2_000, 60_000));
+    }
+    assert_eq!(sync.offset_us(), Some(60_000));
+    assert_eq!(sync.rtt.min_us, 1_000, "the statistics cover the whole link");
+}
+
+#[test]
+fn worker_pings_and_reports_clock_samples() {
+    let mock = MockTransport::new();
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let handle = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(_)));
+    thread::sleep(Duration::from_millis(50));
+    assert!(mock.take_tx().is_empty(), "no pings until asked for");
+
+    command_tx.send(EspCommand::ClockSync(true)).unwrap();
+    let ping = next_written_line(&mock);
+    let host_us = clock_ping_host_us(&ping).unwrap_or_else(|| panic!("not a clock ping: {:?}", ping));
+
+    mock.push_rx(format!("PONG {} 7000000\nOK setTemp 20\n", host_us).as_bytes());
+    let status = wait_for(&status_rx, |s| matches!(s, EspStatus::ClockSample(_) | EspStatus::Message(_)));
+    let EspStatus::ClockSample(sample) = status else { panic!("expected a clock sample first, got {:?}", status) };
+    assert_eq!((sample.host_sent_us, sample.device_us), (host_us, 7_000_000));
+    assert!(sample.host_received_us >= host_us);
+    let EspStatus::Message(rest) = wait_for(&status_rx, |s| matches!(s, EspStatus::Message(_))) else { unreachable!() };
+    assert_eq!(rest, "OK setTemp 20", "the reply is not forwarded as device output");
+
+    // Older firmware: pinging stops
+    assert!(clock_ping_host_us(&next_written_line(&mock)).is_some());
+    mock.push_rx(b"PONG\n");
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ClockUnsupported));
+    thread::sleep(Duration::from_millis(20));
+    mock.take_tx();
+    thread::sleep(clock::CLOCK_SYNC_FAST_INTERVAL * 3);
+    assert!(mock.take_tx().is_empty());
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn plain_pings_leave_clock_sync_on() {
+    let mock = MockTransport::new();
+    mock.set_keepalive_interval(Some(Duration::from_millis(30)));
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let handle = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(_)));
+    command_tx.send(EspCommand::ClockSync(true)).unwrap();
+
+    // Firmware with clock sync answers keepalives and console pings with a plain PONG
+    let (mut keepalives, mut samples) = (0, 0);
+    let deadline = Instant::now() + WAIT;
+    while (keepalives < 2 || samples < 3) && Instant::now() < deadline {
+        for line in next_written_line(&mock).lines() {
+            match clock_ping_host_us(line) {
+                Some(host_us) => mock.push_rx(format!("PONG {} 5000000\n", host_us).as_bytes()),
+                None if line == "PING" => {
+                    keepalives += 1;
+                    mock.push_rx(b"PONG\n");
+                    if keepalives == 1 {
+                        command_tx.send(EspCommand::SendCommand("PING".to_string())).unwrap();
+                    }
+                }
+                None => panic!("unexpected {:?}", line),
+            }
+        }
+        while let Ok(status) = status_rx.try_recv() {
+            match status {
+                EspStatus::ClockSample(_) => samples += 1,
+                EspStatus::ClockUnsupported => panic!("clock sync turned off by a plain PONG"),
+                _ => {}
+            }
+        }
+    }
+    assert!(keepalives >= 2 && samples >= 3, "{} keepalives, {} samples", keepalives, samples);
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn telemetry_carries_the_device_clock() {
+    let telemetry = Telemetry::parse("Skin_Temp_Smoothed:32.00,Device_us:81234567").unwrap().unwrap();
+    assert_eq!((telemetry.skin_temp_smoothed, telemetry.device_us), (Some(32.0), Some(81_234_567)));
+    assert!(Telemetry::parse("Device_us:1.5").unwrap().is_err());
+}
+
+#[test]
+fn simulated_modules_are_synchronized() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.modules[1].port = "sim:R:legacy".to_string();
+    controller.connect(0);
+    controller.connect(1);
+    let deadline = Instant::now() + WAIT;
+    let done = |controller: &Controller| {
+        controller.modules[0].clock.rtt.count >= 3
+            && controller.modules[1].clock.supported == Some(false)
+            && controller.telemetry_log.iter().any(|line| line.device_time)
+    };
+    while !done(&controller) && Instant::now() < deadline {
+        controller.tick();
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(done(&controller), "left {:?}, right {:?}", controller.modules[0].clock, controller.modules[1].clock);
+
+    // Both clocks run at the same rate here, so the stamps are close to the arrival times
+    let clock = &controller.modules[0].clock;
+    assert!(clock.rtt.max_us < 100_000, "{:?}", clock.rtt);
+    let now = chrono::Local::now();
+    for line in controller.telemetry_log.iter() {
+        let age = now.signed_duration_since(line.timestamp);
+        assert!(age > chrono::Duration::milliseconds(-50) && age < chrono::Duration::seconds(5), "{:?} stamped {:?} ago", line.line, age);
+    }
+    assert!(controller.telemetry_log.iter().all(|line| line.source == "ESP L" || !line.device_time));
+    controller.shutdown();
+    assert_eq!(controller.modules[0].clock, ClockSync::default());
+}
@@ -0,0 +1,101 @@
+// Perceived-temperature mode: the comfort model, OSC sensations and the skin baseline.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::comfort::{self, ComfortModel};
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::osc::OscInput;
+
+#[test]
+fn sensations_map_around_the_skin_baseline() {
+    let model = ComfortModel { warm_span: 8.0, cool_span: 12.0, exponent: 2.0, ..ComfortModel::default() };
+    assert_eq!(model.target(0.0, 33.0, None), 33.0);
+    assert_eq!(model.target(1.0, 33.0, None), 41.0);
+    assert_eq!(model.target(-1.0, 33.0, None), 21.0);
+    assert_eq!(model.target(-0.5, 33.0, None), 30.0); // 12·0.5²
+    assert_eq!(model.target(3.0, 33.0, None), 41.0, "clamped to +1");
+
+    // A warmer room moves the neutral point
+    let model = ComfortModel { ambient_weight: 0.5, ambient_reference: 22.0, ..model };
+    assert_eq!(model.neutral(33.0, Some(26.0)), 35.0);
+    assert_eq!(model.neutral(33.0, None), 33.0);
+
+    assert_eq!(comfort::label(-0.3), "slightly cool");
+    assert_eq!(comfort::label(1.0), "hot");
+    assert_eq!(comfort::label(0.1), "neutral");
+}
+
+#[test]
+fn osc_sensations_follow_the_measured_skin_temperature() {
+    let mut controller = Controller::default();
+    controller.limits.value_max = 38.0;
+    let (osc_sender, osc_receiver) = mpsc::channel();
+    controller.set_osc_receiver(osc_receiver);
+    controller.modules[1].port = "sim:R".to_string();
+    controller.connect(1);
+
+    // Before the module reports a skin temperature the default baseline is used
+    osc_sender.send(OscInput::Sensation(1, 0.5)).unwrap();
+    controller.tick();
+    let state = controller.modules[1].comfort.unwrap();
+    assert!(!state.measured && state.baseline == ComfortModel::default().default_skin);
+    assert_eq!(controller.modules[1].target, 36.0);
+
+    // The first reading becomes the baseline, and stays it while the target changes the skin
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !controller.modules[1].comfort.unwrap().measured && Instant::now() < deadline {
+        controller.tick();
+        thread::sleep(Duration::from_millis(10));
+    }
+    let state = controller.modules[1].comfort.unwrap();
+    assert!(state.measured, "no telemetry from the simulated module");
+    let expected = controller.limits.quantize(state.baseline + 4.0, controller.target_decimals);
+    assert_eq!(controller.modules[1].target, expected);
+
+    osc_sender.send(OscInput::Sensation(1, 1.0)).unwrap();
+    controller.tick();
+    assert_eq!(controller.modules[1].target, 38.0, "hot is kept within the safety limits");
+
+    // An absolute target ends the mode
+    osc_sender.send(OscInput::Target(1, 25.0)).unwrap();
+    controller.tick();
+    assert!(controller.modules[1].comfort.is_none());
+    assert_eq!(controller.modules[1].target, 25.0);
+    controller.shutdown();
+}
+
+#[test]
+fn manual_override_keeps_the_target() {
+    let mut controller = Controller::default();
+    controller.set_sensation(0, -1.0);
+    assert_eq!(controller.modules[0].target, 20.0);
+    controller.set_manual_target(0, 30.0);
+    controller.modules[0].manual_override = true;
+    controller.set_sensation(0, 1.0);
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 30.0);
+    assert_eq!(controller.modules[0].comfort.unwrap().sensation, -1.0);
+
+    assert!(controller.rebaseline_comfort(0).unwrap_err().contains("No skin temperature"));
+    controller.stop_comfort(0);
+    assert!(controller.modules[0].comfort.is_none());
+}
+
+#[test]
+fn comfort_model_is_saved_with_the_config() {
+    let mut controller = Controller::default();
+    controller.comfort_model.warm_span = 5.0;
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!(config.comfort, controller.comfort_model);
+
+    let mut invalid = config.clone();
+    invalid.comfort.exponent = 0.0;
+    invalid.comfort.default_skin = f32::NAN;
+    let error = invalid.validate().unwrap_err();
+    for expected in ["comfort.exponent", "comfort.default_skin"] {
+        assert!(error.contains(expected), "missing '{}' in: {}", expected, error);
+    }
+}
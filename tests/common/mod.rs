@@ -0,0 +1,36 @@
+// Helpers shared by the integration tests, each test crate uses some of them
+#![allow(dead_code)]
+
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::EspStatus;
+
+pub const WAIT: Duration = Duration::from_secs(3);
+
+// Waits for the first status matching `pred`, skipping everything else
+pub fn wait_for(status_rx: &Receiver<EspStatus>, pred: impl Fn(&EspStatus) -> bool) -> EspStatus {
+    let deadline = Instant::now() + WAIT;
+    loop {
+        match status_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(status) if pred(&status) => return status,
+            Ok(_) => continue,
+            Err(e) => panic!("timed out waiting for status: {:?}", e),
+        }
+    }
+}
+
+// Ticks the controller until `done` holds, false if it didn't within `timeout`
+pub fn tick_until(controller: &mut Controller, timeout: Duration, done: impl Fn(&Controller) -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        controller.tick();
+        if done(controller) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
@@ -0,0 +1,116 @@
+// Versioned configuration: round trip, migration of older layouts and validation errors.
+
+use TempSenseGUI::config::{self, CONFIG_VERSION, Config};
+use TempSenseGUI::controller::{Controller, SafetyLimits, DEFAULT_SKIN_LIMITS, DEFAULT_TARGET_DECIMALS};
+use TempSenseGUI::esp_comm::WireProtocol;
+use TempSenseGUI::headless;
+
+#[test]
+fn export_import_round_trip() {
+    let mut config = Config::default();
+    config.modules[1].port = "tcp://192.168.4.1:3333".to_string();
+    config.modules[1].protocol = WireProtocol::Binary;
+    config.limits.value_max = 30.0;
+
+    let imported = Config::from_json(&config.to_json()).unwrap();
+    assert_eq!(imported, config);
+
+    let mut controller = Controller::default();
+    imported.apply_to(&mut controller).unwrap();
+    assert_eq!(controller.modules[1].port, "tcp://192.168.4.1:3333");
+    assert_eq!(controller.limits.value_max, 30.0);
+}
+
+#[test]
+fn legacy_flat_json_is_migrated() {
+    let legacy = r#"{
+        "osc_ip": "127.0.0.1",
+        "osc_port": "9001",
+        "value_max": 35,
+        "value_min": 5,
+        "esp_port_1": "COM7",
+        "esp_baud_rate_1": 57600,
+        "esp_port_2": "sim:R",
+        "esp_protocol_2": "Binary"
+    }"#;
+    let config = Config::from_json(legacy).unwrap();
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.osc.port, 9001);
+    assert_eq!(config.target_decimals, DEFAULT_TARGET_DECIMALS);
+    assert_eq!((config.limits.value_min, config.limits.value_max), (5.0, 35.0));
+    assert_eq!(config.modules[0].port, "COM7");
+    assert_eq!(config.modules[0].baud_rate, 57600);
+    assert_eq!(config.modules[0].protocol, WireProtocol::Ascii);
+    assert_eq!(config.modules[1].port, "sim:R");
+    assert_eq!(config.modules[1].protocol, WireProtocol::Binary);
+}
+
+#[test]
+fn settings_persisted_by_older_gui_versions_are_migrated() {
+    // What eframe stored under APP_KEY before the versioned format existed
+    let stored = r#"(osc_ip:"127.0.0.1",value_max:40,value_min:-10,osc_port:"9000",esp_port_1:"sim:L",esp_baud_rate_1:115200,esp_protocol_1:Binary,esp_port_2:"/dev/ttyUSB1",esp_baud_rate_2:115200,esp_protocol_2:Ascii)"#;
+    let config = config::from_storage(None, Some(stored.to_string())).unwrap().unwrap();
+    assert_eq!(config.modules[0].port, "sim:L");
+    assert_eq!(config.modules[0].protocol, WireProtocol::Binary);
+    assert_eq!(config.modules[1].port, "/dev/ttyUSB1");
+
+    // Once saved in the current format, that takes precedence over the legacy settings
+    let mut current = config.clone();
+    current.osc.port = 9100;
+    let loaded = config::from_storage(Some(current.to_json()), Some(stored.to_string())).unwrap();
+    assert_eq!(loaded, Some(current));
+    assert_eq!(config::from_storage(None, None).unwrap(), None);
+}
+
+#[test]
+fn validation_reports_every_problem() {
+    let mut config = Config::default();
+    config.osc.ip = "localhost:9000".to_string();
+    config.limits.value_min = 50.0;
+    config.target_decimals = 4;
+    config.modules[1].short_name = config.modules[0].short_name.clone();
+    config.modules[1].baud_rate = 0;
+
+    let error = Config::from_json(&config.to_json()).unwrap_err();
+    for expected in ["osc.ip", "limits", "target_decimals", "modules[1].short_name", "modules[1].baud_rate"] {
+        assert!(error.contains(expected), "missing '{}' in: {}", expected, error);
+    }
+}
+
+#[test]
+fn unknown_fields_and_newer_versions_are_rejected() {
+    let mut value: serde_json::Value = serde_json::from_str(&Config::default().to_json()).unwrap();
+    value["osc"]["prot"] = 9000.into();
+    assert!(Config::from_value(value.clone()).unwrap_err().contains("prot"));
+
+    value["version"] = (CONFIG_VERSION + 1).into();
+    assert!(Config::from_value(value).unwrap_err().contains("not supported"));
+
+    assert!(Config::from_json(r#"{"osc_port": "ninety"}"#).unwrap_err().contains("osc_port"));
+}
+
+#[test]
+fn skin_limits_are_separate_from_the_target_limits() {
+    let mut controller = Controller::default();
+    controller.skin_limits = SafetyLimits { value_min: 20.0, value_max: 42.0 };
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!((config.limits, config.skin_limits), (controller.limits, controller.skin_limits));
+
+    let mut invalid = config.clone();
+    invalid.skin_limits.value_min = 50.0;
+    let error = invalid.validate().unwrap_err();
+    assert!(error.contains("skin_limits: Invalid limits: min 50 is above max 42"), "{}", error);
+
+    // Configurations from before get the defaults, whatever their target limits
+    let mut value: serde_json::Value = serde_json::from_str(&config.to_json()).unwrap();
+    value["version"] = 9.into();
+    value.as_object_mut().unwrap().remove("skin_limits");
+    assert_eq!(Config::from_value(value).unwrap().skin_limits, DEFAULT_SKIN_LIMITS);
+}
+
+#[test]
+fn bridge_refuses_invalid_settings_as_a_usage_error() {
+    let mut config = Config::default();
+    config.limits.value_min = 99.0;
+    assert_eq!(headless::run(config, None, false), headless::EXIT_USAGE);
+}
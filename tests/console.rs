@@ -0,0 +1,54 @@
+// Device console: input encoding, history and the RX/TX view.
+
+use TempSenseGUI::console::{self, Console, LineEnding, ViewMode};
+use TempSenseGUI::esp_comm::{RawChunk, RawDirection};
+
+#[test]
+fn input_is_encoded_with_the_selected_line_ending() {
+    let mut console = Console::default();
+    console.input = "PING".to_string();
+    assert_eq!(console.encode_input().unwrap(), b"PING\n");
+    console.line_ending = LineEnding::CrLf;
+    assert_eq!(console.encode_input().unwrap(), b"PING\r\n");
+    console.line_ending = LineEnding::None;
+    assert_eq!(console.encode_input().unwrap(), b"PING");
+
+    console.hex_input = true;
+    console.input = "c0 0x01,DB dc".to_string();
+    assert_eq!(console.encode_input().unwrap(), [0xC0, 0x01, 0xDB, 0xDC]);
+    console.input = "C0 1".to_string();
+    assert!(console.take_input().is_err());
+    assert_eq!(console.input, "C0 1", "invalid input is kept for editing");
+    assert!(console::parse_hex("zz").is_err());
+}
+
+#[test]
+fn history_is_browsed_with_up_and_down() {
+    let mut console = Console::default();
+    for command in ["PING", "setTemp 20", "setTemp 20", "tempActive 1"] {
+        console.input = command.to_string();
+        console.take_input().unwrap();
+    }
+    assert_eq!(console.history(), ["PING", "setTemp 20", "tempActive 1"]);
+
+    console.history_prev();
+    assert_eq!(console.input, "tempActive 1");
+    console.history_prev();
+    console.history_prev();
+    console.history_prev();
+    assert_eq!(console.input, "PING");
+    console.history_next();
+    assert_eq!(console.input, "setTemp 20");
+    console.history_next();
+    console.history_next();
+    assert_eq!(console.input, "");
+}
+
+#[test]
+fn chunks_are_shown_as_text_or_hex() {
+    let chunk = RawChunk { direction: RawDirection::Rx, timestamp: chrono::Local::now(), bytes: b"OK\r\n\xC0".to_vec() };
+    let text = console::format_chunk(&chunk, ViewMode::Text);
+    assert!(text.ends_with("RX< OK\\r\\n\\xC0"), "{}", text);
+    let hex = console::format_chunk(&chunk, ViewMode::Hex);
+    assert!(hex.ends_with("RX< 4F 4B 0D 0A C0"), "{}", hex);
+}
@@ -0,0 +1,119 @@
+// Drives the controller without any UI, against simulated modules.
+
+mod common;
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use TempSenseGUI::controller::{self, Controller, SafetyLimits};
+use TempSenseGUI::osc::OscInput;
+use common::tick_until;
+
+fn controller_with_sim_ports() -> Controller {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.modules[1].port = "sim:R".to_string();
+    controller
+}
+
+#[test]
+fn osc_targets_reach_connected_modules() {
+    let mut controller = controller_with_sim_ports();
+    let (osc_sender, osc_receiver) = mpsc::channel();
+    controller.set_osc_receiver(osc_receiver);
+    controller.connect(0);
+    controller.connect(1);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules.iter().all(|m| m.connected)));
+
+    osc_sender.send(OscInput::Target(1, 12.0)).unwrap();
+    let sent = |c: &Controller| c.modules[1].sent_target() == Some(12.0);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), sent));
+    assert_eq!(controller.modules[1].target, 12.0);
+
+    // Telemetry from the simulated module fills in the skin temperature and the telemetry log
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[1].skin_temp.is_some()));
+    assert!(controller.telemetry_log.iter().any(|line| line.source == "ESP R"));
+    controller.shutdown();
+    assert!(controller.modules.iter().all(|m| !m.connected && !m.is_worker_running()));
+}
+
+#[test]
+fn targets_are_clamped_and_manual_override_ignores_osc() {
+    let mut controller = controller_with_sim_ports();
+    controller.update_pelt_temp(0, 100.0);
+    assert_eq!(controller.modules[0].target, controller.limits.value_max);
+
+    controller.modules[0].manual_override = true;
+    assert!(controller.set_manual_target(0, 5.0));
+    controller.update_pelt_temp(0, 20.0);
+    assert_eq!(controller.modules[0].target, 5.0);
+
+    // Unknown ids fall back to the first module
+    controller.update_pelt_temp(7, 3.0);
+    assert_eq!(controller.modules[0].target, 5.0);
+    controller.update_pelt_temp(-1, 3.0);
+    assert_eq!(controller.modules[1].target, 0.0);
+}
+
+#[test]
+fn running_controller_activates_modules_when_they_connect() {
+    let mut controller = controller_with_sim_ports();
+    controller.start();
+    controller.connect(0);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].active));
+    controller.shutdown();
+    assert!(!controller.modules[0].active);
+}
+
+#[test]
+fn modules_are_only_activated_once_they_have_a_target() {
+    let mut controller = controller_with_sim_ports();
+    controller.connect(0);
+    controller.connect(1);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules.iter().all(|m| m.active_protocol.is_some())));
+    controller.activate_targeted();
+    assert!(controller.modules.iter().all(|m| !m.active), "no module was asked for a temperature yet");
+
+    controller.update_pelt_temp(1, 28.0);
+    controller.tick();
+    controller.activate_targeted();
+    assert!(!controller.modules[0].active && controller.modules[1].active);
+    assert_eq!(controller.modules[1].sent_target(), Some(28.0), "the target goes out before the module is activated");
+
+    controller.update_pelt_temp(0, 30.0);
+    controller.tick();
+    controller.activate_targeted();
+    assert!(controller.modules[0].active);
+    controller.shutdown();
+}
+
+#[test]
+fn targets_keep_their_decimals_unless_the_firmware_is_too_old() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.modules[1].port = "sim:R:legacy".to_string();
+    controller.connect(0);
+    controller.connect(1);
+    assert!(controller.set_manual_target(0, 20.25));
+    assert!(controller.set_manual_target(1, 20.25));
+    assert_eq!(controller.modules[0].target, 20.3, "rounded to the default 0.1°C");
+
+    let sent = |c: &Controller| c.modules[0].sent_target() == Some(20.3) && c.modules[1].capabilities.is_none() && c.modules[1].sent_target() == Some(20.0);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), sent));
+    assert!(controller.modules[0].capabilities.as_ref().is_some_and(|c| c.float_targets()));
+    // The simulated devices report what they were set to
+    let reported = |c: &Controller, index: usize, temp: f32| c.modules[index].telemetry.and_then(|t| t.target_temp) == Some(temp);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| reported(c, 0, 20.3) && reported(c, 1, 20.0)));
+    controller.shutdown();
+}
+
+#[test]
+fn quantized_targets_stay_inside_the_limits() {
+    let limits = SafetyLimits { value_min: -0.5, value_max: 39.6 };
+    assert_eq!(limits.quantize(39.6, 0), 39.0);
+    assert_eq!(limits.quantize(39.6, 1), 39.6);
+    assert_eq!(limits.quantize(-0.5, 0), 0.0);
+    assert_eq!(limits.quantize(-0.04, 1).to_string(), "0", "no negative zero");
+    assert_eq!(controller::format_target(20.0, 0), "setTemp 20");
+    assert_eq!(controller::format_target(limits.quantize(20.25, 2), 2), "setTemp 20.25");
+}
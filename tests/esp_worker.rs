@@ -0,0 +1,227 @@
+// tests/esp_worker.rs
+//
+// Drives esp_worker_thread through the in-memory mock transport and the simulated device.
+
+mod common;
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::esp_comm::transport::{MockTransport, TransportDescriptor};
+use TempSenseGUI::esp_comm::{EspCommand, EspStatus, RawDirection, WireProtocol, esp_worker_thread};
+use common::{WAIT, wait_for};
+
+// Upper bound for a command to reach the wire while the reader is blocked in a read
+const MAX_COMMAND_LATENCY: Duration = Duration::from_millis(50);
+
+fn spawn_worker() -> (Sender<EspCommand>, Receiver<EspStatus>, JoinHandle<()>) {
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let handle = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+    (command_tx, status_rx, handle)
+}
+
+fn wait_for_tx(mock: &MockTransport, expected: &[u8]) {
+    let deadline = Instant::now() + WAIT;
+    let mut written = Vec::new();
+    while Instant::now() < deadline {
+        written.extend(mock.take_tx());
+        if written == expected {
+            return;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    panic!("expected {:?} on the wire, got {:?}", String::from_utf8_lossy(expected), String::from_utf8_lossy(&written));
+}
+
+fn connect_mock(command_tx: &Sender<EspCommand>, status_rx: &Receiver<EspStatus>, mock: &MockTransport) {
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(status_rx, |s| matches!(s, EspStatus::Connected));
+    wait_for(status_rx, |s| matches!(s, EspStatus::ProtocolSelected(WireProtocol::Ascii)));
+}
+
+#[test]
+fn sends_commands_and_forwards_received_lines() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    command_tx.send(EspCommand::SendCommand("setTemp 20".to_string())).unwrap();
+    wait_for_tx(&mock, b"setTemp 20\n");
+
+    mock.push_rx(b"Skin_Temp_Smoothed:31.50,Ambient:22.0\n");
+    match wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg.starts_with("Skin_Temp"))) {
+        EspStatus::Message(msg) => assert_eq!(msg, "Skin_Temp_Smoothed:31.50,Ambient:22.0"),
+        other => panic!("unexpected status {:?}", other),
+    }
+
+    command_tx.send(EspCommand::Disconnect).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Disconnected(_)));
+    handle.join().unwrap();
+}
+
+#[test]
+fn lines_split_across_reads_are_forwarded_once_complete() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    mock.push_rx(b"Skin_Temp_Smoo");
+    thread::sleep(Duration::from_millis(30));
+    mock.push_rx(b"thed:31.50\nOK set");
+    thread::sleep(Duration::from_millis(30));
+    mock.push_rx(b"Temp 20\n");
+    let mut messages = Vec::new();
+    while messages.len() < 2 {
+        match wait_for(&status_rx, |s| matches!(s, EspStatus::Message(_))) {
+            EspStatus::Message(msg) => messages.push(msg),
+            other => panic!("unexpected status {:?}", other),
+        }
+    }
+    assert_eq!(messages, ["Skin_Temp_Smoothed:31.50", "OK setTemp 20"]);
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn raw_input_is_written_as_is_and_the_stream_is_captured() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    command_tx.send(EspCommand::CaptureRaw(true)).unwrap();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    command_tx.send(EspCommand::SendRaw(b"PING\r\n".to_vec())).unwrap();
+    wait_for_tx(&mock, b"PING\r\n");
+    match wait_for(&status_rx, |s| matches!(s, EspStatus::Raw(_))) {
+        EspStatus::Raw(chunk) => assert_eq!((chunk.direction, chunk.bytes), (RawDirection::Tx, b"PING\r\n".to_vec())),
+        other => panic!("unexpected status {:?}", other),
+    }
+
+    mock.push_rx(b"PONG\n");
+    match wait_for(&status_rx, |s| matches!(s, EspStatus::Raw(_))) {
+        EspStatus::Raw(chunk) => assert_eq!((chunk.direction, chunk.bytes), (RawDirection::Rx, b"PONG\n".to_vec())),
+        other => panic!("unexpected status {:?}", other),
+    }
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn commands_are_written_while_a_read_is_pending() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    // Let the reader settle into a blocking read, then measure how fast a command goes out
+    thread::sleep(Duration::from_millis(30));
+    let sent_at = Instant::now();
+    command_tx.send(EspCommand::SendCommand("tempActive 1".to_string())).unwrap();
+    wait_for_tx(&mock, b"tempActive 1\n");
+    assert!(sent_at.elapsed() < MAX_COMMAND_LATENCY, "command took {:?}", sent_at.elapsed());
+
+    match wait_for(&status_rx, |s| matches!(s, EspStatus::CommandLatency(_))) {
+        EspStatus::CommandLatency(latency) => {
+            assert_eq!(latency.count, 1);
+            assert!(latency.max < MAX_COMMAND_LATENCY, "reported {:?}", latency.max);
+        }
+        other => panic!("unexpected status {:?}", other),
+    }
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn failed_open_keeps_worker_alive_for_reconnect() {
+    let mock = MockTransport::new();
+    mock.set_fail_open(Some(io::ErrorKind::NotFound));
+    let (command_tx, status_rx, handle) = spawn_worker();
+
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Error(_)));
+    assert_eq!(mock.open_count(), 0);
+
+    mock.set_fail_open(None);
+    connect_mock(&command_tx, &status_rx, &mock);
+    assert_eq!(mock.open_count(), 1);
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn second_connect_while_connected_is_rejected() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Error(msg) if msg.contains("Already connected")));
+    assert_eq!(mock.open_count(), 1);
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn read_error_disconnects_and_ends_worker() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    mock.fail_next_read(io::ErrorKind::BrokenPipe);
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Error(_)));
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Disconnected(Some(_))));
+    handle.join().unwrap();
+}
+
+#[test]
+fn write_error_disconnects_and_ends_worker() {
+    let mock = MockTransport::new();
+    let (command_tx, status_rx, handle) = spawn_worker();
+    connect_mock(&command_tx, &status_rx, &mock);
+
+    mock.fail_next_write(io::ErrorKind::BrokenPipe);
+    command_tx.send(EspCommand::SendCommand("tempActive 1".to_string())).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Disconnected(Some(msg)) if msg.contains("Failed to send")));
+    handle.join().unwrap();
+}
+
+#[test]
+fn simulated_device_negotiates_binary_framing_and_reports_telemetry() {
+    let (command_tx, status_rx, handle) = spawn_worker();
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Simulated, WireProtocol::Binary)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(WireProtocol::Binary)));
+
+    command_tx.send(EspCommand::SendCommand("PING".to_string())).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "PONG"));
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg.starts_with("Skin_Temp_Smoothed:")));
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn binary_request_falls_back_to_ascii_without_acknowledgement() {
+    let mock = MockTransport::new();
+    mock.push_rx(b"TempSense v1.2\nSkin_Temp_Smoo");
+    let (command_tx, status_rx, handle) = spawn_worker();
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Binary)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "TempSense v1.2"));
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg.contains("falling back to ASCII")));
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(WireProtocol::Ascii)));
+    wait_for_tx(&mock, b"setProto bin\n");
+
+    // The line cut off by the fallback is completed by the next read
+    mock.push_rx(b"thed:31.50\n");
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "Skin_Temp_Smoothed:31.50"));
+    command_tx.send(EspCommand::SendCommand("tempActive 1".to_string())).unwrap();
+    wait_for_tx(&mock, b"tempActive 1\n");
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
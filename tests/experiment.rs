@@ -0,0 +1,101 @@
+// Experiment protocols: reproducible schedules, validation and a short run against the controller.
+
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+use rosc::{OscPacket, OscType};
+use TempSenseGUI::controller::{Controller, SafetyLimits};
+use TempSenseGUI::experiment::{MARKER_ADDRESS, Protocol};
+
+fn example_protocol() -> Protocol {
+    Protocol::from_json(include_str!("../examples/cold_pulses.protocol.json")).unwrap()
+}
+
+fn names(controller: &Controller) -> Vec<String> {
+    controller.modules.iter().map(|m| m.short_name.clone()).collect()
+}
+
+#[test]
+fn schedule_is_reproducible_for_a_seed() {
+    let protocol = example_protocol();
+    protocol.validate(&names(&Controller::default()), &SafetyLimits::default()).unwrap();
+
+    let schedule = protocol.schedule(42);
+    assert_eq!(schedule.len(), 1 + 10 * 4);
+    assert_eq!(schedule, protocol.schedule(42));
+    assert_ne!(schedule, protocol.schedule(43));
+    assert_eq!(schedule[0].step, "hold");
+    // Trials are shuffled as a whole, so every cold step is followed by its rest
+    for pair in schedule[1..].chunks(2) {
+        assert_eq!((pair[0].step.as_str(), pair[1].step.as_str()), ("cold", "rest"));
+        assert_eq!(pair[0].trial, pair[1].trial);
+    }
+    for repetition in 1..=10 {
+        let trials: Vec<_> = schedule.iter().filter(|s| s.repetition == repetition && s.block == "stimuli").map(|s| &s.trial).collect();
+        assert_eq!(trials.len(), 4, "each trial once per repetition");
+    }
+}
+
+#[test]
+fn validation_reports_unknown_modules_and_unsafe_targets() {
+    let mut protocol = example_protocol();
+    protocol.blocks[1].trials[0].steps[0].targets.insert("X".to_string(), 20.0);
+    protocol.blocks[1].trials[1].steps[0].targets.insert("R".to_string(), -30.0);
+    protocol.blocks[1].repetitions = 0;
+    let error = protocol.validate(&names(&Controller::default()), &SafetyLimits::default()).unwrap_err();
+    for expected in ["no module named 'X'", "-30°C", "repetitions"] {
+        assert!(error.contains(expected), "missing '{}' in: {}", expected, error);
+    }
+    assert!(Protocol::from_json(r#"{"name": "x", "blocks": [], "extra": 1}"#).is_err());
+}
+
+#[test]
+fn runner_applies_steps_and_sends_markers() {
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let protocol = Protocol::from_json(&format!(r#"{{
+        "name": "short",
+        "markers": "{}",
+        "blocks": [{{ "name": "b", "repetitions": 2, "trials": [{{ "name": "t", "steps": [
+            {{ "name": "warm", "duration_secs": 0.05, "targets": {{ "L": 30 }} }},
+            {{ "name": "cool", "duration_secs": 0.05, "targets": {{ "L": 20 }} }}
+        ] }}] }}]
+    }}"#, listener.local_addr().unwrap())).unwrap();
+
+    let mut controller = Controller::default();
+    controller.start_experiment(protocol.clone()).unwrap();
+    assert!(controller.start_experiment(protocol).is_err(), "one protocol at a time");
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 30.0);
+    controller.update_pelt_temp(0, 5.0); // OSC doesn't interfere with driven modules
+    assert_eq!(controller.modules[0].target, 30.0);
+
+    controller.pause_experiment();
+    thread::sleep(Duration::from_millis(80));
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 30.0, "paused steps don't advance");
+    controller.resume_experiment();
+
+    let mut targets = vec![30.0];
+    while controller.experiment.is_some() {
+        controller.tick();
+        if targets.last() != Some(&controller.modules[0].target) {
+            targets.push(controller.modules[0].target);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(targets, [30.0, 20.0, 30.0, 20.0]);
+
+    let mut events = Vec::new();
+    let mut buf = [0u8; rosc::decoder::MTU];
+    while !events.contains(&"end".to_string()) {
+        let size = listener.recv(&mut buf).expect("marker");
+        let (_, packet) = rosc::decoder::decode_udp(&buf[..size]).unwrap();
+        let OscPacket::Message(message) = packet else { panic!("expected a message") };
+        assert_eq!(message.addr, MARKER_ADDRESS);
+        let Some(OscType::String(event)) = message.args.first() else { panic!("expected an event name") };
+        events.push(event.clone());
+    }
+    assert_eq!(events, ["start", "step", "pause", "resume", "step", "step", "step", "end"]);
+}
@@ -0,0 +1,109 @@
+// Firmware updates through the ROM bootloader protocol, against the simulated bootloader.
+
+use std::time::Duration;
+
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::bootloader_sim::SimulatedBootloader;
+use TempSenseGUI::esp_comm::flasher::{self, FlashOptions, FlashProgress, FlashStage, DEFAULT_APP_OFFSET};
+
+// Application image with an ESP-IDF app description, `size` bytes long
+fn image(size: usize) -> Vec<u8> {
+    let mut image: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+    image[..32].fill(0);
+    image[0] = 0xE9;
+    image[1] = 1; // Segments
+    image[32..36].copy_from_slice(&0xABCD_5432u32.to_le_bytes());
+    image[48..80].fill(0);
+    image[48..53].copy_from_slice(b"1.4.0");
+    image[80..112].fill(0);
+    image[80..89].copy_from_slice(b"tempsense");
+    image
+}
+
+fn flash(device: &mut SimulatedBootloader, image: &[u8]) -> (Result<flasher::FlashReport, String>, Vec<FlashProgress>) {
+    let mut progress = Vec::new();
+    let result = flasher::flash_image(device, image, &FlashOptions::default(), &mut |p| progress.push(p));
+    (result, progress)
+}
+
+#[test]
+fn md5_and_image_checks() {
+    assert_eq!(flasher::hex(&flasher::md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(flasher::hex(&flasher::md5(b"The quick brown fox jumps over the lazy dog")), "9e107d9d372bb6826bd81d3542a419d6");
+
+    let info = flasher::inspect_image(&image(5000), DEFAULT_APP_OFFSET).unwrap();
+    assert_eq!((info.version.as_deref(), info.project.as_deref(), info.size), (Some("1.4.0"), Some("tempsense"), 5000));
+
+    let mut not_an_image = image(5000);
+    not_an_image[0] = b'{';
+    assert!(flasher::inspect_image(&not_an_image, DEFAULT_APP_OFFSET).unwrap_err().contains("0xE9"));
+    let mut other_chip = image(5000);
+    other_chip[12] = 2; // ESP32-S2
+    assert!(flasher::inspect_image(&other_chip, DEFAULT_APP_OFFSET).unwrap_err().contains("another chip"));
+    assert!(flasher::inspect_image(&image(5000), (flasher::FLASH_SIZE - 100) as u32).unwrap_err().contains("does not fit"));
+}
+
+#[test]
+fn image_is_written_verified_and_started() {
+    let mut device = SimulatedBootloader::new();
+    let image = image(5000); // Not a whole number of blocks
+    let (result, progress) = flash(&mut device, &image);
+    let report = result.unwrap();
+    assert!(report.verified && report.bytes == 5000);
+
+    let offset = DEFAULT_APP_OFFSET as usize;
+    assert_eq!(&device.flash[offset..offset + image.len()], &image[..]);
+    assert!(device.flash[offset + image.len()..offset + 5 * flasher::BLOCK_SIZE].iter().all(|&b| b == 0xFF), "padding stays erased");
+    assert!(!device.in_bootloader && device.app_starts == 1, "restarted into the new firmware");
+    assert_eq!(device.baud_rate, flasher::DEFAULT_FLASH_BAUD_RATE);
+
+    let stages: Vec<FlashStage> = progress.iter().map(|p| p.stage).collect();
+    for stage in [FlashStage::Syncing, FlashStage::Erasing, FlashStage::Writing, FlashStage::Verifying, FlashStage::Restarting] {
+        assert!(stages.contains(&stage), "{:?} missing from {:?}", stage, stages);
+    }
+    let written = progress.iter().rfind(|p| p.stage == FlashStage::Writing).unwrap();
+    assert_eq!((written.done, written.total), (5000, 5000));
+}
+
+#[test]
+fn failures_explain_what_happened_to_the_old_firmware() {
+    // No auto-reset circuit: nothing was touched and the board runs its old firmware
+    let mut device = SimulatedBootloader::new();
+    device.auto_reset = false;
+    let error = flash(&mut device, &image(2000)).0.unwrap_err();
+    assert!(error.contains("No answer from the bootloader") && error.contains("not changed"), "{}", error);
+
+    // A write error half way leaves the board in the bootloader
+    let mut device = SimulatedBootloader::new();
+    device.fail_block = Some(2);
+    let error = flash(&mut device, &image(5000)).0.unwrap_err();
+    assert!(error.contains("block 3/5") && error.contains("flash write error") && error.contains("roll back"), "{}", error);
+    assert!(device.in_bootloader);
+
+    let mut device = SimulatedBootloader::new();
+    device.corrupt_block = Some(1);
+    let error = flash(&mut device, &image(5000)).0.unwrap_err();
+    assert!(error.contains("Verification failed") && error.contains("roll back"), "{}", error);
+    assert!(device.in_bootloader && device.app_starts == 0);
+}
+
+#[test]
+fn controller_flashes_disconnected_modules_in_the_background() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.connect(0);
+    let error = controller.start_flash(0, image(3000), FlashOptions::default()).unwrap_err();
+    assert!(error.contains("Disconnect"), "{}", error);
+    controller.disconnect_all();
+
+    controller.start_flash(0, image(3000), FlashOptions::default()).unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while controller.modules[0].flash.as_ref().unwrap().is_running() && std::time::Instant::now() < deadline {
+        controller.tick();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let job = controller.modules[0].flash.as_ref().unwrap();
+    assert!(job.result.as_ref().unwrap().as_ref().is_ok_and(|report| report.verified), "{:?}", job.result);
+    assert_eq!(job.image.version.as_deref(), Some("1.4.0"));
+    controller.shutdown();
+}
@@ -0,0 +1,116 @@
+// Binary framing: the CRC, SLIP escaping, the incremental decoder and how the worker reports
+// corrupted frames.
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+
+use TempSenseGUI::esp_comm::framing::{self, Frame, FrameDecoder, FrameError, FrameType, MAX_FRAME_LEN, SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC};
+use TempSenseGUI::esp_comm::transport::{MockTransport, TransportDescriptor};
+use TempSenseGUI::esp_comm::{EspCommand, EspStatus, WireProtocol, esp_worker_thread};
+use common::wait_for;
+
+fn text(payload: &str) -> Frame {
+    Frame { frame_type: FrameType::Text, payload: payload.as_bytes().to_vec() }
+}
+
+#[test]
+fn crc_matches_the_ccitt_false_check_value() {
+    assert_eq!(framing::crc16(b"123456789"), 0x29B1);
+    assert_eq!(framing::crc16(b""), 0xFFFF);
+}
+
+#[test]
+fn special_bytes_are_escaped_and_round_trip() {
+    let mut escaped = Vec::new();
+    framing::slip_escape_into(&[0x01, SLIP_END, 0x02, SLIP_ESC], &mut escaped);
+    assert_eq!(escaped, [0x01, SLIP_ESC, SLIP_ESC_END, 0x02, SLIP_ESC, SLIP_ESC_ESC]);
+
+    let payload = [b'a', SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_END, b'z'];
+    let encoded = framing::encode_frame(FrameType::Telemetry, &payload);
+    assert_eq!((encoded[0], encoded[encoded.len() - 1]), (SLIP_END, SLIP_END));
+    assert_eq!(encoded.iter().filter(|&&b| b == SLIP_END).count(), 2, "END only delimits");
+    let frames = FrameDecoder::new().push(&encoded);
+    assert_eq!(frames, [Ok(Frame { frame_type: FrameType::Telemetry, payload: payload.to_vec() })]);
+}
+
+#[test]
+fn frames_can_arrive_a_byte_at_a_time() {
+    let mut decoder = FrameDecoder::new();
+    let mut frames = Vec::new();
+    for byte in [framing::encode_frame(FrameType::Text, b"PONG"), framing::encode_frame(FrameType::Text, b"OK")].concat() {
+        frames.extend(decoder.push(&[byte]));
+    }
+    assert_eq!(frames, [Ok(text("PONG")), Ok(text("OK"))]);
+}
+
+#[test]
+fn invalid_escapes_and_oversized_frames_are_rejected() {
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decoder.push(&[SLIP_END, 0x03, SLIP_ESC, b'x', b'y', SLIP_END]), [Err(FrameError::BadEscape(b'x'))]);
+
+    let oversized = framing::encode_frame(FrameType::Text, &vec![b'a'; MAX_FRAME_LEN]);
+    assert_eq!(decoder.push(&oversized), [Err(FrameError::Oversized)]);
+    assert_eq!(decoder.push(&[SLIP_END, 0x03, SLIP_END]), [Err(FrameError::TooShort(1))]);
+
+    // Each error only costs its own frame
+    assert_eq!(decoder.push(&framing::encode_frame(FrameType::Text, b"OK")), [Ok(text("OK"))]);
+}
+
+#[test]
+fn decoder_resynchronizes_after_garbage() {
+    let mut decoder = FrameDecoder::new();
+    // Noise before the first END (an ASCII boot banner, say) decodes as one broken frame
+    let mut bytes = b"ets Jun  8 2016 rst:0x1\r\n".to_vec();
+    bytes.extend(framing::encode_frame(FrameType::Text, b"ready"));
+    let frames = decoder.push(&bytes);
+    assert_eq!(frames.len(), 2, "{:?}", frames);
+    assert!(frames[0].is_err());
+    assert_eq!(frames[1], Ok(text("ready")));
+
+    let crc = framing::crc16(&[0x07]).to_be_bytes();
+    assert_eq!(decoder.push(&[0x07, crc[0], crc[1], SLIP_END, SLIP_END]), [Err(FrameError::UnknownType(0x07))]);
+    assert_eq!(decoder.push(&framing::encode_frame(FrameType::Text, b"OK")), [Ok(text("OK"))]);
+}
+
+#[test]
+fn flipped_byte_fails_the_crc() {
+    let mut encoded = framing::encode_frame(FrameType::Telemetry, b"Skin_Temp_Smoothed:31.50");
+    encoded[5] ^= 0x01;
+    let frames = FrameDecoder::new().push(&encoded);
+    assert!(matches!(frames[..], [Err(FrameError::BadCrc { .. })]), "{:?}", frames);
+}
+
+#[test]
+fn worker_counts_corrupt_frames_and_keeps_decoding() {
+    let mock = MockTransport::new();
+    mock.push_rx(b"PROTO:BIN\n");
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let handle = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Binary)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(WireProtocol::Binary)));
+
+    for _ in 0..2 {
+        let mut corrupted = framing::encode_frame(FrameType::Text, b"OK setTemp 20");
+        corrupted[4] ^= 0x20;
+        mock.push_rx(&corrupted);
+    }
+    mock.push_rx(&framing::encode_frame(FrameType::Text, b"OK setTemp 21"));
+    let status = wait_for(&status_rx, |s| matches!(s, EspStatus::CorruptFrame(2, _) | EspStatus::Message(_)));
+    assert!(matches!(&status, EspStatus::CorruptFrame(2, reason) if reason.contains("CRC")), "{:?}", status);
+    let EspStatus::Message(message) = wait_for(&status_rx, |s| matches!(s, EspStatus::Message(_))) else { unreachable!() };
+    assert_eq!(message, "OK setTemp 21", "corrupt frames are never forwarded");
+
+    // A command echoed back is not taken for a reply
+    mock.push_rx(&framing::encode_frame(FrameType::Command, b"OK setTemp 22"));
+    mock.push_rx(&framing::encode_frame(FrameType::Telemetry, b"Skin_Temp_Smoothed:31.50"));
+    let status = wait_for(&status_rx, |s| matches!(s, EspStatus::CorruptFrame(..) | EspStatus::Message(_)));
+    assert!(matches!(&status, EspStatus::CorruptFrame(3, reason) if reason.contains("Command")), "{:?}", status);
+    let EspStatus::Message(message) = wait_for(&status_rx, |s| matches!(s, EspStatus::Message(_))) else { unreachable!() };
+    assert_eq!(message, "Skin_Temp_Smoothed:31.50");
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
@@ -0,0 +1,53 @@
+// Structured application log: filtering and log file rotation.
+
+use TempSenseGUI::logging::{LogBuffer, LogEntry, LogFileConfig, LogFilter, rotated_path};
+
+fn entry(level: log::Level, source: &str, message: &str) -> LogEntry {
+    LogEntry { timestamp: chrono::Local::now(), level, source: source.to_string(), message: message.to_string() }
+}
+
+#[test]
+fn filter_by_level_source_and_search() {
+    let entries = [
+        entry(log::Level::Info, "ESP L", "Connected."),
+        entry(log::Level::Debug, "ESP L", "Device: OK setTemp 20"),
+        entry(log::Level::Warn, "ESP R", "Dropped corrupt frame (1 total): bad CRC"),
+        entry(log::Level::Error, "APP", "Can't open log file"),
+    ];
+    let shown = |filter: &LogFilter| entries.iter().filter(|e| filter.matches(e)).count();
+
+    let mut filter = LogFilter::default();
+    assert_eq!(shown(&filter), 3, "debug is hidden by default");
+    filter.min_level = log::Level::Debug;
+    assert_eq!(shown(&filter), 4);
+    filter.source = "ESP L".to_string();
+    assert_eq!(shown(&filter), 2);
+    filter.search = "settemp".to_string();
+    assert_eq!(shown(&filter), 1);
+    filter.source.clear();
+    filter.search = "esp r".to_string();
+    assert_eq!(shown(&filter), 1);
+}
+
+#[test]
+fn log_file_is_rotated() {
+    let dir = std::env::temp_dir().join(format!("tempsense-log-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("app.log");
+    let buffer = LogBuffer::default();
+    buffer.set_file(Some(LogFileConfig { path: path.clone(), max_bytes: 200, max_files: 2 })).unwrap();
+
+    for n in 0..20 {
+        buffer.push(entry(log::Level::Info, "APP", &format!("message {}", n)));
+    }
+    buffer.set_file(None).unwrap();
+
+    let current = std::fs::read_to_string(&path).unwrap();
+    assert!(current.contains("INFO  [APP] message 19"), "{}", current);
+    assert!(current.len() <= 200);
+    assert!(rotated_path(&path, 1).exists() && rotated_path(&path, 2).exists());
+    assert!(!rotated_path(&path, 3).exists(), "only max_files rotated files are kept");
+    assert_eq!(buffer.snapshot().len(), 20);
+    assert_eq!(buffer.recent(5)[0].message, "message 15");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,106 @@
+// tests/network_transport.rs
+//
+// Runs esp_worker_thread over the TCP and UDP transports against local stand-ins for a wristband.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+use TempSenseGUI::esp_comm::transport::TransportDescriptor;
+use TempSenseGUI::esp_comm::{EspCommand, EspStatus, WireProtocol, esp_worker_thread};
+use common::{WAIT, wait_for};
+
+#[test]
+fn tcp_echo_server_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 256];
+        while let Ok(n) = stream.read(&mut buf) {
+            if n == 0 || stream.write_all(&buf[..n]).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let worker = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+
+    let descriptor = TransportDescriptor::from_port_name(&format!("tcp://{}", address), 0);
+    command_tx.send(EspCommand::Connect(descriptor, WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Connected));
+
+    command_tx.send(EspCommand::SendCommand("setTemp 25".to_string())).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "setTemp 25"));
+
+    command_tx.send(EspCommand::Disconnect).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Disconnected(_)));
+    worker.join().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn tcp_connect_failure_is_reported() {
+    // Bind then drop to get a local port with nothing listening on it
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let worker = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+
+    let descriptor = TransportDescriptor::Tcp { address: address.to_string(), connect_timeout_ms: 500 };
+    command_tx.send(EspCommand::Connect(descriptor, WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Error(msg) if msg.starts_with("Failed to connect")));
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    worker.join().unwrap();
+}
+
+#[test]
+fn udp_local_port_is_the_device_port_unless_given() {
+    let descriptor = TransportDescriptor::from_port_name("udp://192.168.4.1:4210", 0);
+    assert!(matches!(&descriptor, TransportDescriptor::Udp { device_address, local_port: 4210 } if device_address == "192.168.4.1:4210"), "{:?}", descriptor);
+    let descriptor = TransportDescriptor::from_port_name("udp://192.168.4.1:4210?local=4211", 0);
+    assert!(matches!(&descriptor, TransportDescriptor::Udp { device_address, local_port: 4211 } if device_address == "192.168.4.1:4210"), "{:?}", descriptor);
+    assert_eq!(descriptor.to_string(), "udp://192.168.4.1:4210?local=4211");
+    // An invalid local port fails to open, naming it
+    let descriptor = TransportDescriptor::from_port_name("udp://127.0.0.1:4210?local=x", 0);
+    let error = descriptor.open().err().expect("opened with an invalid local port");
+    assert!(error.to_string().contains("?local=x"), "{}", error);
+}
+
+#[test]
+fn udp_telemetry_and_commands() {
+    let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+    device.set_read_timeout(Some(WAIT)).unwrap();
+    let host_port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let worker = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+
+    let descriptor = TransportDescriptor::Udp { device_address: device.local_addr().unwrap().to_string(), local_port: host_port };
+    command_tx.send(EspCommand::Connect(descriptor, WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Connected));
+
+    command_tx.send(EspCommand::SendCommand("tempActive 1".to_string())).unwrap();
+    let mut buf = [0u8; 256];
+    let (n, host_addr) = device.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"tempActive 1\n");
+
+    device.send_to(b"Skin_Temp_Smoothed:30.10\n", host_addr).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "Skin_Temp_Smoothed:30.10"));
+
+    // Telemetry sent from another socket of the device still arrives
+    let telemetry_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    telemetry_socket.send_to(b"Skin_Temp_Smoothed:30.20\n", host_addr).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg == "Skin_Temp_Smoothed:30.20"));
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    worker.join().unwrap();
+}
@@ -0,0 +1,129 @@
+// Recording outlet: the JSON-lines format, a local client following a running controller and the
+// settings in the config.
+
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::outlet::{OutletEvent, OutletSettings, STREAM_NAME};
+
+const WAIT: Duration = Duration::from_secs(3);
+
+// Parses every line the outlet at `address` sends, in a thread of its own
+fn client(address: std::net::SocketAddr) -> mpsc::Receiver<OutletEvent> {
+    let stream = TcpStream::connect(address).unwrap();
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { return };
+            let event = serde_json::from_str(&line).unwrap_or_else(|e| panic!("{}: {}", e, line));
+            if event_tx.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    event_rx
+}
+
+// Ticks the controller until `pred` matches an event, returning everything received on the way
+fn collect_until(controller: &mut Controller, events: &mpsc::Receiver<OutletEvent>, pred: impl Fn(&OutletEvent) -> bool) -> Vec<OutletEvent> {
+    let deadline = Instant::now() + WAIT;
+    let mut received = Vec::new();
+    while Instant::now() < deadline {
+        controller.tick();
+        while let Ok(event) = events.try_recv() {
+            let done = pred(&event);
+            received.push(event);
+            if done {
+                return received;
+            }
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    panic!("timed out, received {:?}", received);
+}
+
+fn is_marker(event: &OutletEvent, name: &str) -> bool {
+    matches!(event, OutletEvent::Marker { marker, .. } if marker == name)
+}
+
+#[test]
+fn events_are_json_lines() {
+    let line = OutletEvent::marker(1_500_000, "START", None).to_line();
+    assert!(line.starts_with(r#"{"type":"marker","t":1.5,"unix":"#) && line.ends_with("\"marker\":\"START\",\"module\":null}\n"), "{}", line);
+    let sample = OutletEvent::sample(2_000_000, "L", 20.5, Some(20.5), None, true);
+    assert_eq!(serde_json::from_str::<OutletEvent>(sample.to_line().trim()).unwrap(), sample);
+    let (OutletEvent::Marker { unix: first, .. }, OutletEvent::Sample { unix: second, .. }) = (OutletEvent::marker(1_500_000, "START", None), sample) else { unreachable!() };
+    assert!((second - first - 0.5).abs() < 1e-5, "the wall clock follows the host clock");
+}
+
+#[test]
+fn client_receives_telemetry_and_markers() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.modules[1].port = "sim:R".to_string();
+    controller.outlet_settings.address = "127.0.0.1:0".to_string();
+    let address = controller.start_outlet().unwrap();
+    let events = client(address);
+
+    let header = events.recv_timeout(WAIT).unwrap();
+    assert!(matches!(&header, OutletEvent::Header { stream, modules, .. } if stream == STREAM_NAME && *modules == ["L", "R"]), "{:?}", header);
+
+    controller.connect(0);
+    controller.connect(1);
+    controller.set_manual_target(0, 25.0);
+    let deadline = Instant::now() + WAIT;
+    while !controller.modules.iter().all(|m| m.connected && m.active_protocol.is_some()) && Instant::now() < deadline {
+        controller.tick();
+        thread::sleep(Duration::from_millis(5));
+    }
+    controller.start();
+    let received = collect_until(&mut controller, &events, |event| is_marker(event, "START"));
+    let Some(OutletEvent::Marker { t: start, module: None, .. }) = received.last() else { unreachable!() };
+
+    // Samples from both modules, timestamped by the device clock once it is synchronized
+    let received = collect_until(&mut controller, &events, |event| matches!(event, OutletEvent::Sample { module, device_time: true, t, .. } if module == "R" && t > start));
+    let left = received.iter().find_map(|event| match event {
+        OutletEvent::Sample { module, target, skin, .. } if module == "L" => Some((*target, *skin)),
+        _ => None,
+    });
+    assert!(matches!(left, Some((25.0, Some(_)))), "{:?}", received);
+
+    controller.modules[1].manual_override = true;
+    let received = collect_until(&mut controller, &events, |event| is_marker(event, "OVERRIDE_ON"));
+    assert!(matches!(received.last(), Some(OutletEvent::Marker { module: Some(module), .. }) if module == "R"));
+
+    controller.stop();
+    collect_until(&mut controller, &events, |event| is_marker(event, "STOP"));
+    assert_eq!(controller.outlet().map(|outlet| outlet.clients()), Some(1));
+
+    // Stopping closes the connection
+    controller.stop_outlet();
+    let deadline = Instant::now() + WAIT;
+    while events.recv_timeout(Duration::from_millis(50)).is_ok() && Instant::now() < deadline {}
+    assert!(matches!(events.recv_timeout(Duration::from_millis(50)), Err(mpsc::RecvTimeoutError::Disconnected)));
+    controller.shutdown();
+}
+
+#[test]
+fn outlet_settings_are_saved_with_the_config() {
+    let mut controller = Controller::default();
+    controller.outlet_settings = OutletSettings { enabled: true, address: "0.0.0.0:16700".to_string() };
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!(config.outlet, controller.outlet_settings);
+
+    let mut invalid = config.clone();
+    invalid.outlet.address = "localhost".to_string();
+    let error = invalid.validate().unwrap_err();
+    assert!(error.contains("outlet.address: 'localhost' is not an IP:PORT address"), "{}", error);
+
+    // Configurations from before the outlet leave it off
+    let mut value: serde_json::Value = serde_json::from_str(&config.to_json()).unwrap();
+    value["version"] = 8.into();
+    value.as_object_mut().unwrap().remove("outlet");
+    assert_eq!(Config::from_value(value).unwrap().outlet, OutletSettings::default());
+}
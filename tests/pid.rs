@@ -0,0 +1,115 @@
+// Remote PID tuning: the wire format, reading/writing/committing gains and step tests.
+
+mod common;
+
+use std::time::Duration;
+
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::sim::SimulatedEsp;
+use TempSenseGUI::pid::{self, PidGains, PidLoop, PidPreset, PidSettings, StepResponse, StepSample};
+use common::tick_until;
+
+#[test]
+fn gains_are_sent_and_parsed() {
+    let settings = PidSettings { kp: 8.5, ki: 0.25, kd: 1.0, output_min: 0.0, output_max: 80.0, deadband: 0.1 };
+    let command = pid::write_command(PidLoop::Cool, &settings);
+    assert_eq!(command, "setPid cool kp=8.5,ki=0.25,kd=1,min=0,max=80,deadband=0.1");
+    assert_eq!(pid::parse_reply(&format!("PID:cool {}", settings.to_fields())), Some(Ok((PidLoop::Cool, settings))));
+    assert_eq!(pid::parse_reply("OK setPid cool"), None);
+
+    let error = pid::parse_reply("PID:heat kp=1,ki=0,kd=0,min=0,max=100").unwrap().unwrap_err();
+    assert!(error.contains("deadband"), "{}", error);
+    assert!(pid::parse_reply("PID:fan kp=1").unwrap().is_err());
+
+    let invalid = PidSettings { kp: -1.0, output_min: 90.0, output_max: 50.0, ..settings };
+    let problems = invalid.problems();
+    assert!(problems.iter().any(|p| p.starts_with("kp")) && problems.iter().any(|p| p.contains("above output_max")), "{:?}", problems);
+
+    // The simulated firmware refuses them too
+    let mut device = SimulatedEsp::new();
+    device.handle_line(&pid::write_command(PidLoop::Heat, &invalid));
+    let mut reply = [0u8; 256];
+    let count = device.take_output(&mut reply);
+    assert!(String::from_utf8_lossy(&reply[..count]).starts_with("ERR invalid PID settings"));
+    assert_eq!(device.pid, PidGains::default());
+}
+
+#[test]
+fn gains_are_written_read_back_and_committed() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.connect(0);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].connected));
+    assert!(controller.read_pid(0));
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].pid_gains().is_some()));
+    assert_eq!(controller.modules[0].pid_gains(), Some(PidGains::default()));
+
+    let mut gains = PidGains::default();
+    gains.heat.ki = 0.5;
+    gains.cool.deadband = 0.2;
+    controller.write_pid(0, &gains).unwrap();
+    assert!(controller.modules[0].pid_unsaved);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].pid_gains() == Some(gains)));
+
+    assert!(controller.commit_pid(0));
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| !c.modules[0].pid_unsaved));
+
+    gains.heat.output_max = 120.0;
+    assert!(controller.write_pid(0, &gains).unwrap_err().contains("heat.output_max"));
+    controller.shutdown();
+}
+
+#[test]
+fn gain_sets_are_saved_with_the_config() {
+    let mut controller = Controller::default();
+    let mut gains = PidGains::default();
+    gains.cool.kp = 20.0;
+    controller.pid_presets.push(PidPreset { name: "aggressive cooling".to_string(), gains });
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!(config.pid_presets, controller.pid_presets);
+
+    let mut invalid = config.clone();
+    invalid.pid_presets.push(invalid.pid_presets[0].clone());
+    invalid.pid_presets[0].gains.heat.kd = f32::NAN;
+    let error = invalid.validate().unwrap_err();
+    for expected in ["pid_presets[0].gains.heat.kd", "pid_presets[1].name"] {
+        assert!(error.contains(expected), "missing '{}' in: {}", expected, error);
+    }
+}
+
+#[test]
+fn step_response_metrics() {
+    let mut step = StepResponse::new(20.0, 30.0, Duration::from_secs(10));
+    // Rises by 2°C per second, overshoots to 30.5 and settles at 30.1
+    for (t, temp) in [(0.0, 20.0), (1.0, 22.0), (2.0, 24.0), (3.0, 26.0), (4.0, 28.0), (5.0, 30.0), (6.0, 30.5), (7.0, 30.1), (8.0, 30.1)] {
+        step.samples.push(StepSample { t, exterior_temp: temp, heat_output: 0.0, cool_output: 0.0 });
+    }
+    let metrics = step.metrics().unwrap();
+    assert_eq!(metrics.rise_time, Some(4.0)); // 21°C to 29°C
+    assert!((metrics.overshoot - 0.5).abs() < 1e-4, "{:?}", metrics);
+    assert_eq!(metrics.settling_time, Some(7.0));
+    assert_eq!(StepResponse::new(30.0, 30.1, Duration::from_secs(1)).metrics(), None);
+}
+
+#[test]
+fn step_test_records_the_response() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.connect(0);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].connected));
+    assert!(controller.start_step_test(0, 30.0, Duration::from_secs(1)).unwrap_err().contains("START"));
+    controller.start();
+    assert!(tick_until(&mut controller, Duration::from_secs(2), |c| c.modules[0].telemetry.is_some()));
+
+    controller.start_step_test(0, 30.0, Duration::from_millis(800)).unwrap();
+    assert!(controller.modules[0].manual_override);
+    assert_eq!(controller.modules[0].target, 30.0);
+    let finished = |c: &Controller| c.modules[0].step_response.as_ref().is_some_and(StepResponse::is_finished);
+    assert!(tick_until(&mut controller, Duration::from_secs(2), finished));
+    let step = controller.modules[0].step_response.as_ref().unwrap();
+    assert!(step.samples.len() >= 2, "{:?}", step.samples);
+    assert!(step.samples.last().unwrap().exterior_temp > step.from, "heats towards the target: {:?}", step.samples);
+    assert!(step.samples.iter().any(|s| s.heat_output > 0.0));
+    controller.shutdown();
+}
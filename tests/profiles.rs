@@ -0,0 +1,73 @@
+// Named profiles: management rules and persistence of the active profile.
+
+use TempSenseGUI::config::Config;
+use TempSenseGUI::profiles::{self, DEFAULT_PROFILE_NAME, Profiles};
+
+fn vest_config() -> Config {
+    let mut config = Config::default();
+    config.modules = (1..=8).map(|n| {
+        let mut module = config.modules[0].clone();
+        module.short_name = format!("V{}", n);
+        module.long_name = format!("Vest {}", n);
+        module.port = format!("udp://192.168.4.{}:4210", n);
+        module
+    }).collect();
+    config.limits.value_min = 16.0;
+    config
+}
+
+#[test]
+fn create_clone_rename_delete() {
+    let mut profiles = Profiles::default();
+    assert_eq!(profiles.active().name, DEFAULT_PROFILE_NAME);
+
+    let vest = profiles.create("Vest", vest_config()).unwrap();
+    let booth = profiles.clone_profile(vest, " Demo booth ").unwrap();
+    assert_eq!(profiles.profiles()[booth].name, "Demo booth");
+    assert_eq!(profiles.profiles()[booth].config, profiles.profiles()[vest].config);
+
+    assert!(profiles.create("Vest", Config::default()).is_err());
+    assert!(profiles.create("  ", Config::default()).is_err());
+    assert!(profiles.rename(booth, "Vest").is_err());
+    profiles.rename(booth, "Booth").unwrap();
+
+    profiles.set_active(booth).unwrap();
+    assert!(profiles.delete(booth).is_err(), "the active profile can't be deleted");
+    assert_eq!(profiles.delete(0).unwrap().name, DEFAULT_PROFILE_NAME);
+    assert_eq!(profiles.active().name, "Booth");
+    assert_eq!(profiles.profiles().len(), 2);
+}
+
+#[test]
+fn active_profile_is_persisted() {
+    let mut profiles = Profiles::default();
+    let vest = profiles.create("Vest", vest_config()).unwrap();
+    profiles.set_active(vest).unwrap();
+
+    let loaded = profiles::from_storage(Some(profiles.to_json()), None, None).unwrap().unwrap();
+    assert_eq!(loaded, profiles);
+    assert_eq!(loaded.active().config.modules.len(), 8);
+}
+
+#[test]
+fn settings_from_before_profiles_become_the_default_profile() {
+    let mut config = Config::default();
+    config.osc.port = 9100;
+    let loaded = profiles::from_storage(None, Some(config.to_json()), None).unwrap().unwrap();
+    assert_eq!(loaded.profiles().len(), 1);
+    assert_eq!(loaded.active().name, DEFAULT_PROFILE_NAME);
+    assert_eq!(loaded.active().config, config);
+    assert_eq!(profiles::from_storage(None, None, None).unwrap(), None);
+}
+
+#[test]
+fn invalid_profiles_are_reported_by_name() {
+    let mut profiles = Profiles::default();
+    profiles.create("Vest", vest_config()).unwrap();
+    let json = profiles.to_json().replace("\"value_min\": 16", "\"value_min\": 99");
+    let error = Profiles::from_json(&json).unwrap_err();
+    assert!(error.contains("Profile 'Vest'") && error.contains("limits"), "{}", error);
+
+    let json = profiles.to_json().replace("\"active\": \"Default\"", "\"active\": \"Gone\"");
+    assert!(Profiles::from_json(&json).unwrap_err().contains("Gone"));
+}
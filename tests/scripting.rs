@@ -0,0 +1,102 @@
+// Mapping scripts: inputs and outputs, sandbox limits, hot reload and the controller hookup.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::osc::OscInput;
+use TempSenseGUI::scripting::{ModuleInput, Script};
+
+const WATER_MAPPING: &str = include_str!("../examples/water_mapping.rhai");
+
+fn modules() -> Vec<ModuleInput<'static>> {
+    ["L", "R"].into_iter().map(|short_name| ModuleInput { short_name, target: 0.0, telemetry: None, held: false }).collect()
+}
+
+#[test]
+fn osc_inputs_are_mapped_with_state_between_runs() {
+    let mut script = Script::from_source(WATER_MAPPING).unwrap();
+    assert_eq!(script.run(&modules()).unwrap(), [("L".to_string(), 32.0), ("R".to_string(), 32.0)]);
+
+    script.set_osc("/avatar/inWater", 1.0);
+    script.set_osc("/avatar/speed", 1.5);
+    assert_eq!(script.run(&modules()).unwrap(), [("L".to_string(), 18.0), ("R".to_string(), 18.0)]);
+
+    // Inside the hysteresis band nothing changes, so nothing is reported
+    script.set_osc("/avatar/speed", 1.0);
+    assert!(script.run(&modules()).unwrap().is_empty());
+
+    script.set_osc("/avatar/speed", 0.5);
+    assert_eq!(script.run(&modules()).unwrap(), [("L".to_string(), 24.0), ("R".to_string(), 24.0)]);
+}
+
+#[test]
+fn script_errors_are_reported_and_runaway_scripts_stopped() {
+    assert!(Script::from_source("let x = ").is_err());
+
+    let mut endless = Script::from_source("loop { }").unwrap();
+    assert!(endless.run(&modules()).is_err());
+    assert!(endless.error().is_some());
+
+    let mut unknown = Script::from_source("#{ X: 20 }").unwrap();
+    assert!(unknown.run(&modules()).unwrap_err().contains("no module named 'X'"));
+
+    let mut wrong_type = Script::from_source("42").unwrap();
+    assert!(wrong_type.run(&modules()).unwrap_err().contains("expected a map"));
+
+    let eval = Script::from_source(r##"eval("#{ L: 40 }")"##).and_then(|mut script| script.run(&modules()));
+    assert!(eval.is_err(), "eval is disabled");
+}
+
+#[test]
+fn changed_files_are_reloaded_and_broken_ones_keep_the_old_version() {
+    let path = std::env::temp_dir().join(format!("tempsense-script-{}.rhai", std::process::id()));
+    std::fs::write(&path, "#{ L: 20 }").unwrap();
+    let mut script = Script::load_file(&path).unwrap();
+    assert_eq!(script.run(&modules()).unwrap(), [("L".to_string(), 20.0)]);
+
+    thread::sleep(Duration::from_millis(600));
+    std::fs::write(&path, "#{ L: 25 }").unwrap();
+    assert!(script.reload_if_changed());
+    assert_eq!(script.run(&modules()).unwrap(), [("L".to_string(), 25.0)]);
+
+    thread::sleep(Duration::from_millis(600));
+    std::fs::write(&path, "#{ L: ").unwrap();
+    assert!(!script.reload_if_changed());
+    assert!(script.error().is_some_and(|e| e.contains("previous version")));
+    assert!(script.run(&modules()).is_ok(), "the previous version keeps running");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn controller_routes_osc_through_the_script() {
+    let mut controller = Controller::default();
+    let (osc_sender, osc_receiver) = mpsc::channel();
+    controller.set_osc_receiver(osc_receiver);
+    controller.set_script(Some(Script::from_source(r#"#{ L: (osc["/Pelt1"] ?? 0.0) + (osc["/wind"] ?? 0.0) * 100 }"#).unwrap()));
+
+    osc_sender.send(OscInput::Target(0, 10.0)).unwrap();
+    osc_sender.send(OscInput::Value("/wind".to_string(), 0.05)).unwrap();
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 15.0);
+
+    // The safety limits still apply
+    osc_sender.send(OscInput::Value("/wind".to_string(), 1.0)).unwrap();
+    controller.tick();
+    assert_eq!(controller.modules[0].target, controller.limits.value_max);
+
+    // A constant output is applied again once manual override is released
+    controller.modules[0].manual_override = true;
+    controller.modules[0].target = 20.0;
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 20.0);
+    controller.modules[0].manual_override = false;
+    controller.tick();
+    assert_eq!(controller.modules[0].target, controller.limits.value_max);
+
+    controller.set_script(None);
+    osc_sender.send(OscInput::Target(0, 12.0)).unwrap();
+    controller.tick();
+    assert_eq!(controller.modules[0].target, 12.0);
+}
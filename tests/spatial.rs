@@ -0,0 +1,116 @@
+// Spatial mapping: falloff kernels, /TempSense/point input and the layout in the config.
+
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use rosc::{OscMessage, OscPacket, OscType};
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::osc::{self, OscInput};
+use TempSenseGUI::spatial::{Falloff, ModulePosition, SpatialLayout, SpatialPoint, POINT_ADDRESS};
+
+fn layout() -> SpatialLayout {
+    SpatialLayout {
+        falloff: Falloff::Linear,
+        neutral: 32.0,
+        span: 10.0,
+        positions: vec![
+            ModulePosition { module: "L".to_string(), x: 0.2, y: 0.5 },
+            ModulePosition { module: "R".to_string(), x: 0.8, y: 0.5 },
+        ],
+        ..SpatialLayout::default()
+    }
+}
+
+fn point(x: f32, intensity: f32, source: u32) -> SpatialPoint {
+    SpatialPoint { x, y: 0.5, intensity, radius: 0.3, source }
+}
+
+#[test]
+fn points_are_parsed_from_osc_arguments() {
+    assert_eq!(SpatialPoint::from_args(&[0.2, 0.5, 0.8, 0.1]), Ok(SpatialPoint { x: 0.2, y: 0.5, intensity: 0.8, radius: 0.1, source: 0 }));
+    assert_eq!(SpatialPoint::from_args(&[1.5, 0.5, -2.0, 0.1, 3.0]), Ok(SpatialPoint { x: 1.0, y: 0.5, intensity: -1.0, radius: 0.1, source: 3 }));
+    for (args, expected) in [(&[0.2, 0.5, 0.8][..], "found 3 arguments"), (&[0.2, 0.5, 0.8, 0.0], "radius"), (&[0.2, 0.5, 0.8, 0.1, 1.5], "source"), (&[0.2, f32::NAN, 0.8, 0.1], "numbers")] {
+        let error = SpatialPoint::from_args(args).unwrap_err();
+        assert!(error.contains(expected), "{:?}: {}", args, error);
+    }
+}
+
+#[test]
+fn sources_fall_off_with_distance() {
+    assert_eq!(Falloff::Gaussian.weight(0.0), 1.0);
+    assert_eq!(Falloff::Linear.weight(0.5), 0.5);
+    assert!((Falloff::Cosine.weight(0.5) - 0.5).abs() < 1e-6);
+    assert_eq!(Falloff::Cosine.weight(1.0), 0.0);
+
+    let layout = layout();
+    let mut sources = BTreeMap::new();
+    sources.insert(0, point(0.2, 0.5, 0));
+    assert_eq!(layout.targets(&sources), vec![("L".to_string(), 37.0), ("R".to_string(), 32.0)]);
+
+    // Half a radius away gives half the weight with a linear falloff, and a cold source cancels it out
+    let rounded = |targets: Vec<(String, f32)>| -> Vec<f32> { targets.into_iter().map(|(_, t)| (t * 100.0).round() / 100.0).collect() };
+    sources.insert(0, point(0.35, 1.0, 0));
+    assert_eq!(rounded(layout.targets(&sources)), vec![37.0, 32.0]);
+    sources.insert(1, point(0.2, -0.5, 1));
+    assert_eq!(rounded(layout.targets(&sources)), vec![32.0, 32.0]);
+
+    // Distances across the body part are scaled by its aspect ratio
+    assert!((layout.distance((0.0, 0.0), (0.0, 1.0)) - 0.3).abs() < 1e-6);
+}
+
+#[test]
+fn controller_sets_the_targets_of_placed_modules() {
+    let mut controller = Controller::default();
+    controller.apply_point(point(0.2, 1.0, 0));
+    assert_eq!((controller.modules[0].target, controller.modules[1].target), (0.0, 0.0), "nothing is placed yet");
+
+    controller.spatial = layout();
+    controller.apply_point(point(0.2, 0.5, 0));
+    assert_eq!((controller.modules[0].target, controller.modules[1].target), (37.0, 32.0));
+    controller.apply_point(point(0.8, -0.5, 1));
+    assert_eq!((controller.modules[0].target, controller.modules[1].target), (37.0, 27.0));
+
+    // Manual override wins, and intensity 0 removes a source
+    controller.modules[1].manual_override = true;
+    controller.apply_point(point(0.2, 0.0, 0));
+    assert_eq!((controller.modules[0].target, controller.modules[1].target), (32.0, 27.0));
+    assert_eq!(controller.spatial_sources.len(), 1);
+    controller.clear_points();
+    assert!(controller.spatial_sources.is_empty());
+}
+
+#[test]
+fn points_arrive_over_osc() {
+    let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let receiver = osc::spawn_osc_listener(&format!("127.0.0.1:{}", port));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let message = OscPacket::Message(OscMessage {
+        addr: POINT_ADDRESS.to_string(),
+        args: vec![OscType::Float(0.25), OscType::Float(0.5), OscType::Float(-0.75), OscType::Double(0.2), OscType::Int(2)],
+    });
+    let bytes = rosc::encoder::encode(&message).unwrap();
+    // The listener may still be binding, so keep sending until something arrives
+    let input = (0..50).find_map(|_| {
+        socket.send_to(&bytes, ("127.0.0.1", port)).unwrap();
+        receiver.recv_timeout(Duration::from_millis(100)).ok()
+    });
+    assert_eq!(input, Some(OscInput::Point(SpatialPoint { x: 0.25, y: 0.5, intensity: -0.75, radius: 0.2, source: 2 })));
+}
+
+#[test]
+fn layout_is_saved_with_the_config() {
+    let mut controller = Controller::default();
+    controller.spatial = layout();
+    let config = Config::from_json(&Config::capture(&controller, &Default::default()).to_json()).unwrap();
+    assert_eq!(config.spatial, controller.spatial);
+
+    let mut invalid = config.clone();
+    invalid.spatial.positions.push(ModulePosition { module: "L".to_string(), x: 1.5, y: 0.0 });
+    invalid.spatial.positions.push(ModulePosition { module: "X".to_string(), x: 0.0, y: 0.0 });
+    let error = invalid.validate().unwrap_err();
+    for expected in ["spatial.positions[2].module: 'L' is placed twice", "spatial.positions[2].x", "spatial.positions[3].module: 'X' is not a module"] {
+        assert!(error.contains(expected), "missing '{}' in: {}", expected, error);
+    }
+}
@@ -0,0 +1,111 @@
+// Synchronized START/STOP: scheduled commands in the worker, skew reports and the controller.
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use TempSenseGUI::controller::Controller;
+use TempSenseGUI::esp_comm::clock::{ClockSample, ClockSync};
+use TempSenseGUI::esp_comm::transport::{MockTransport, TransportDescriptor};
+use TempSenseGUI::esp_comm::{EspCommand, EspStatus, WireProtocol, esp_worker_thread};
+use TempSenseGUI::sync::{self, SyncRun};
+use common::{WAIT, wait_for};
+
+#[test]
+fn worker_holds_scheduled_commands_until_they_are_due() {
+    let mock = MockTransport::new();
+    let (command_tx, command_rx) = mpsc::channel();
+    let (status_tx, status_rx) = mpsc::channel();
+    let handle = thread::spawn(move || esp_worker_thread(command_rx, status_tx));
+    command_tx.send(EspCommand::Connect(TransportDescriptor::Mock(mock.clone()), WireProtocol::Ascii)).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::ProtocolSelected(_)));
+
+    let due = Instant::now() + Duration::from_millis(60);
+    command_tx.send(EspCommand::SendAt("tempActive 1".to_string(), due)).unwrap();
+    let EspStatus::ScheduledSent(sent) = wait_for(&status_rx, |s| matches!(s, EspStatus::ScheduledSent(_))) else { unreachable!() };
+    assert!(sent.written >= due && sent.written - due < Duration::from_millis(20), "written {:?} after the due instant", sent.written.saturating_duration_since(due));
+    assert_eq!((sent.command.as_str(), mock.take_tx()), ("tempActive 1", b"tempActive 1\n".to_vec()));
+
+    // Commands sent meanwhile aren't held up, and a stop cancels a start that is still due
+    command_tx.send(EspCommand::SendAt("tempActive 1".to_string(), Instant::now() + Duration::from_millis(300))).unwrap();
+    command_tx.send(EspCommand::SendCommand("setTemp 25".to_string())).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::CommandLatency(_)));
+    assert_eq!(mock.take_tx(), b"setTemp 25\n");
+    command_tx.send(EspCommand::SendCommand("tempActive 0".to_string())).unwrap();
+    wait_for(&status_rx, |s| matches!(s, EspStatus::Message(msg) if msg.contains("'tempActive 1' cancelled")));
+    wait_for(&status_rx, |s| matches!(s, EspStatus::CommandLatency(_)));
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(mock.take_tx(), b"tempActive 0\n", "the start never went out");
+
+    // Too far ahead is refused
+    command_tx.send(EspCommand::SendAt("tempActive 0".to_string(), Instant::now() + Duration::from_secs(5))).unwrap();
+    let EspStatus::Error(e) = wait_for(&status_rx, |s| matches!(s, EspStatus::Error(_))) else { unreachable!() };
+    assert!(e.contains("Not sent"), "{}", e);
+    assert!(mock.take_tx().is_empty());
+
+    command_tx.send(EspCommand::StopThread).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn skew_is_estimated_from_the_write_times_and_measured_link_delays() {
+    assert_eq!(sync::serial_transmit_time(12, 9600), Duration::from_micros(12_500));
+    // Before the first pong the baud rate is all there is to go by
+    let unsynchronized = ClockSync::default();
+    assert_eq!(sync::link_delay(&unsynchronized, "/dev/ttyUSB0", 115200, WireProtocol::Ascii, "tempActive 1"), sync::serial_transmit_time(13, 115200));
+    assert_eq!(sync::link_delay(&unsynchronized, "tcp://192.168.4.1:3333", 115200, WireProtocol::Ascii, "tempActive 1"), Duration::ZERO);
+    let mut synchronized = ClockSync::default();
+    synchronized.add(ClockSample { host_sent_us: 1_000, device_us: 90_000, host_received_us: 9_000 });
+    synchronized.add(ClockSample { host_sent_us: 2_000_000, device_us: 2_090_000, host_received_us: 2_003_000 });
+    for port in ["/dev/ttyUSB0", "tcp://192.168.4.1:3333"] {
+        assert_eq!(sync::link_delay(&synchronized, port, 115200, WireProtocol::Ascii, "tempActive 1"), Duration::from_micros(1_500), "{}", port);
+    }
+
+    let due = Instant::now() + Duration::from_millis(30);
+    let mut run = SyncRun::new("START", due);
+    run.expect("L", Duration::from_millis(2));
+    run.expect("R", Duration::ZERO);
+    run.record("L", due - Duration::from_millis(2) + Duration::from_micros(100));
+    assert!(!run.is_complete());
+    let partial = run.report();
+    assert_eq!(partial.missing, vec!["R".to_string()]);
+    assert!(partial.describe().contains("no report from R"), "{}", partial.describe());
+
+    run.record("R", due + Duration::from_micros(400));
+    assert!(run.is_complete());
+    let report = run.report();
+    assert!((report.skew.as_secs_f64() * 1000.0 - 0.3).abs() < 1e-6, "{:?}", report);
+    assert!(report.describe().starts_with("START reached 2 modules within 0.30 ms, host-side estimate (L +0.10 ms, R +0.40 ms)"), "{}", report.describe());
+}
+
+#[test]
+fn start_and_stop_reach_all_modules_together() {
+    let mut controller = Controller::default();
+    controller.modules[0].port = "sim:L".to_string();
+    controller.modules[1].port = "sim:R".to_string();
+    controller.connect(0);
+    controller.connect(1);
+    let deadline = Instant::now() + WAIT;
+    while !controller.modules.iter().all(|m| m.connected && m.active_protocol.is_some()) && Instant::now() < deadline {
+        controller.tick();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    for (label, start) in [("START", true), ("STOP", false)] {
+        controller.last_sync = None;
+        if start { controller.start() } else { controller.stop() }
+        assert!(controller.modules.iter().all(|m| m.active == start));
+        let deadline = Instant::now() + WAIT;
+        while controller.last_sync.is_none() && Instant::now() < deadline {
+            controller.tick();
+            thread::sleep(Duration::from_millis(2));
+        }
+        let report = controller.last_sync.clone().expect("no skew report");
+        assert_eq!(report.label, label);
+        assert!(report.missing.is_empty() && report.offsets.len() == 2, "{:?}", report);
+        assert!(report.skew < Duration::from_millis(5), "{}", report.describe());
+    }
+    controller.shutdown();
+}
@@ -0,0 +1,88 @@
+// Waveform generator: shapes, validation, and driving a module through the controller.
+
+use std::thread;
+use std::time::Duration;
+
+use TempSenseGUI::config::Config;
+use TempSenseGUI::controller::{Controller, SafetyLimits};
+use TempSenseGUI::waveform::{WaveShape, Waveform, WaveformPreset};
+
+fn waveform(shape: WaveShape) -> Waveform {
+    Waveform { shape, low: 20.0, high: 30.0, period_secs: 10.0, duty_cycle: 0.2, duration_secs: Some(25.0) }
+}
+
+#[test]
+fn shapes() {
+    let at = |shape, t: f32| waveform(shape).value_at(t);
+    assert_eq!((at(WaveShape::Step, 9.9), at(WaveShape::Step, 10.0)), (20.0, 30.0));
+    assert_eq!((at(WaveShape::Ramp, 5.0), at(WaveShape::Ramp, 15.0)), (25.0, 30.0));
+    assert!((at(WaveShape::Sine, 0.0) - 20.0).abs() < 1e-3 && (at(WaveShape::Sine, 5.0) - 30.0).abs() < 1e-3);
+    assert_eq!((at(WaveShape::Square, 4.9), at(WaveShape::Square, 5.1), at(WaveShape::Square, 10.1)), (30.0, 20.0, 30.0));
+    assert_eq!((at(WaveShape::Pulse, 1.9), at(WaveShape::Pulse, 2.1), at(WaveShape::Pulse, 11.0)), (30.0, 20.0, 30.0));
+
+    let ramp = waveform(WaveShape::Ramp);
+    assert!(ramp.target_at(Duration::from_secs(3)).is_some_and(|t| (t - 23.0).abs() < 1e-4));
+    assert_eq!(ramp.target_at(Duration::from_secs(25)), None, "over once the duration has passed");
+}
+
+#[test]
+fn waveforms_outside_the_safety_limits_are_refused() {
+    let mut controller = Controller::default();
+    controller.limits = SafetyLimits { value_min: 15.0, value_max: 28.0 };
+    let error = controller.start_waveform(0, "hot", waveform(WaveShape::Sine)).unwrap_err();
+    assert!(error.contains("30°C"), "{}", error);
+
+    let mut pulse = waveform(WaveShape::Pulse);
+    pulse.high = 25.0;
+    pulse.duty_cycle = 1.5;
+    assert!(controller.start_waveform(0, "pulse", pulse).unwrap_err().contains("duty_cycle"));
+    assert!(controller.modules[0].waveform.is_none());
+}
+
+#[test]
+fn running_waveform_replaces_osc_and_pauses_under_override() {
+    let mut controller = Controller::default();
+    let mut step = waveform(WaveShape::Step);
+    step.period_secs = 0.05;
+    step.duration_secs = Some(0.3);
+    controller.start_waveform(1, "step", step).unwrap();
+
+    controller.tick();
+    controller.update_pelt_temp(1, 12.0); // Ignored while the waveform runs
+    assert_eq!(controller.modules[1].target, 20.0);
+
+    controller.modules[1].manual_override = true;
+    controller.tick(); // The phase is frozen from here
+    thread::sleep(Duration::from_millis(80));
+    controller.tick();
+    assert_eq!(controller.modules[1].target, 20.0, "manual override keeps the target");
+    assert!(controller.modules[1].waveform.as_ref().is_some_and(|run| run.is_paused() && run.elapsed() < Duration::from_millis(50)));
+
+    controller.modules[1].manual_override = false;
+    controller.tick();
+    assert_eq!(controller.modules[1].target, 20.0, "resumes where it was paused");
+    thread::sleep(Duration::from_millis(60));
+    controller.tick();
+    assert_eq!(controller.modules[1].target, 30.0);
+
+    thread::sleep(Duration::from_millis(250));
+    controller.tick();
+    assert!(controller.modules[1].waveform.is_none(), "finished after its duration");
+    controller.update_pelt_temp(1, 12.0);
+    assert_eq!(controller.modules[1].target, 12.0);
+}
+
+#[test]
+fn presets_are_saved_with_the_config() {
+    let mut controller = Controller::default();
+    controller.waveform_presets.push(WaveformPreset { name: "warm pulses".to_string(), waveform: waveform(WaveShape::Pulse) });
+    let config = Config::capture(&controller, &Default::default());
+    let loaded = Config::from_json(&config.to_json()).unwrap();
+    assert_eq!(loaded.waveforms, controller.waveform_presets);
+
+    let mut invalid = config.clone();
+    invalid.waveforms.push(invalid.waveforms[0].clone());
+    invalid.waveforms[0].waveform.high = 90.0;
+    let error = invalid.validate().unwrap_err();
+    assert!(error.contains("waveforms[0]") && error.contains("waveforms[1].name"), "{}", error);
+}